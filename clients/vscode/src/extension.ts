@@ -3,11 +3,12 @@
  * Licensed under the MIT License. See License.txt in the project root for license information.
  * ------------------------------------------------------------------------------------------ */
 
-import { workspace, ExtensionContext, window, commands, CompletionList } from 'vscode';
+import { workspace, ExtensionContext, window, commands, CompletionList, Uri, EventEmitter, ViewColumn } from 'vscode';
 import {
 	CloseAction,
 	ErrorAction,
 	Executable,
+	ExecuteCommandRequest,
 	LanguageClient,
 	LanguageClientOptions,
 	ServerOptions,
@@ -17,6 +18,18 @@ import { platform } from 'os'
 
 let client: LanguageClient;
 
+const STDLIB_SCHEME = 'amber-std';
+const STDLIB_CONTENTS_REQUEST = 'amber/stdlibContents';
+const COMPILED_BASH_SCHEME = 'amber-bash';
+const SHOW_COMPILED_BASH_COMMAND = 'amber.showCompiledBash';
+const RUN_COMMAND = 'amber.run';
+const RUN_WITH_ARGUMENTS_COMMAND = 'amber.runWithArguments';
+
+interface CompiledBash {
+	uri: string;
+	text: string;
+}
+
 export function activate(context: ExtensionContext) {
 	const version = workspace.getConfiguration('amber-lsp').get<string>('version', 'auto');
 
@@ -43,7 +56,10 @@ export function activate(context: ExtensionContext) {
 	// Options to control the language client
 	let clientOptions: LanguageClientOptions = {
 	  // Register the server for plain text documents
-	  documentSelector: [{ scheme: "file", language: "amber" }],
+	  documentSelector: [
+			{ scheme: "file", language: "amber" },
+			{ scheme: STDLIB_SCHEME, language: "amber" },
+		],
 	  synchronize: {
 			// Notify the server about file changes to '.clientrc files contained in the workspace
 			fileEvents: workspace.createFileSystemWatcher("**/.clientrc"),
@@ -135,6 +151,84 @@ export function activate(context: ExtensionContext) {
 
 	context.subscriptions.push(pathEdits);
 
+	// Stdlib files are embedded in the server and opened as read-only documents
+	const stdlibContents = workspace.registerTextDocumentContentProvider(STDLIB_SCHEME, {
+		provideTextDocumentContent: (uri: Uri) =>
+			client.sendRequest<string>(STDLIB_CONTENTS_REQUEST, { uri: uri.toString() }),
+	});
+
+	context.subscriptions.push(stdlibContents);
+
+	// Bash compiled by the server, keyed by the URI of its virtual document
+	const compiledBash = new Map<string, string>();
+	const compiledBashChanges = new EventEmitter<Uri>();
+
+	const compiledBashContents = workspace.registerTextDocumentContentProvider(COMPILED_BASH_SCHEME, {
+		onDidChange: compiledBashChanges.event,
+		provideTextDocumentContent: (uri: Uri) => compiledBash.get(uri.toString()) ?? '',
+	});
+
+	const showCompiledBash = commands.registerCommand('amber-lsp.showCompiledBash', async () => {
+		const editor = window.activeTextEditor;
+
+		if (!editor || editor.document.languageId !== 'amber') {
+			return;
+		}
+
+		const result = await client.sendRequest(ExecuteCommandRequest.type, {
+			command: SHOW_COMPILED_BASH_COMMAND,
+			arguments: [editor.document.uri.toString()],
+		}) as CompiledBash | null;
+
+		// Compiler errors are reported as diagnostics of the document
+		if (!result) {
+			return;
+		}
+
+		const uri = Uri.parse(result.uri);
+		compiledBash.set(uri.toString(), result.text);
+		compiledBashChanges.fire(uri);
+
+		const document = await workspace.openTextDocument(uri);
+		await window.showTextDocument(document, { viewColumn: ViewColumn.Beside, preview: true, preserveFocus: true });
+	});
+
+	context.subscriptions.push(compiledBashContents, compiledBashChanges, showCompiledBash);
+
+	// Scripts run from the saved file, their output is logged to the output channel
+	const runScript = async (args?: string[]) => {
+		const editor = window.activeTextEditor;
+
+		if (!editor || editor.document.languageId !== 'amber') {
+			return;
+		}
+
+		if (!await editor.document.save()) {
+			return;
+		}
+
+		client.outputChannel.show(true);
+
+		await client.sendRequest(ExecuteCommandRequest.type, {
+			command: args ? RUN_WITH_ARGUMENTS_COMMAND : RUN_COMMAND,
+			arguments: args ? [editor.document.uri.toString(), args] : [editor.document.uri.toString()],
+		});
+	};
+
+	const runCommand = commands.registerCommand('amber-lsp.run', () => runScript());
+
+	const runWithArgumentsCommand = commands.registerCommand('amber-lsp.runWithArguments', async () => {
+		const input = await window.showInputBox({ prompt: 'Arguments passed to main(args)' });
+
+		if (input === undefined) {
+			return;
+		}
+
+		await runScript(input.split(/\s+/).filter((arg) => arg.length));
+	});
+
+	context.subscriptions.push(runCommand, runWithArgumentsCommand);
+
 	client.setTrace(Trace.Verbose)
 	client.start();
 }
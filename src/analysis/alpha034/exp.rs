@@ -4,20 +4,26 @@ use chumsky::span::SimpleSpan;
 
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_reference,
+        check_cast, check_unnecessary_trust, constrain_generic_argument,
+        get_symbol_definition_info, get_variable_type, insert_symbol_reference,
+        narrow_variable_type,
+        operators::{check_operator, Operator},
+        shell::{check_command, register_shell_script},
+        stdlib_commands::check_stdlib_alternative,
         types::{make_union_type, matches_type, DataType, GenericsMap},
-        Context, FunctionArgument, FunctionSymbol, SymbolInfo, SymbolLocation, SymbolType,
-        VariableSymbol,
+        BlockContext, Context, FunctionArgument, FunctionSymbol, SymbolInfo, SymbolLocation,
+        SymbolType, VariableSymbol,
     },
+    backend::AmberVersion,
     files::{FileVersion, Files},
     grammar::{
-        alpha034::{Expression, InterpolatedCommand, InterpolatedText},
-        Spanned,
+        alpha034::{shell_script, Expression, InterpolatedCommand, InterpolatedText},
+        CommandModifier, Span, Spanned,
     },
     paths::FileId,
 };
 
-use super::stmnts::analyze_failure_handler;
+use super::{shell::command_parts, stmnts::analyze_failure_handler};
 
 #[tracing::instrument(skip(file_version, files, scoped_generic_types))]
 pub fn analyze_exp(
@@ -38,7 +44,7 @@ pub fn analyze_exp(
     let file = (file_id, file_version);
 
     let ty: DataType = match exp {
-        Expression::FunctionInvocation(_, (name, name_span), args, failure) => {
+        Expression::FunctionInvocation(modifiers, (name, name_span), args, failure) => {
             let fun_symbol = get_symbol_definition_info(files, name, &file, name_span.start);
 
             let expected_types = match fun_symbol {
@@ -48,8 +54,8 @@ pub fn analyze_exp(
                 }) => fun_symbol
                     .arguments
                     .iter()
-                    .map(|(arg, _)| (arg.data_type.clone(), arg.is_ref))
-                    .collect::<Vec<(DataType, bool)>>(),
+                    .map(|(arg, _)| (arg.data_type.clone(), arg.is_ref, arg.name.clone()))
+                    .collect::<Vec<(DataType, bool, String)>>(),
                 Some(_) => {
                     files.report_error(&file, &format!("{} is not a function", name), *name_span);
 
@@ -63,12 +69,17 @@ pub fn analyze_exp(
             };
 
             args.iter().enumerate().for_each(|(idx, arg)| {
-                if let Some((ty, is_ref)) = expected_types.get(idx) {
+                if let Some((ty, is_ref, arg_name)) = expected_types.get(idx) {
+                    let expected_ty = match ty {
+                        DataType::Generic(id) => scoped_generic_types.argument_type(*id),
+                        ty => ty.clone(),
+                    };
+
                     let exp_ty = analyze_exp(
                         file_id,
                         file_version,
                         arg,
-                        ty.clone(),
+                        expected_ty,
                         files,
                         scoped_generic_types,
                         contexts,
@@ -101,7 +112,14 @@ pub fn analyze_exp(
                     }
 
                     if let DataType::Generic(id) = ty {
-                        scoped_generic_types.constrain_generic_type(*id, exp_ty.clone());
+                        constrain_generic_argument(
+                            files,
+                            &file,
+                            (name, arg_name),
+                            *id,
+                            (exp_ty.clone(), arg.1),
+                            scoped_generic_types,
+                        );
                     }
                 } else {
                     files.report_error(
@@ -137,6 +155,29 @@ pub fn analyze_exp(
                 ..
             }) = fun_symbol
             {
+                check_unnecessary_trust(files, &file, data_type, modifiers, scoped_generic_types);
+
+                if matches!(
+                    scoped_generic_types.deref_type(data_type),
+                    DataType::Failable(_)
+                ) && modifiers
+                    .iter()
+                    .all(|(modifier, _)| *modifier != CommandModifier::Unsafe)
+                    && contexts.iter().all(|ctx| match ctx {
+                        Context::Block(BlockContext { modifiers }) => modifiers
+                            .iter()
+                            .all(|modifier| *modifier != CommandModifier::Unsafe),
+                        _ => true,
+                    })
+                    && failure.is_none()
+                {
+                    files.report_error(
+                        &file,
+                        "Failable function must be handled with a failure handler or marked as unsafe",
+                        *name_span,
+                    );
+                }
+
                 let mut symbol_table = match files.symbol_table.get_mut(&file) {
                     Some(symbol_table) => symbol_table,
                     None => {
@@ -212,78 +253,26 @@ pub fn analyze_exp(
                 contexts,
             );
 
-            match get_symbol_definition_info(files, name, &file, name_span.start) {
-                Some(info) => info.data_type,
-                None => DataType::Null,
-            }
-        }
-        Expression::Add(exp1, exp2) => {
-            let ty = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Union(vec![
-                    DataType::Number,
-                    DataType::Text,
-                    DataType::Array(Box::new(DataType::Union(vec![
-                        DataType::Number,
-                        DataType::Text,
-                    ]))),
-                ]),
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let right_hand_ty = analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                ty.clone(),
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            if let DataType::Generic(id) = ty {
-                scoped_generic_types.constrain_generic_type(id, right_hand_ty.clone());
-            }
-
-            if !matches_type(&right_hand_ty, &ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Expected type {}, found type {}",
-                        right_hand_ty.to_string(scoped_generic_types),
-                        ty.to_string(scoped_generic_types),
-                    ),
-                    exp1.1,
-                );
-            }
-
-            ty
-        }
-        Expression::And(exp1, _, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
+            get_variable_type(files, name, &file, name_span.start).unwrap_or(DataType::Null)
         }
+        Expression::Add(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Add, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::And(exp1, _, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::And, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Array(elements) => {
             let types: Vec<DataType> = elements
                 .iter()
@@ -313,7 +302,7 @@ pub fn analyze_exp(
             DataType::Array(Box::new(array_type))
         }
         Expression::Cast(exp, _, (ty, _)) => {
-            analyze_exp(
+            let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
@@ -323,9 +312,18 @@ pub fn analyze_exp(
                 contexts,
             );
 
+            check_cast(
+                files,
+                &file,
+                (&exp_ty, ty),
+                *exp_span,
+                contexts,
+                scoped_generic_types,
+            );
+
             ty.clone()
         }
-        Expression::Command(_, inter_cmd, failure) => {
+        Expression::Command(modifiers, inter_cmd, failure) => {
             inter_cmd.iter().for_each(|(inter_cmd, _)| {
                 if let InterpolatedCommand::Expression(exp) = inter_cmd {
                     analyze_exp(
@@ -351,96 +349,66 @@ pub fn analyze_exp(
                 );
             }
 
-            DataType::Text
-        }
-        Expression::Divide(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
+            let parts = command_parts(files, &(file_id, file_version), inter_cmd);
 
-            DataType::Number
-        }
-        Expression::Eq(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Any,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Any,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
+            let script = shell_script(inter_cmd);
 
-            DataType::Boolean
-        }
-        Expression::Ge(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
+            check_command(
                 files,
-                scoped_generic_types,
+                &(file_id, file_version),
+                &parts,
+                &script,
+                modifiers,
                 contexts,
             );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
+            check_stdlib_alternative(
                 files,
-                scoped_generic_types,
-                contexts,
+                &(file_id, file_version),
+                &AmberVersion::Alpha034,
+                &parts,
+                failure.is_some(),
+                *exp_span,
             );
+            register_shell_script(files, &(file_id, file_version), script, *exp_span);
 
-            DataType::Boolean
-        }
-        Expression::Gt(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
+            DataType::Text
         }
+        Expression::Divide(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Divide, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Eq(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Eq, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Ge(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Ge, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Gt(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Gt, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Is(exp, _, _) => {
             analyze_exp(
                 file_id,
@@ -454,94 +422,42 @@ pub fn analyze_exp(
 
             DataType::Boolean
         }
-        Expression::Le(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
-        }
-        Expression::Lt(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
-        }
-        Expression::Modulo(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Number
-        }
-        Expression::Multiply(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Number
-        }
+        Expression::Le(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Le, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Lt(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Lt, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Modulo(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Modulo, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Multiply(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Multiply, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Nameof(_, exp) => {
             analyze_exp(
                 file_id,
@@ -568,28 +484,15 @@ pub fn analyze_exp(
 
             DataType::Number
         }
-        Expression::Neq(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Any,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Any,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
-        }
+        Expression::Neq(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Neq, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Not(_, exp) => {
             analyze_exp(
                 file_id,
@@ -603,28 +506,15 @@ pub fn analyze_exp(
 
             DataType::Boolean
         }
-        Expression::Or(exp1, _, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Boolean
-        }
+        Expression::Or(exp1, _, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Or, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Parentheses(exp) => analyze_exp(
             file_id,
             file_version,
@@ -634,50 +524,24 @@ pub fn analyze_exp(
             scoped_generic_types,
             contexts,
         ),
-        Expression::Range(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Array(Box::new(DataType::Number))
-        }
-        Expression::Subtract(exp1, exp2) => {
-            analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-
-            DataType::Number
-        }
+        Expression::Range(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Range, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
+        Expression::Subtract(exp1, exp2) => analyze_operator(
+            file_id,
+            file_version,
+            (Operator::Subtract, exp1, exp2),
+            *exp_span,
+            files,
+            scoped_generic_types,
+            contexts,
+        ),
         Expression::Ternary(exp1, _, exp2, _, exp3) => {
             analyze_exp(
                 file_id,
@@ -688,6 +552,9 @@ pub fn analyze_exp(
                 scoped_generic_types,
                 contexts,
             );
+
+            narrow_condition_types(files, &file, exp1, exp2.1);
+
             let if_true = analyze_exp(
                 file_id,
                 file_version,
@@ -771,3 +638,73 @@ pub fn analyze_exp(
 
     ty
 }
+
+/// Analyze the operands of a binary operator, checking it applies to their types.
+fn analyze_operator(
+    file_id: FileId,
+    file_version: FileVersion,
+    (operator, left, right): (Operator, &Spanned<Expression>, &Spanned<Expression>),
+    span: Span,
+    files: &Files,
+    scoped_generic_types: &GenericsMap,
+    contexts: &Vec<Context>,
+) -> DataType {
+    let left_ty = analyze_exp(
+        file_id,
+        file_version,
+        left,
+        DataType::Any,
+        files,
+        scoped_generic_types,
+        contexts,
+    );
+    let right_ty = analyze_exp(
+        file_id,
+        file_version,
+        right,
+        DataType::Any,
+        files,
+        scoped_generic_types,
+        contexts,
+    );
+
+    check_operator(
+        files,
+        &(file_id, file_version),
+        &AmberVersion::Alpha034,
+        operator,
+        (&left_ty, &right_ty),
+        span,
+        scoped_generic_types,
+    )
+}
+
+/// Narrow the types of variables checked with `is` in the condition, within the scope
+/// the condition holds in.
+pub fn narrow_condition_types(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (condition, _): &Spanned<Expression>,
+    scope: Span,
+) {
+    match condition {
+        Expression::Is(exp, _, (ty, _)) => {
+            if let (Expression::Var((name, name_span)), _) = &**exp {
+                narrow_variable_type(
+                    files,
+                    file,
+                    name,
+                    name_span.start,
+                    ty.clone(),
+                    scope.start..=scope.end,
+                );
+            }
+        }
+        Expression::And(left, _, right) => {
+            narrow_condition_types(files, file, left, scope);
+            narrow_condition_types(files, file, right, scope);
+        }
+        Expression::Parentheses(exp) => narrow_condition_types(files, file, exp, scope),
+        _ => {}
+    }
+}
@@ -1,20 +1,28 @@
 use crate::{
     analysis::{
-        self, import_symbol, insert_symbol_definition, map_import_path,
-        types::{make_union_type, matches_type, DataType},
+        self, check_shadowed_import, import_symbol, insert_symbol_definition, map_import_path,
+        shell::{define_shell_value, ShellValue},
+        signatures::{check_duplicate_signatures, register_signatures, FunctionSignature},
+        types::{has_unresolved_generic, make_union_type, matches_type, DataType},
         Context, FunctionContext, FunctionSymbol, ImportContext, SymbolInfo, SymbolType,
         VariableSymbol,
     },
     backend::Backend,
-    files::FileVersion,
+    diagnostics::DiagnosticKind,
+    files::{FileVersion, Files},
     grammar::{
-        alpha034::{FunctionArgument, GlobalStatement, ImportContent},
-        Span, Spanned,
+        alpha034::{
+            Block, ElseCondition, Expression, FailureHandler, FunctionArgument, GlobalStatement,
+            IfChainContent, IfCondition, ImportContent, InterpolatedCommand, InterpolatedText,
+            Statement, VariableInitType,
+        },
+        CompilerFlag, Grammar, Span, Spanned,
     },
     paths::FileId,
+    stdlib_index,
 };
 
-use super::stmnts::analyze_stmnt;
+use super::{flow::build_cfg, stmnts::analyze_stmnt};
 
 #[tracing::instrument(skip_all)]
 pub async fn analyze_global_stmnt(
@@ -23,6 +31,23 @@ pub async fn analyze_global_stmnt(
     ast: &[Spanned<GlobalStatement>],
     backend: &Backend,
 ) {
+    let mut file_generics = vec![];
+    let mut top_level_stmnts = vec![];
+
+    let mut first_main = None;
+
+    let signatures = function_signatures(ast);
+    let duplicate_functions =
+        check_duplicate_signatures(&backend.files, &(file_id, file_version), &signatures);
+    let mut function_return_types = register_signatures(
+        &backend.files,
+        &(file_id, file_version),
+        &signatures
+            .into_iter()
+            .filter(|signature| !duplicate_functions.contains(&signature.name.1.start))
+            .collect::<Vec<_>>(),
+    );
+
     for (global, span) in ast.iter() {
         match global {
             GlobalStatement::FunctionDefinition(
@@ -34,6 +59,8 @@ pub async fn analyze_global_stmnt(
                 ty,
                 body,
             ) => {
+                check_shadowed_import(&backend.files, &(file_id, file_version), (name, *name_span));
+
                 // We create scoped generics map, to not overwrite other generics, not defined here
                 let scoped_generics_map = backend.files.generic_types.clone();
 
@@ -94,13 +121,23 @@ pub async fn analyze_global_stmnt(
                         span.end,
                         &scoped_generics_map,
                         &vec![Context::Function(FunctionContext {
-                            compiler_flags: vec![],
+                            compiler_flags: compiler_flags
+                                .iter()
+                                .map(|(flag, _)| flag.clone())
+                                .collect(),
+                            return_ty: ty.as_ref().map(|(ty, _)| ty.clone()),
                         })],
                     ) {
                         return_types.push(ty);
                     }
                 });
 
+                build_cfg(body).report(
+                    &backend.files,
+                    &(file_id, file_version),
+                    ty.as_ref().map(|(ty, _)| (ty, *name_span)),
+                );
+
                 new_generic_types.iter().for_each(|generic_id| {
                     backend
                         .files
@@ -109,11 +146,14 @@ pub async fn analyze_global_stmnt(
                     backend.files.generic_types.mark_as_inferred(*generic_id);
                 });
 
+                file_generics.extend(new_generic_types.iter().copied());
+
                 let return_type = match return_types.len() {
                     0 => DataType::Null,
                     _ => make_union_type(return_types),
                 };
 
+                // Failability isn't declared, a `fail` or `?` in the body makes the function failable
                 let data_type = match ty {
                     Some((ty, ty_span)) => {
                         if !matches_type(ty, &return_type, &backend.files.generic_types) {
@@ -127,67 +167,94 @@ pub async fn analyze_global_stmnt(
                             );
                         }
 
-                        ty.clone()
+                        failable_type(ty.clone(), body)
                     }
-                    None => return_type,
+                    None => function_return_types.solve(
+                        name_span.start,
+                        failable_type(return_type, body),
+                        &backend.files.generic_types,
+                    ),
                 };
 
+                if ty.is_none()
+                    && !compiler_flags
+                        .iter()
+                        .any(|(flag, _)| *flag == CompilerFlag::AllowGenericReturn)
+                    && has_unresolved_generic(&data_type, &backend.files.generic_types)
+                    && !function_return_types.is_pending(&data_type)
+                {
+                    backend.files.report_diagnostic(
+                        &(file_id, file_version),
+                        DiagnosticKind::GenericReturn,
+                        &format!(
+                            "Function returns a generic type, declare its return type or use `#[{}]` to allow it",
+                            CompilerFlag::AllowGenericReturn
+                        ),
+                        *name_span,
+                    );
+                }
+
                 let mut symbol_table = backend
                     .files
                     .symbol_table
                     .entry((file_id, file_version))
                     .or_insert_with(Default::default);
 
-                insert_symbol_definition(
-                    &mut symbol_table,
-                    &SymbolInfo {
-                        name: name.to_string(),
-                        symbol_type: SymbolType::Function(FunctionSymbol {
-                            arguments: args
-                                .iter()
-                                .filter_map(|(arg, span)| match arg {
-                                    FunctionArgument::Generic((is_ref, _), (name, _)) => Some((
-                                        analysis::FunctionArgument {
-                                            name: name.clone(),
-                                            data_type: DataType::Generic(
-                                                new_generic_types.remove(0),
-                                            ),
-                                            is_optional: false,
-                                            is_ref: *is_ref,
-                                        },
-                                        *span,
-                                    )),
-                                    FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => {
-                                        Some((
-                                            analysis::FunctionArgument {
-                                                name: name.clone(),
-                                                data_type: ty.clone(),
-                                                is_optional: false,
-                                                is_ref: *is_ref,
-                                            },
-                                            *span,
-                                        ))
-                                    }
-                                    _ => None,
-                                })
-                                .collect::<Vec<_>>(),
-                            is_public: *is_pub,
-                            compiler_flags: compiler_flags
-                                .iter()
-                                .map(|(flag, _)| flag.clone())
-                                .collect(),
-                            docs: None,
-                        }),
-                        data_type: data_type.clone(),
-                        is_definition: true,
-                        undefined: false,
-                        span: *name_span,
-                        contexts: vec![],
-                    },
-                    (file_id, file_version),
-                    span.end..=usize::MAX,
-                    *is_pub,
-                );
+                let symbol_info = SymbolInfo {
+                    name: name.to_string(),
+                    symbol_type: SymbolType::Function(FunctionSymbol {
+                        arguments: args
+                            .iter()
+                            .filter_map(|(arg, span)| match arg {
+                                FunctionArgument::Generic((is_ref, _), (name, _)) => Some((
+                                    analysis::FunctionArgument {
+                                        name: name.clone(),
+                                        data_type: DataType::Generic(new_generic_types.remove(0)),
+                                        is_optional: false,
+                                        is_ref: *is_ref,
+                                    },
+                                    *span,
+                                )),
+                                FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => Some((
+                                    analysis::FunctionArgument {
+                                        name: name.clone(),
+                                        data_type: ty.clone(),
+                                        is_optional: false,
+                                        is_ref: *is_ref,
+                                    },
+                                    *span,
+                                )),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>(),
+                        is_public: *is_pub,
+                        compiler_flags: compiler_flags
+                            .iter()
+                            .map(|(flag, _)| flag.clone())
+                            .collect(),
+                        docs: None,
+                    }),
+                    data_type: data_type.clone(),
+                    is_definition: true,
+                    undefined: false,
+                    span: *name_span,
+                    contexts: vec![],
+                };
+
+                if duplicate_functions.contains(&name_span.start) {
+                    // Calls resolve to the first definition of the function
+                    symbol_table
+                        .symbols
+                        .insert(name_span.start..=name_span.end, symbol_info);
+                } else {
+                    insert_symbol_definition(
+                        &mut symbol_table,
+                        &symbol_info,
+                        (file_id, file_version),
+                        span.end..=usize::MAX,
+                        *is_pub,
+                    );
+                }
             }
             GlobalStatement::Import(
                 (is_public_import, _),
@@ -198,9 +265,11 @@ pub async fn analyze_global_stmnt(
             ) => {
                 let uri = &backend.files.lookup(&file_id);
 
-                let result = backend
-                    .open_document(&map_import_path(uri, path, backend).await)
-                    .await;
+                let import_uri = map_import_path(uri, path, backend).await;
+                let result = match stdlib_index::load(backend, &import_uri) {
+                    Some(file) => Ok(file),
+                    None => backend.open_document(&import_uri).await,
+                };
 
                 {
                     let mut symbol_table = backend
@@ -232,6 +301,9 @@ pub async fn analyze_global_stmnt(
                         "File doesn't exist",
                         *path_span,
                     );
+                    backend
+                        .files
+                        .add_missing_file_dependency(&(file_id, file_version), import_uri);
 
                     continue;
                 }
@@ -252,6 +324,13 @@ pub async fn analyze_global_stmnt(
                     .files
                     .add_file_dependency(&(file_id, file_version), imported_file.0);
 
+                check_imported_file(
+                    &backend.files,
+                    &(file_id, file_version),
+                    &imported_file,
+                    *path_span,
+                );
+
                 let imported_file_symbol_table =
                     match backend.files.symbol_table.get(&imported_file) {
                         Some(symbol_table_ref) => symbol_table_ref.clone(),
@@ -412,7 +491,18 @@ pub async fn analyze_global_stmnt(
                         }),
                 }
             }
-            GlobalStatement::Main(_, args, body) => {
+            GlobalStatement::Main((_, main_span), args, body) => {
+                match first_main {
+                    Some(first_span) => backend.files.report_diagnostic_with_related(
+                        &(file_id, file_version),
+                        DiagnosticKind::Analysis,
+                        "Only one `main` block is allowed",
+                        *main_span,
+                        vec![("First `main` block is here".to_string(), first_span)],
+                    ),
+                    None => first_main = Some(*main_span),
+                }
+
                 if let Some((args, args_span)) = args {
                     let mut symbol_table = backend
                         .files
@@ -435,6 +525,9 @@ pub async fn analyze_global_stmnt(
                         args_span.end..=span.end,
                         false,
                     );
+
+                    // Arguments the script is run with can hold anything
+                    define_shell_value(&mut symbol_table, args_span.start, ShellValue::TAINTED);
                 }
 
                 body.iter().for_each(|stmnt| {
@@ -448,8 +541,20 @@ pub async fn analyze_global_stmnt(
                         &vec![Context::Main],
                     );
                 });
+
+                build_cfg(body).report(&backend.files, &(file_id, file_version), None);
             }
             GlobalStatement::Statement(stmnt) => {
+                if let Statement::VariableInit(_, (name, name_span), _)
+                | Statement::ConstInit(_, (name, name_span), _) = &stmnt.0
+                {
+                    check_shadowed_import(
+                        &backend.files,
+                        &(file_id, file_version),
+                        (name, *name_span),
+                    );
+                }
+
                 analyze_stmnt(
                     file_id,
                     file_version,
@@ -459,7 +564,227 @@ pub async fn analyze_global_stmnt(
                     &backend.files.generic_types.clone(),
                     &vec![],
                 );
+
+                top_level_stmnts.push(stmnt.clone());
             }
         }
     }
+
+    build_cfg(&top_level_stmnts).report_unassigned_reads(&backend.files, &(file_id, file_version));
+    function_return_types.finish(&backend.files, &(file_id, file_version));
+
+    backend
+        .files
+        .generic_types
+        .insert(file_id, file_version, file_generics);
+}
+
+/// Signatures of the functions defined in the file.
+fn function_signatures(ast: &[Spanned<GlobalStatement>]) -> Vec<FunctionSignature> {
+    ast.iter()
+        .filter_map(|(global, span)| match global {
+            GlobalStatement::FunctionDefinition(_, _, _, name, args, return_ty, body) => {
+                Some(FunctionSignature {
+                    name: name.clone(),
+                    arguments: args
+                        .iter()
+                        .filter_map(|(arg, span)| {
+                            let (is_ref, name, data_type) = match arg {
+                                FunctionArgument::Generic((is_ref, _), (name, _)) => {
+                                    (is_ref, name, DataType::Any)
+                                }
+                                FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => {
+                                    (is_ref, name, ty.clone())
+                                }
+                                FunctionArgument::Error => return None,
+                            };
+
+                            Some((
+                                analysis::FunctionArgument {
+                                    name: name.clone(),
+                                    data_type,
+                                    is_optional: false,
+                                    is_ref: *is_ref,
+                                },
+                                *span,
+                            ))
+                        })
+                        .collect(),
+                    return_ty: return_ty
+                        .as_ref()
+                        .map(|(ty, _)| failable_type(ty.clone(), body)),
+                    span: *span,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Warn about a `main` block and statements with side effects in an imported file.
+fn check_imported_file(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    imported_file: &(FileId, FileVersion),
+    path_span: Span,
+) {
+    let ast = match files.ast_map.get(imported_file) {
+        Some(ast) => ast,
+        None => return,
+    };
+
+    let ast = match &*ast {
+        Grammar::Alpha034(Some(ast)) => ast,
+        _ => return,
+    };
+
+    if ast
+        .iter()
+        .any(|(global, _)| matches!(global, GlobalStatement::Main(..)))
+    {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::ImportSideEffects,
+            "Imported file has a `main` block, it doesn't run when the file is imported",
+            path_span,
+        );
+    }
+
+    if ast.iter().any(|(global, _)| match global {
+        GlobalStatement::Statement(stmnt) => has_side_effects(&stmnt.0),
+        _ => false,
+    }) {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::ImportSideEffects,
+            "Imported file runs statements at the top level when it's imported",
+            path_span,
+        );
+    }
+}
+
+/// Whether running the statement does more than defining a variable.
+fn has_side_effects(stmnt: &Statement) -> bool {
+    let is_call = |exp: &Expression| {
+        matches!(
+            exp,
+            Expression::Command(..) | Expression::FunctionInvocation(..)
+        )
+    };
+
+    match stmnt {
+        Statement::Comment(_) | Statement::Shebang(_) | Statement::Error => false,
+        Statement::VariableInit(_, _, (VariableInitType::Expression((exp, _)), _)) => is_call(exp),
+        Statement::ConstInit(_, _, exp) => is_call(&exp.0),
+        Statement::VariableInit(..) => false,
+        _ => true,
+    }
+}
+
+/// Return type of a function with the given body, failable if the body fails or
+/// propagates a failure.
+fn failable_type(ty: DataType, body: &[Spanned<Statement>]) -> DataType {
+    if body.iter().any(|(stmnt, _)| stmnt_fails(stmnt)) {
+        DataType::Failable(Box::new(ty))
+    } else {
+        ty
+    }
+}
+
+fn stmnt_fails(stmnt: &Statement) -> bool {
+    let block_fails = |block: &Block| match block {
+        Block::Block(_, stmnts) => stmnts.iter().any(|(stmnt, _)| stmnt_fails(stmnt)),
+        Block::Error => false,
+    };
+    let if_fails = |if_cond: &IfCondition| match if_cond {
+        IfCondition::IfCondition(exp, (block, _)) => exp_fails(&exp.0) || block_fails(block),
+        IfCondition::InlineIfCondition(exp, stmnt) => exp_fails(&exp.0) || stmnt_fails(&stmnt.0),
+        IfCondition::Error => false,
+    };
+    let else_fails = |else_cond: &ElseCondition| match else_cond {
+        ElseCondition::Else(_, (block, _)) => block_fails(block),
+        ElseCondition::InlineElse(_, stmnt) => stmnt_fails(&stmnt.0),
+    };
+
+    match stmnt {
+        Statement::Fail(..) => true,
+        Statement::Expression(exp)
+        | Statement::ConstInit(_, _, exp)
+        | Statement::VariableSet(_, exp)
+        | Statement::ShorthandAdd(_, exp)
+        | Statement::ShorthandSub(_, exp)
+        | Statement::ShorthandMul(_, exp)
+        | Statement::ShorthandDiv(_, exp)
+        | Statement::ShorthandModulo(_, exp)
+        | Statement::Echo(_, exp)
+        | Statement::Return(_, Some(exp)) => exp_fails(&exp.0),
+        Statement::VariableInit(_, _, (VariableInitType::Expression((exp, _)), _)) => {
+            exp_fails(exp)
+        }
+        Statement::IfCondition(_, (if_cond, _), else_cond) => {
+            if_fails(if_cond)
+                || else_cond
+                    .as_ref()
+                    .is_some_and(|(else_cond, _)| else_fails(else_cond))
+        }
+        Statement::IfChain(_, chain) => chain.iter().any(|(content, _)| match content {
+            IfChainContent::IfCondition((if_cond, _)) => if_fails(if_cond),
+            IfChainContent::Else((else_cond, _)) => else_fails(else_cond),
+        }),
+        Statement::InfiniteLoop(_, (block, _)) | Statement::Block((block, _)) => block_fails(block),
+        Statement::IterLoop(_, _, _, exp, (block, _)) => exp_fails(&exp.0) || block_fails(block),
+        _ => false,
+    }
+}
+
+fn exp_fails(exp: &Expression) -> bool {
+    let handler_fails = |failure: &Option<Spanned<FailureHandler>>| match failure {
+        Some((FailureHandler::Propagate, _)) => true,
+        Some((FailureHandler::Handle(_, stmnts), _)) => {
+            stmnts.iter().any(|(stmnt, _)| stmnt_fails(stmnt))
+        }
+        None => false,
+    };
+
+    match exp {
+        Expression::FunctionInvocation(_, _, args, failure) => {
+            handler_fails(failure) || args.iter().any(|(arg, _)| exp_fails(arg))
+        }
+        Expression::Command(_, parts, failure) => {
+            handler_fails(failure)
+                || parts.iter().any(|(part, _)| match part {
+                    InterpolatedCommand::Expression(exp) => exp_fails(&exp.0),
+                    _ => false,
+                })
+        }
+        Expression::Text(parts) => parts.iter().any(|(part, _)| match part {
+            InterpolatedText::Expression(exp) => exp_fails(&exp.0),
+            _ => false,
+        }),
+        Expression::Array(exps) => exps.iter().any(|(exp, _)| exp_fails(exp)),
+        Expression::Parentheses(exp)
+        | Expression::Neg(_, exp)
+        | Expression::Not(_, exp)
+        | Expression::Cast(exp, _, _)
+        | Expression::Nameof(_, exp)
+        | Expression::Is(exp, _, _) => exp_fails(&exp.0),
+        Expression::Add(lhs, rhs)
+        | Expression::Subtract(lhs, rhs)
+        | Expression::Multiply(lhs, rhs)
+        | Expression::Divide(lhs, rhs)
+        | Expression::Modulo(lhs, rhs)
+        | Expression::And(lhs, _, rhs)
+        | Expression::Or(lhs, _, rhs)
+        | Expression::Gt(lhs, rhs)
+        | Expression::Ge(lhs, rhs)
+        | Expression::Lt(lhs, rhs)
+        | Expression::Le(lhs, rhs)
+        | Expression::Eq(lhs, rhs)
+        | Expression::Neq(lhs, rhs)
+        | Expression::Range(lhs, rhs) => exp_fails(&lhs.0) || exp_fails(&rhs.0),
+        Expression::Ternary(cond, _, if_true, _, if_false) => {
+            exp_fails(&cond.0) || exp_fails(&if_true.0) || exp_fails(&if_false.0)
+        }
+        _ => false,
+    }
 }
@@ -0,0 +1,82 @@
+use crate::{
+    analysis::shell::{variable_shell_value, CommandPart, ShellValue},
+    files::{FileVersion, Files},
+    grammar::{
+        alpha034::{Expression, InterpolatedCommand, InterpolatedText},
+        Spanned,
+    },
+    paths::FileId,
+};
+
+/// What is known about the value of the expression, once it's interpolated into a command.
+pub fn shell_value(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (exp, _): &Spanned<Expression>,
+) -> ShellValue {
+    match exp {
+        Expression::Number(_) | Expression::Boolean(_) | Expression::Status => {
+            ShellValue::NON_EMPTY
+        }
+        Expression::Text(parts) => parts
+            .iter()
+            .fold(ShellValue::default(), |value, (part, _)| match part {
+                InterpolatedText::Text((text, _)) | InterpolatedText::Escape((text, _)) => value
+                    .join(ShellValue {
+                        is_tainted: false,
+                        is_non_empty: !text.is_empty(),
+                    }),
+                InterpolatedText::Expression(exp) => value.join(shell_value(files, file, exp)),
+            }),
+        Expression::Parentheses(exp) | Expression::Cast(exp, _, _) => shell_value(files, file, exp),
+        Expression::Var((name, span)) => variable_shell_value(files, file, name, span.start),
+        Expression::Add(left, right) => {
+            shell_value(files, file, left).join(shell_value(files, file, right))
+        }
+        Expression::Ternary(_, _, if_true, _, if_false) => {
+            shell_value(files, file, if_true).either(shell_value(files, file, if_false))
+        }
+        Expression::Array(items) => items
+            .iter()
+            .map(|item| shell_value(files, file, item))
+            .reduce(ShellValue::either)
+            .unwrap_or_default(),
+        Expression::FunctionInvocation(_, _, args, _) => args
+            .iter()
+            .fold(ShellValue::default(), |value, arg| {
+                value.join(shell_value(files, file, arg))
+            })
+            .derived(),
+        Expression::Command(_, parts, _) => command_parts(files, file, parts)
+            .into_iter()
+            .fold(ShellValue::default(), |value, (part, _)| match part {
+                CommandPart::Value(part_value) => value.join(part_value),
+                CommandPart::Text(_) => value,
+            })
+            .derived(),
+        _ => ShellValue::default(),
+    }
+}
+
+/// Parts of the command literal, with the values of its interpolated expressions.
+pub fn command_parts(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    parts: &[Spanned<InterpolatedCommand>],
+) -> Vec<Spanned<CommandPart>> {
+    parts
+        .iter()
+        .map(|(part, span)| {
+            let part = match part {
+                InterpolatedCommand::Text(text)
+                | InterpolatedCommand::Escape(text)
+                | InterpolatedCommand::CommandOption(text) => CommandPart::Text(text.clone()),
+                InterpolatedCommand::Expression(exp) => {
+                    CommandPart::Value(shell_value(files, file, exp))
+                }
+            };
+
+            (part, *span)
+        })
+        .collect()
+}
@@ -1,15 +1,23 @@
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_definition, insert_symbol_reference,
-        types::{make_union_type, matches_type, GenericsMap},
+        clear_narrowed_type, get_symbol_definition_info, insert_symbol_definition,
+        insert_symbol_reference, is_allowed_by_flag,
+        operators::{check_operator, Operator},
+        shell::{assign_shell_value, define_shell_value, ShellValue},
+        types::{make_union_type, GenericsMap},
         BlockContext, Context, DataType, SymbolInfo, SymbolLocation, SymbolType, VariableSymbol,
     },
+    backend::AmberVersion,
+    diagnostics::DiagnosticKind,
     files::{FileVersion, Files},
-    grammar::{alpha034::*, Spanned},
+    grammar::{alpha034::*, CompilerFlag, Spanned},
     paths::FileId,
 };
 
-use super::exp::analyze_exp;
+use super::{
+    exp::{analyze_exp, narrow_condition_types},
+    shell::shell_value,
+};
 
 /// Analyze a statement.
 ///
@@ -51,6 +59,9 @@ pub fn analyze_stmnt(
                                 scoped_generic_types,
                                 contexts,
                             );
+
+                            narrow_condition_types(files, &file, exp, block.1);
+
                             return analyze_block(
                                 file_id,
                                 file_version,
@@ -70,6 +81,9 @@ pub fn analyze_stmnt(
                                 scoped_generic_types,
                                 contexts,
                             );
+
+                            narrow_condition_types(files, &file, exp, boxed_stmnt.1);
+
                             return analyze_stmnt(
                                 file_id,
                                 file_version,
@@ -109,6 +123,10 @@ pub fn analyze_stmnt(
             }
         }
         Statement::IfCondition(_, if_cond, else_cond) => {
+            if let Some((else_cond, _)) = else_cond {
+                check_nested_if_else(&file, else_cond, files, contexts);
+            }
+
             match &if_cond.0 {
                 IfCondition::IfCondition(exp, block) => {
                     analyze_exp(
@@ -120,6 +138,9 @@ pub fn analyze_stmnt(
                         scoped_generic_types,
                         contexts,
                     );
+
+                    narrow_condition_types(files, &file, exp, block.1);
+
                     return analyze_block(
                         file_id,
                         file_version,
@@ -139,6 +160,9 @@ pub fn analyze_stmnt(
                         scoped_generic_types,
                         contexts,
                     );
+
+                    narrow_condition_types(files, &file, exp, boxed_stmnt.1);
+
                     return analyze_stmnt(
                         file_id,
                         file_version,
@@ -191,6 +215,9 @@ pub fn analyze_stmnt(
             );
         }
         Statement::IterLoop(_, (vars, _), _, exp, block) => {
+            // Items of the iterated array come from wherever the array does
+            let item_value = shell_value(files, &file, exp).derived();
+
             let block_span = block.1;
 
             let iter_type = match analyze_exp(
@@ -243,6 +270,7 @@ pub fn analyze_stmnt(
                         block_span.start..=block_span.end,
                         false,
                     );
+                    define_shell_value(&mut symbol_table, var2_span.start, item_value);
                 }
                 IterLoopVars::Single((var, var_span)) => {
                     let mut symbol_table = files
@@ -264,6 +292,7 @@ pub fn analyze_stmnt(
                         block_span.start..=block_span.end,
                         false,
                     );
+                    define_shell_value(&mut symbol_table, var_span.start, item_value);
                 }
                 _ => {}
             }
@@ -280,6 +309,11 @@ pub fn analyze_stmnt(
             );
         }
         Statement::VariableInit(_, (var_name, var_span), (value, _)) => {
+            let var_value = match value {
+                VariableInitType::Expression(exp) => shell_value(files, &file, exp),
+                _ => ShellValue::default(),
+            };
+
             let var_type = match value {
                 VariableInitType::Expression(exp) => analyze_exp(
                     file_id,
@@ -313,8 +347,11 @@ pub fn analyze_stmnt(
                 span.end..=scope_end,
                 false,
             );
+            define_shell_value(&mut symbol_table, var_span.start, var_value);
         }
         Statement::ConstInit(_, (var_name, var_span), exp) => {
+            let var_value = shell_value(files, &file, exp);
+
             let var_type = analyze_exp(
                 file_id,
                 file_version,
@@ -344,6 +381,7 @@ pub fn analyze_stmnt(
                 span.end..=scope_end,
                 false,
             );
+            define_shell_value(&mut symbol_table, var_span.start, var_value);
         }
         Statement::Echo(_, exp) => {
             analyze_exp(
@@ -418,37 +456,25 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            let default_ty = DataType::Union(vec![
-                DataType::Text,
-                DataType::Number,
-                DataType::Array(Box::new(DataType::Union(vec![
-                    DataType::Text,
-                    DataType::Number,
-                ]))),
-            ]);
-
             let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                var_ty.clone(),
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            if !matches_type(&default_ty, &var_ty, scoped_generic_types)
-                || !matches_type(&exp_ty, &var_ty, scoped_generic_types)
-            {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot add to variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha034,
+                Operator::Add,
+                (&var_ty, &exp_ty),
+                *span,
+                scoped_generic_types,
+            );
 
             insert_symbol_reference(
                 var,
@@ -468,27 +494,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot divide variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            analyze_exp(
+            let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha034,
+                Operator::Divide,
+                (&var_ty, &exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -507,27 +532,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use modulo with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            analyze_exp(
+            let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha034,
+                Operator::Modulo,
+                (&var_ty, &exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -546,27 +570,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use multiply with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            analyze_exp(
+            let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha034,
+                Operator::Multiply,
+                (&var_ty, &exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -585,27 +608,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use subtract with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            analyze_exp(
+            let exp_ty = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha034,
+                Operator::Subtract,
+                (&var_ty, &exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -619,6 +641,8 @@ pub fn analyze_stmnt(
             );
         }
         Statement::VariableSet((var, var_span), exp) => {
+            let var_value = shell_value(files, &file, exp);
+
             let var_ty = match get_symbol_definition_info(files, var, &file, var_span.start) {
                 Some(info) => info.data_type,
                 None => DataType::Any,
@@ -634,6 +658,9 @@ pub fn analyze_stmnt(
                 contexts,
             );
 
+            assign_shell_value(files, &file, var, var_span.start, var_value);
+            clear_narrowed_type(files, &file, var, var_span.start);
+
             insert_symbol_reference(
                 var,
                 files,
@@ -708,17 +735,67 @@ pub fn analyze_failure_handler(
     scoped_generic_types: &GenericsMap,
     contexts: &Vec<Context>,
 ) {
-    if let FailureHandler::Handle(_, stmnts) = failure {
-        stmnts.iter().for_each(|stmnt| {
-            analyze_stmnt(
-                file_id,
-                file_version,
-                stmnt,
-                files,
-                span.end,
-                scoped_generic_types,
-                contexts,
-            );
-        });
+    match failure {
+        FailureHandler::Handle(_, stmnts) => {
+            stmnts.iter().for_each(|stmnt| {
+                analyze_stmnt(
+                    file_id,
+                    file_version,
+                    stmnt,
+                    files,
+                    span.end,
+                    scoped_generic_types,
+                    contexts,
+                );
+            });
+        }
+        FailureHandler::Propagate => {
+            if !contexts
+                .iter()
+                .any(|c| matches!(c, Context::Function(_) | Context::Main))
+            {
+                files.report_error(
+                    &(file_id, file_version),
+                    "Propagate can only be used inside of main block or function",
+                    *span,
+                );
+            }
+        }
+    }
+}
+
+/// Warn about an `else` holding just another condition, which should be an if chain.
+fn check_nested_if_else(
+    file: &(FileId, FileVersion),
+    else_cond: &ElseCondition,
+    files: &Files,
+    contexts: &[Context],
+) {
+    if is_allowed_by_flag(contexts, &CompilerFlag::AllowNestedIfElse) {
+        return;
+    }
+
+    let stmnts = match else_cond {
+        ElseCondition::Else(_, (Block::Block(_, stmnts), _)) => stmnts.iter().collect(),
+        ElseCondition::Else(_, (Block::Error, _)) => vec![],
+        ElseCondition::InlineElse(_, stmnt) => vec![stmnt.as_ref()],
+    };
+
+    let mut stmnts = stmnts
+        .into_iter()
+        .filter(|(stmnt, _)| !matches!(stmnt, Statement::Comment(_)));
+
+    if let (Some((Statement::IfCondition(..) | Statement::IfChain(..), span)), None) =
+        (stmnts.next(), stmnts.next())
+    {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::NestedIfElse,
+            &format!(
+                "Use an if chain instead of nested if else statements, or `#[{}]` to allow it",
+                CompilerFlag::AllowNestedIfElse
+            ),
+            *span,
+        );
     }
 }
@@ -4,20 +4,29 @@ use chumsky::span::SimpleSpan;
 
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_reference,
+        check_cast, check_unnecessary_trust, constrain_generic_argument,
+        get_symbol_definition_info, get_variable_type, insert_symbol_reference,
+        narrow_variable_type,
+        operators::{check_operator, Operator},
+        shell::{check_command, register_shell_script},
+        stdlib_commands::check_stdlib_alternative,
         types::{make_union_type, matches_type, DataType, GenericsMap},
         BlockContext, Context, FunctionArgument, FunctionSymbol, SymbolInfo, SymbolLocation,
         SymbolType, VariableSymbol,
     },
+    backend::AmberVersion,
     files::{FileVersion, Files},
     grammar::{
-        alpha035::{Expression, InterpolatedCommand, InterpolatedText},
-        CommandModifier, Spanned,
+        alpha035::{shell_script, Expression, InterpolatedCommand, InterpolatedText},
+        CommandModifier, Span, Spanned,
     },
     paths::FileId,
 };
 
-use super::stmnts::{analyze_failure_handler, StmntAnalysisResult};
+use super::{
+    shell::command_parts,
+    stmnts::{analyze_failure_handler, StmntAnalysisResult},
+};
 
 #[derive(Debug, Clone)]
 pub struct ExpAnalysisResult {
@@ -62,8 +71,15 @@ pub fn analyze_exp(
                 }) => fun_symbol
                     .arguments
                     .iter()
-                    .map(|(arg, _)| (arg.data_type.clone(), arg.is_optional, arg.is_ref))
-                    .collect::<Vec<(DataType, bool, bool)>>(),
+                    .map(|(arg, _)| {
+                        (
+                            arg.data_type.clone(),
+                            arg.is_optional,
+                            arg.is_ref,
+                            arg.name.clone(),
+                        )
+                    })
+                    .collect::<Vec<(DataType, bool, bool, String)>>(),
                 Some(_) => {
                     files.report_error(&file, &format!("{} is not a function", name), *name_span);
 
@@ -77,7 +93,12 @@ pub fn analyze_exp(
             };
 
             args.iter().enumerate().for_each(|(idx, arg)| {
-                if let Some((ty, _, is_ref)) = expected_types.get(idx) {
+                if let Some((ty, _, is_ref, arg_name)) = expected_types.get(idx) {
+                    let expected_ty = match ty {
+                        DataType::Generic(id) => scoped_generic_types.argument_type(*id),
+                        ty => ty.clone(),
+                    };
+
                     let ExpAnalysisResult {
                         is_propagating_failure: propagates_failure,
                         return_ty,
@@ -86,7 +107,7 @@ pub fn analyze_exp(
                         file_id,
                         file_version,
                         arg,
-                        ty.clone(),
+                        expected_ty,
                         files,
                         scoped_generic_types,
                         contexts,
@@ -122,7 +143,14 @@ pub fn analyze_exp(
                     is_propagating_failure |= propagates_failure;
 
                     if let DataType::Generic(id) = ty {
-                        scoped_generic_types.constrain_generic_type(*id, exp_ty.clone());
+                        constrain_generic_argument(
+                            files,
+                            &file,
+                            (name, arg_name),
+                            *id,
+                            (exp_ty.clone(), arg.1),
+                            scoped_generic_types,
+                        );
                     }
                 } else {
                     files.report_error(
@@ -135,7 +163,7 @@ pub fn analyze_exp(
 
             if expected_types
                 .iter()
-                .filter(|(_, is_optional, _)| !*is_optional)
+                .filter(|(_, is_optional, _, _)| !*is_optional)
                 .count()
                 > args.len()
             {
@@ -175,6 +203,8 @@ pub fn analyze_exp(
                 ..
             }) = fun_symbol
             {
+                check_unnecessary_trust(files, &file, data_type, modifiers, scoped_generic_types);
+
                 let mut symbol_table = match files.symbol_table.get_mut(&file) {
                     Some(symbol_table) => symbol_table,
                     None => {
@@ -262,103 +292,47 @@ pub fn analyze_exp(
                 contexts,
             );
 
-            match get_symbol_definition_info(files, name, &file, name_span.start) {
-                Some(info) => info.data_type,
-                None => DataType::Null,
-            }
+            get_variable_type(files, name, &file, name_span.start).unwrap_or(DataType::Null)
         }
         Expression::Add(exp1, exp2) => {
             let ExpAnalysisResult {
-                exp_ty: ty,
-                return_ty: return1,
-                is_propagating_failure: is_prop1,
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Union(vec![
-                    DataType::Number,
-                    DataType::Text,
-                    DataType::Array(Box::new(DataType::Union(vec![
-                        DataType::Number,
-                        DataType::Text,
-                    ]))),
-                ]),
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: is_prop2,
-                exp_ty: right_hand_ty,
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                ty.clone(),
+                (Operator::Add, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= is_prop1 || is_prop2;
-
-            return_types.extend(return1);
-            return_types.extend(return2);
-
-            if let DataType::Generic(id) = ty {
-                scoped_generic_types.constrain_generic_type(id, right_hand_ty.clone());
-            }
-
-            if !matches_type(&right_hand_ty, &ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Expected type {}, found type {}",
-                        right_hand_ty.to_string(scoped_generic_types),
-                        ty.to_string(scoped_generic_types),
-                    ),
-                    exp1.1,
-                );
-            }
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            ty
+            exp_ty
         }
         Expression::And(exp1, _, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Boolean,
+                (Operator::And, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Array(elements) => {
             let types: Vec<DataType> = elements
@@ -399,9 +373,9 @@ pub fn analyze_exp(
         }
         Expression::Cast(exp, _, (ty, _)) => {
             let ExpAnalysisResult {
+                exp_ty,
                 return_ty,
                 is_propagating_failure: prop,
-                ..
             } = analyze_exp(
                 file_id,
                 file_version,
@@ -412,6 +386,15 @@ pub fn analyze_exp(
                 contexts,
             );
 
+            check_cast(
+                files,
+                &file,
+                (&exp_ty, ty),
+                *exp_span,
+                contexts,
+                scoped_generic_types,
+            );
+
             is_propagating_failure |= prop;
             return_types.extend(return_ty);
 
@@ -467,143 +450,109 @@ pub fn analyze_exp(
                 files.report_error(&file, "Command must have a failure handler", *exp_span);
             }
 
-            DataType::Text
-        }
-        Expression::Divide(exp1, exp2) => {
-            let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
+            let parts = command_parts(files, &(file_id, file_version), inter_cmd);
+
+            let script = shell_script(inter_cmd);
+
+            check_command(
                 files,
-                scoped_generic_types,
+                &(file_id, file_version),
+                &parts,
+                &script,
+                modifiers,
                 contexts,
             );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp2,
-                DataType::Number,
+            check_stdlib_alternative(
                 files,
-                scoped_generic_types,
-                contexts,
+                &(file_id, file_version),
+                &AmberVersion::Alpha035,
+                &parts,
+                failure.is_some(),
+                *exp_span,
             );
+            register_shell_script(files, &(file_id, file_version), script, *exp_span);
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
-
-            DataType::Number
+            DataType::Text
         }
-        Expression::Eq(exp1, exp2) => {
+        Expression::Divide(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp1,
-                DataType::Any,
+                (Operator::Divide, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
+
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
+
+            exp_ty
+        }
+        Expression::Eq(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Any,
+                (Operator::Eq, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Ge(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Ge, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Gt(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Gt, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Is(exp, _, _) => {
             let ExpAnalysisResult {
@@ -627,139 +576,83 @@ pub fn analyze_exp(
         }
         Expression::Le(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Le, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Lt(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Lt, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Modulo(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Modulo, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Number
+            exp_ty
         }
         Expression::Multiply(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Multiply, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Number
+            exp_ty
         }
         Expression::Nameof(_, exp) => {
             let ExpAnalysisResult {
@@ -803,37 +696,23 @@ pub fn analyze_exp(
         }
         Expression::Neq(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Any,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Any,
+                (Operator::Neq, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Not(_, exp) => {
             let ExpAnalysisResult {
@@ -857,37 +736,23 @@ pub fn analyze_exp(
         }
         Expression::Or(exp1, _, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Boolean,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Boolean,
+                (Operator::Or, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Boolean
+            exp_ty
         }
         Expression::Parentheses(exp) => {
             let ExpAnalysisResult {
@@ -911,71 +776,43 @@ pub fn analyze_exp(
         }
         Expression::Range(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Range, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Array(Box::new(DataType::Number))
+            exp_ty
         }
         Expression::Subtract(exp1, exp2) => {
             let ExpAnalysisResult {
-                return_ty: return1,
-                is_propagating_failure: prop1,
-                ..
-            } = analyze_exp(
-                file_id,
-                file_version,
-                exp1,
-                DataType::Number,
-                files,
-                scoped_generic_types,
-                contexts,
-            );
-            let ExpAnalysisResult {
-                return_ty: return2,
-                is_propagating_failure: prop2,
-                ..
-            } = analyze_exp(
+                exp_ty,
+                return_ty,
+                is_propagating_failure: prop,
+            } = analyze_operator(
                 file_id,
                 file_version,
-                exp2,
-                DataType::Number,
+                (Operator::Subtract, exp1, exp2),
+                *exp_span,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            is_propagating_failure |= prop1 || prop2;
-            return_types.extend(return1);
-            return_types.extend(return2);
+            is_propagating_failure |= prop;
+            return_types.extend(return_ty);
 
-            DataType::Number
+            exp_ty
         }
         Expression::Ternary(exp1, _, exp2, _, exp3) => {
             let ExpAnalysisResult {
@@ -991,6 +828,9 @@ pub fn analyze_exp(
                 scoped_generic_types,
                 contexts,
             );
+
+            narrow_condition_types(files, &file, exp1, exp2.1);
+
             let ExpAnalysisResult {
                 exp_ty: if_true,
                 return_ty: return2,
@@ -1096,3 +936,86 @@ pub fn analyze_exp(
         },
     }
 }
+
+/// Analyze the operands of a binary operator, checking it applies to their types.
+fn analyze_operator(
+    file_id: FileId,
+    file_version: FileVersion,
+    (operator, left, right): (Operator, &Spanned<Expression>, &Spanned<Expression>),
+    span: Span,
+    files: &Files,
+    scoped_generic_types: &GenericsMap,
+    contexts: &Vec<Context>,
+) -> ExpAnalysisResult {
+    let left = analyze_exp(
+        file_id,
+        file_version,
+        left,
+        DataType::Any,
+        files,
+        scoped_generic_types,
+        contexts,
+    );
+    let right = analyze_exp(
+        file_id,
+        file_version,
+        right,
+        DataType::Any,
+        files,
+        scoped_generic_types,
+        contexts,
+    );
+
+    let return_types = left
+        .return_ty
+        .into_iter()
+        .chain(right.return_ty)
+        .collect::<Vec<_>>();
+
+    ExpAnalysisResult {
+        exp_ty: check_operator(
+            files,
+            &(file_id, file_version),
+            &AmberVersion::Alpha035,
+            operator,
+            (&left.exp_ty, &right.exp_ty),
+            span,
+            scoped_generic_types,
+        ),
+        is_propagating_failure: left.is_propagating_failure || right.is_propagating_failure,
+        return_ty: match return_types.is_empty() {
+            true => None,
+            false => Some(make_union_type(return_types)),
+        },
+    }
+}
+
+/// Narrow the types of variables checked with `is` in the condition, within the scope
+/// the condition holds in.
+pub fn narrow_condition_types(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (condition, _): &Spanned<Expression>,
+    scope: Span,
+) {
+    match condition {
+        Expression::Is(exp, _, (ty, _)) => {
+            if let (Expression::Var((name, name_span)), _) = &**exp {
+                narrow_variable_type(
+                    files,
+                    file,
+                    name,
+                    name_span.start,
+                    ty.clone(),
+                    scope.start..=scope.end,
+                );
+            }
+        }
+        Expression::And(left, _, right) => {
+            narrow_condition_types(files, file, left, scope);
+            narrow_condition_types(files, file, right, scope);
+        }
+        Expression::Parentheses(exp) => narrow_condition_types(files, file, exp, scope),
+        _ => {}
+    }
+}
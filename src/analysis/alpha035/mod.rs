@@ -1,3 +1,5 @@
 pub mod exp;
+pub mod flow;
 pub mod global;
+pub mod shell;
 pub mod stmnts;
@@ -0,0 +1,268 @@
+use crate::{
+    analysis::{
+        flow::{Accesses, ControlFlowGraph, LoopTargets, NodeId, ENTRY, RETURN},
+        types::DataType,
+    },
+    grammar::{
+        alpha040::{
+            Block, ElseCondition, Expression, FailureHandler, IfChainContent, IfCondition,
+            Statement, VariableInitType,
+        },
+        Span, Spanned,
+    },
+};
+
+/// Build the control flow graph of a function body or the main block.
+pub fn build_cfg(body: &[Spanned<Statement>]) -> ControlFlowGraph {
+    let mut cfg = ControlFlowGraph::new();
+
+    let exits = lower_stmnts(body, &mut cfg, vec![ENTRY], &mut vec![]);
+    cfg.finish(&exits);
+
+    cfg
+}
+
+/// Add the statements to the graph, returning the nodes control leaves them from.
+fn lower_stmnts(
+    stmnts: &[Spanned<Statement>],
+    cfg: &mut ControlFlowGraph,
+    predecessors: Vec<NodeId>,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    stmnts.iter().fold(predecessors, |predecessors, stmnt| {
+        lower_stmnt(stmnt, cfg, predecessors, loops)
+    })
+}
+
+fn lower_block(
+    (block, _): &Spanned<Block>,
+    cfg: &mut ControlFlowGraph,
+    predecessors: Vec<NodeId>,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    match block {
+        Block::Block(_, stmnts) => lower_stmnts(stmnts, cfg, predecessors, loops),
+        Block::Error => predecessors,
+    }
+}
+
+fn lower_stmnt(
+    (stmnt, span): &Spanned<Statement>,
+    cfg: &mut ControlFlowGraph,
+    predecessors: Vec<NodeId>,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    match stmnt {
+        Statement::Comment(_)
+        | Statement::DocString(_)
+        | Statement::Shebang(_)
+        | Statement::Error => return predecessors,
+        _ => {}
+    }
+
+    let node = cfg.add_node(*span, &predecessors);
+    cfg.set_accesses(node, accesses(stmnt));
+
+    match stmnt {
+        Statement::Block(block) => lower_block(block, cfg, vec![node], loops),
+        Statement::IfCondition(_, (if_cond, _), else_cond) => {
+            let mut exits = lower_if_condition(if_cond, cfg, node, loops);
+
+            match else_cond {
+                Some((else_cond, _)) => exits.extend(lower_else(else_cond, cfg, node, loops)),
+                None => exits.push(node),
+            }
+
+            exits
+        }
+        Statement::IfChain(_, if_chain) => {
+            let mut exits = vec![];
+            let mut has_else = false;
+
+            for (if_chain_content, _) in if_chain.iter() {
+                match if_chain_content {
+                    IfChainContent::IfCondition((if_cond, _)) => {
+                        exits.extend(lower_if_condition(if_cond, cfg, node, loops));
+                    }
+                    IfChainContent::Else((else_cond, _)) => {
+                        exits.extend(lower_else(else_cond, cfg, node, loops));
+                        has_else = true;
+                    }
+                }
+            }
+
+            if !has_else {
+                exits.push(node);
+            }
+
+            exits
+        }
+        Statement::InfiniteLoop(_, block) => lower_loop(block, cfg, node, loops),
+        Statement::IterLoop(_, _, _, _, block) => {
+            let mut exits = lower_loop(block, cfg, node, loops);
+            // The iterated array can be empty
+            exits.push(node);
+
+            exits
+        }
+        Statement::Break => {
+            if let Some(targets) = loops.last_mut() {
+                targets.breaks.push(node);
+            }
+
+            vec![]
+        }
+        Statement::Continue => {
+            if let Some(targets) = loops.last() {
+                cfg.add_edge(node, targets.head);
+            }
+
+            vec![]
+        }
+        Statement::Return(..) | Statement::Fail(..) => {
+            cfg.add_edge(node, RETURN);
+
+            vec![]
+        }
+        Statement::Expression(exp) if matches!(exp.0, Expression::Exit(..)) => vec![],
+        stmnt => match failure_handler(stmnt) {
+            Some((FailureHandler::Handle(_, stmnts), _)) => {
+                let mut exits = lower_stmnts(stmnts, cfg, vec![node], loops);
+                exits.push(node);
+
+                exits
+            }
+            _ => vec![node],
+        },
+    }
+}
+
+fn lower_if_condition(
+    if_cond: &IfCondition,
+    cfg: &mut ControlFlowGraph,
+    node: NodeId,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    match if_cond {
+        IfCondition::IfCondition(_, block) => lower_block(block, cfg, vec![node], loops),
+        IfCondition::InlineIfCondition(_, stmnt) => lower_stmnt(stmnt, cfg, vec![node], loops),
+        IfCondition::Error => vec![node],
+    }
+}
+
+fn lower_else(
+    else_cond: &ElseCondition,
+    cfg: &mut ControlFlowGraph,
+    node: NodeId,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    match else_cond {
+        ElseCondition::Else(_, block) => lower_block(block, cfg, vec![node], loops),
+        ElseCondition::InlineElse(_, stmnt) => lower_stmnt(stmnt, cfg, vec![node], loops),
+    }
+}
+
+/// Add the loop body, returning the `break` statements leaving it.
+fn lower_loop(
+    block: &Spanned<Block>,
+    cfg: &mut ControlFlowGraph,
+    head: NodeId,
+    loops: &mut Vec<LoopTargets>,
+) -> Vec<NodeId> {
+    loops.push(LoopTargets {
+        head,
+        breaks: vec![],
+    });
+
+    for exit in lower_block(block, cfg, vec![head], loops) {
+        cfg.add_edge(exit, head);
+    }
+
+    loops
+        .pop()
+        .map(|targets| targets.breaks)
+        .unwrap_or_default()
+}
+
+/// Failure handler of the command or function call the statement consists of.
+fn failure_handler(stmnt: &Statement) -> Option<&Spanned<FailureHandler>> {
+    let exp = match stmnt {
+        Statement::Expression(exp)
+        | Statement::ConstInit(_, _, exp)
+        | Statement::VariableSet(_, exp)
+        | Statement::Echo(_, exp) => &exp.0,
+        Statement::VariableInit(_, _, (VariableInitType::Expression((exp, _)), _)) => exp,
+        Statement::MoveFiles(_, _, _, _, handler) => return handler.as_ref(),
+        _ => return None,
+    };
+
+    match exp {
+        Expression::FunctionInvocation(_, _, _, Some(handler))
+        | Expression::Command(_, _, Some(handler)) => Some(handler),
+        _ => None,
+    }
+}
+
+/// Variables the statement reads and assigns, without its nested statements.
+fn accesses(stmnt: &Statement) -> Accesses {
+    // Statements of the failure handler are nodes of their own
+    let handled = |(_, exp_span): &Spanned<Expression>| match failure_handler(stmnt) {
+        Some((_, handler_span)) => Span::new(exp_span.start, exp_span.end.min(handler_span.start)),
+        None => *exp_span,
+    };
+
+    let reads = match stmnt {
+        Statement::Expression(exp)
+        | Statement::ConstInit(_, _, exp)
+        | Statement::Echo(_, exp)
+        | Statement::Cd(_, exp) => vec![handled(exp)],
+        Statement::VariableInit(_, _, (VariableInitType::Expression(exp), _)) => {
+            vec![handled(exp)]
+        }
+        // An array type is an empty array, other types declare the variable without a value
+        Statement::VariableInit(_, (_, var_span), (VariableInitType::DataType((ty, _)), _))
+            if !matches!(ty, DataType::Array(_)) =>
+        {
+            return Accesses {
+                declares: Some(*var_span),
+                ..Default::default()
+            };
+        }
+        Statement::VariableSet(var, exp) => {
+            return Accesses {
+                reads: vec![handled(exp)],
+                assigns: Some(var.clone()),
+                ..Default::default()
+            };
+        }
+        Statement::ShorthandAdd((_, var_span), exp)
+        | Statement::ShorthandSub((_, var_span), exp)
+        | Statement::ShorthandMul((_, var_span), exp)
+        | Statement::ShorthandDiv((_, var_span), exp)
+        | Statement::ShorthandModulo((_, var_span), exp) => vec![*var_span, exp.1],
+        Statement::Return(_, Some(exp)) | Statement::Fail(_, Some(exp)) => vec![exp.1],
+        Statement::IfCondition(_, (if_cond, _), _) => condition_span(if_cond).into_iter().collect(),
+        Statement::IfChain(_, if_chain) => if_chain
+            .iter()
+            .filter_map(|(if_chain_content, _)| match if_chain_content {
+                IfChainContent::IfCondition((if_cond, _)) => condition_span(if_cond),
+                IfChainContent::Else(_) => None,
+            })
+            .collect(),
+        Statement::IterLoop(_, _, _, exp, _) => vec![exp.1],
+        Statement::MoveFiles(_, _, from, to, _) => vec![handled(from), handled(to)],
+        _ => vec![],
+    };
+
+    Accesses {
+        reads,
+        ..Default::default()
+    }
+}
+
+fn condition_span(if_cond: &IfCondition) -> Option<Span> {
+    match if_cond {
+        IfCondition::IfCondition(exp, _) | IfCondition::InlineIfCondition(exp, _) => Some(exp.1),
+        IfCondition::Error => None,
+    }
+}
@@ -1,22 +1,30 @@
 use crate::{
     analysis::{
-        self, import_symbol, insert_symbol_definition, map_import_path,
-        types::{make_union_type, matches_type, DataType},
+        self, check_shadowed_import, import_symbol, insert_symbol_definition, map_import_path,
+        shell::{define_shell_value, ShellValue},
+        signatures::{check_duplicate_signatures, register_signatures, FunctionSignature},
+        types::{has_unresolved_generic, make_union_type, matches_type, DataType},
         Context, FunctionContext, FunctionSymbol, ImportContext, SymbolInfo, SymbolType,
         VariableSymbol,
     },
     backend::Backend,
-    files::FileVersion,
+    diagnostics::DiagnosticKind,
+    files::{FileVersion, Files},
     grammar::{
-        alpha040::{FunctionArgument, GlobalStatement, ImportContent},
-        Span, Spanned,
+        alpha040::{
+            Expression, FunctionArgument, GlobalStatement, ImportContent, Statement,
+            VariableInitType,
+        },
+        CompilerFlag, Grammar, Span, Spanned,
     },
     paths::FileId,
     stdlib::is_builtin_file,
+    stdlib_index,
 };
 
 use super::{
     exp::analyze_exp,
+    flow::build_cfg,
     stmnts::{analyze_stmnt, StmntAnalysisResult},
 };
 
@@ -28,6 +36,22 @@ pub async fn analyze_global_stmnt(
     backend: &Backend,
 ) {
     let mut contexts = vec![];
+    let mut file_generics = vec![];
+    let mut top_level_stmnts = vec![];
+
+    let mut first_main = None;
+
+    let signatures = function_signatures(ast);
+    let duplicate_functions =
+        check_duplicate_signatures(&backend.files, &(file_id, file_version), &signatures);
+    let mut function_return_types = register_signatures(
+        &backend.files,
+        &(file_id, file_version),
+        &signatures
+            .into_iter()
+            .filter(|signature| !duplicate_functions.contains(&signature.name.1.start))
+            .collect::<Vec<_>>(),
+    );
 
     let url = backend.files.lookup(&file_id);
 
@@ -57,6 +81,8 @@ pub async fn analyze_global_stmnt(
                 declared_return_ty,
                 body,
             ) => {
+                check_shadowed_import(&backend.files, &(file_id, file_version), (name, *name_span));
+
                 // We create scoped generics map, to not overwrite other generics, not defined here
                 let scoped_generics_map = backend.files.generic_types.clone();
 
@@ -169,7 +195,11 @@ pub async fn analyze_global_stmnt(
                 let mut is_propagating = false;
 
                 let mut function_contexts = vec![Context::Function(FunctionContext {
-                    compiler_flags: vec![],
+                    compiler_flags: compiler_flags
+                        .iter()
+                        .map(|(flag, _)| flag.clone())
+                        .collect(),
+                    return_ty: declared_return_ty.as_ref().map(|(ty, _)| ty.clone()),
                 })];
 
                 body.iter().for_each(|stmnt| {
@@ -190,6 +220,12 @@ pub async fn analyze_global_stmnt(
                     return_types.extend(return_ty);
                 });
 
+                build_cfg(body).report(
+                    &backend.files,
+                    &(file_id, file_version),
+                    declared_return_ty.as_ref().map(|(ty, _)| (ty, *name_span)),
+                );
+
                 new_generic_types.iter().for_each(|generic_id| {
                     backend
                         .files
@@ -198,6 +234,8 @@ pub async fn analyze_global_stmnt(
                     backend.files.generic_types.mark_as_inferred(*generic_id);
                 });
 
+                file_generics.extend(new_generic_types.iter().copied());
+
                 let mut inferred_return_type = match return_types.len() {
                     0 => DataType::Null,
                     _ => make_union_type(return_types),
@@ -230,87 +268,114 @@ pub async fn analyze_global_stmnt(
 
                         ty.clone()
                     }
-                    None => inferred_return_type,
+                    None => function_return_types.solve(
+                        name_span.start,
+                        inferred_return_type,
+                        &backend.files.generic_types,
+                    ),
                 };
 
+                if declared_return_ty.is_none()
+                    && !compiler_flags
+                        .iter()
+                        .any(|(flag, _)| *flag == CompilerFlag::AllowGenericReturn)
+                    && has_unresolved_generic(&data_type, &backend.files.generic_types)
+                    && !function_return_types.is_pending(&data_type)
+                {
+                    backend.files.report_diagnostic(
+                        &(file_id, file_version),
+                        DiagnosticKind::GenericReturn,
+                        &format!(
+                            "Function returns a generic type, declare its return type or use `#[{}]` to allow it",
+                            CompilerFlag::AllowGenericReturn
+                        ),
+                        *name_span,
+                    );
+                }
+
                 let mut symbol_table = backend
                     .files
                     .symbol_table
                     .entry((file_id, file_version))
                     .or_insert_with(Default::default);
 
-                insert_symbol_definition(
-                    &mut symbol_table,
-                    &SymbolInfo {
-                        name: name.to_string(),
-                        symbol_type: SymbolType::Function(FunctionSymbol {
-                            arguments: args
-                                .iter()
-                                .filter_map(|(arg, span)| match arg {
-                                    FunctionArgument::Generic((is_ref, _), (name, _)) => Some((
+                let symbol_info = SymbolInfo {
+                    name: name.to_string(),
+                    symbol_type: SymbolType::Function(FunctionSymbol {
+                        arguments: args
+                            .iter()
+                            .filter_map(|(arg, span)| match arg {
+                                FunctionArgument::Generic((is_ref, _), (name, _)) => Some((
+                                    analysis::FunctionArgument {
+                                        name: name.clone(),
+                                        data_type: DataType::Generic(new_generic_types.remove(0)),
+                                        is_optional: false,
+                                        is_ref: *is_ref,
+                                    },
+                                    *span,
+                                )),
+                                FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => Some((
+                                    analysis::FunctionArgument {
+                                        name: name.clone(),
+                                        data_type: ty.clone(),
+                                        is_optional: false,
+                                        is_ref: *is_ref,
+                                    },
+                                    *span,
+                                )),
+                                FunctionArgument::Optional((is_ref, _), (name, _), ty, _) => {
+                                    Some((
                                         analysis::FunctionArgument {
                                             name: name.clone(),
-                                            data_type: DataType::Generic(
-                                                new_generic_types.remove(0),
-                                            ),
-                                            is_optional: false,
+                                            data_type: match ty {
+                                                Some((ty, _)) => ty.clone(),
+                                                None => {
+                                                    DataType::Generic(new_generic_types.remove(0))
+                                                }
+                                            },
+                                            is_optional: true,
                                             is_ref: *is_ref,
                                         },
                                         *span,
-                                    )),
-                                    FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => {
-                                        Some((
-                                            analysis::FunctionArgument {
-                                                name: name.clone(),
-                                                data_type: ty.clone(),
-                                                is_optional: false,
-                                                is_ref: *is_ref,
-                                            },
-                                            *span,
-                                        ))
-                                    }
-                                    FunctionArgument::Optional((is_ref, _), (name, _), ty, _) => {
-                                        Some((
-                                            analysis::FunctionArgument {
-                                                name: name.clone(),
-                                                data_type: match ty {
-                                                    Some((ty, _)) => ty.clone(),
-                                                    None => DataType::Generic(
-                                                        new_generic_types.remove(0),
-                                                    ),
-                                                },
-                                                is_optional: true,
-                                                is_ref: *is_ref,
-                                            },
-                                            *span,
-                                        ))
-                                    }
-                                    FunctionArgument::Error => None,
-                                })
-                                .collect::<Vec<_>>(),
-                            is_public: *is_pub,
-                            compiler_flags: compiler_flags
-                                .iter()
-                                .map(|(flag, _)| flag.clone())
-                                .collect(),
-                            docs: match contexts.clone().last() {
-                                Some(Context::DocString(doc)) => {
-                                    contexts.pop();
-                                    Some(doc.clone())
+                                    ))
                                 }
-                                _ => None,
-                            },
-                        }),
-                        data_type: data_type.clone(),
-                        is_definition: true,
-                        undefined: false,
-                        span: *name_span,
-                        contexts: vec![],
-                    },
-                    (file_id, file_version),
-                    span.end..=usize::MAX,
-                    *is_pub,
-                );
+                                FunctionArgument::Error => None,
+                            })
+                            .collect::<Vec<_>>(),
+                        is_public: *is_pub,
+                        compiler_flags: compiler_flags
+                            .iter()
+                            .map(|(flag, _)| flag.clone())
+                            .collect(),
+                        docs: match contexts.clone().last() {
+                            Some(Context::DocString(doc)) => {
+                                contexts.pop();
+                                Some(doc.clone())
+                            }
+                            _ => None,
+                        },
+                    }),
+                    data_type: data_type.clone(),
+                    is_definition: true,
+                    undefined: false,
+                    span: *name_span,
+                    contexts: vec![],
+                };
+
+                if duplicate_functions.contains(&name_span.start) {
+                    // Calls resolve to the first definition of the function
+                    symbol_table
+                        .symbols
+                        .insert(name_span.start..=name_span.end, symbol_info);
+                } else {
+                    insert_symbol_definition(
+                        &mut symbol_table,
+                        &symbol_info,
+                        (file_id, file_version),
+                        span.end..=usize::MAX,
+                        *is_pub,
+                    );
+                }
             }
             GlobalStatement::Import(
                 (is_public_import, _),
@@ -321,9 +386,11 @@ pub async fn analyze_global_stmnt(
             ) => {
                 let uri = &backend.files.lookup(&file_id);
 
-                let result = backend
-                    .open_document(&map_import_path(uri, path, backend).await)
-                    .await;
+                let import_uri = map_import_path(uri, path, backend).await;
+                let result = match stdlib_index::load(backend, &import_uri) {
+                    Some(file) => Ok(file),
+                    None => backend.open_document(&import_uri).await,
+                };
 
                 {
                     let mut symbol_table = backend
@@ -355,6 +422,9 @@ pub async fn analyze_global_stmnt(
                         "File doesn't exist",
                         *path_span,
                     );
+                    backend
+                        .files
+                        .add_missing_file_dependency(&(file_id, file_version), import_uri);
 
                     continue;
                 }
@@ -375,6 +445,13 @@ pub async fn analyze_global_stmnt(
                     .files
                     .add_file_dependency(&(file_id, file_version), imported_file.0);
 
+                check_imported_file(
+                    &backend.files,
+                    &(file_id, file_version),
+                    &imported_file,
+                    *path_span,
+                );
+
                 let imported_file_symbol_table =
                     match backend.files.symbol_table.get(&imported_file) {
                         Some(symbol_table_ref) => symbol_table_ref.clone(),
@@ -535,7 +612,18 @@ pub async fn analyze_global_stmnt(
                         }),
                 }
             }
-            GlobalStatement::Main(_, args, body) => {
+            GlobalStatement::Main((_, main_span), args, body) => {
+                match first_main {
+                    Some(first_span) => backend.files.report_diagnostic_with_related(
+                        &(file_id, file_version),
+                        DiagnosticKind::Analysis,
+                        "Only one `main` block is allowed",
+                        *main_span,
+                        vec![("First `main` block is here".to_string(), first_span)],
+                    ),
+                    None => first_main = Some(*main_span),
+                }
+
                 if let Some((args, args_span)) = args {
                     let mut symbol_table = backend
                         .files
@@ -558,6 +646,9 @@ pub async fn analyze_global_stmnt(
                         args_span.end..=span.end,
                         false,
                     );
+
+                    // Arguments the script is run with can hold anything
+                    define_shell_value(&mut symbol_table, args_span.start, ShellValue::TAINTED);
                 }
 
                 body.iter().for_each(|stmnt| {
@@ -571,8 +662,20 @@ pub async fn analyze_global_stmnt(
                         &mut vec![Context::Main],
                     );
                 });
+
+                build_cfg(body).report(&backend.files, &(file_id, file_version), None);
             }
             GlobalStatement::Statement(stmnt) => {
+                if let Statement::VariableInit(_, (name, name_span), _)
+                | Statement::ConstInit(_, (name, name_span), _) = &stmnt.0
+                {
+                    check_shadowed_import(
+                        &backend.files,
+                        &(file_id, file_version),
+                        (name, *name_span),
+                    );
+                }
+
                 analyze_stmnt(
                     file_id,
                     file_version,
@@ -582,7 +685,128 @@ pub async fn analyze_global_stmnt(
                     &backend.files.generic_types.clone(),
                     &mut contexts,
                 );
+
+                top_level_stmnts.push((**stmnt).clone());
             }
         }
     }
+
+    build_cfg(&top_level_stmnts).report_unassigned_reads(&backend.files, &(file_id, file_version));
+    function_return_types.finish(&backend.files, &(file_id, file_version));
+
+    backend
+        .files
+        .generic_types
+        .insert(file_id, file_version, file_generics);
+}
+
+/// Signatures of the functions defined in the file.
+fn function_signatures(ast: &[Spanned<GlobalStatement>]) -> Vec<FunctionSignature> {
+    ast.iter()
+        .filter_map(|(global, span)| match global {
+            GlobalStatement::FunctionDefinition(_, _, _, name, args, return_ty, _) => {
+                Some(FunctionSignature {
+                    name: name.clone(),
+                    arguments: args
+                        .iter()
+                        .filter_map(|(arg, span)| {
+                            let (is_ref, name, data_type, is_optional) = match arg {
+                                FunctionArgument::Generic((is_ref, _), (name, _)) => {
+                                    (is_ref, name, DataType::Any, false)
+                                }
+                                FunctionArgument::Typed((is_ref, _), (name, _), (ty, _)) => {
+                                    (is_ref, name, ty.clone(), false)
+                                }
+                                FunctionArgument::Optional((is_ref, _), (name, _), ty, _) => (
+                                    is_ref,
+                                    name,
+                                    ty.as_ref()
+                                        .map(|(ty, _)| ty.clone())
+                                        .unwrap_or(DataType::Any),
+                                    true,
+                                ),
+                                FunctionArgument::Error => return None,
+                            };
+
+                            Some((
+                                analysis::FunctionArgument {
+                                    name: name.clone(),
+                                    data_type,
+                                    is_optional,
+                                    is_ref: *is_ref,
+                                },
+                                *span,
+                            ))
+                        })
+                        .collect(),
+                    return_ty: return_ty.as_ref().map(|(ty, _)| ty.clone()),
+                    span: *span,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Warn about a `main` block and statements with side effects in an imported file.
+fn check_imported_file(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    imported_file: &(FileId, FileVersion),
+    path_span: Span,
+) {
+    let ast = match files.ast_map.get(imported_file) {
+        Some(ast) => ast,
+        None => return,
+    };
+
+    let ast = match &*ast {
+        Grammar::Alpha040(Some(ast)) => ast,
+        _ => return,
+    };
+
+    if ast
+        .iter()
+        .any(|(global, _)| matches!(global, GlobalStatement::Main(..)))
+    {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::ImportSideEffects,
+            "Imported file has a `main` block, it doesn't run when the file is imported",
+            path_span,
+        );
+    }
+
+    if ast.iter().any(|(global, _)| match global {
+        GlobalStatement::Statement(stmnt) => has_side_effects(&stmnt.0),
+        _ => false,
+    }) {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::ImportSideEffects,
+            "Imported file runs statements at the top level when it's imported",
+            path_span,
+        );
+    }
+}
+
+/// Whether running the statement does more than defining a variable.
+fn has_side_effects(stmnt: &Statement) -> bool {
+    let is_call = |exp: &Expression| {
+        matches!(
+            exp,
+            Expression::Command(..) | Expression::FunctionInvocation(..)
+        )
+    };
+
+    match stmnt {
+        Statement::Comment(_)
+        | Statement::DocString(_)
+        | Statement::Shebang(_)
+        | Statement::Error => false,
+        Statement::VariableInit(_, _, (VariableInitType::Expression((exp, _)), _)) => is_call(exp),
+        Statement::ConstInit(_, _, exp) => is_call(&exp.0),
+        Statement::VariableInit(..) => false,
+        _ => true,
+    }
 }
@@ -1,21 +1,29 @@
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_definition, insert_symbol_reference,
-        types::{make_union_type, matches_type, GenericsMap},
+        check_propagation, clear_narrowed_type, get_symbol_definition_info,
+        insert_symbol_definition, insert_symbol_reference, is_allowed_by_flag,
+        operators::{check_operator, Operator},
+        shell::{assign_shell_value, check_path_argument, define_shell_value, ShellValue},
+        types::{make_union_type, GenericsMap},
         BlockContext, Context, DataType, SymbolInfo, SymbolLocation, SymbolType, VariableSymbol,
     },
+    backend::AmberVersion,
+    diagnostics::DiagnosticKind,
     files::{FileVersion, Files},
     grammar::{
         alpha040::{
             Block, ElseCondition, FailureHandler, IfChainContent, IfCondition, IterLoopVars,
             Statement, VariableInitType,
         },
-        CommandModifier, Spanned,
+        CommandModifier, CompilerFlag, Spanned,
     },
     paths::FileId,
 };
 
-use super::exp::{analyze_exp, ExpAnalysisResult};
+use super::{
+    exp::{analyze_exp, narrow_condition_types, ExpAnalysisResult},
+    shell::shell_value,
+};
 
 #[derive(Debug, Clone)]
 pub struct StmntAnalysisResult {
@@ -56,6 +64,8 @@ pub fn analyze_stmnt(
                 match if_chain_content {
                     IfChainContent::IfCondition((condition, _)) => match condition {
                         IfCondition::IfCondition(exp, block) => {
+                            narrow_condition_types(files, &file, exp, block.1);
+
                             let exp = analyze_exp(
                                 file_id,
                                 file_version,
@@ -78,6 +88,8 @@ pub fn analyze_stmnt(
                             stmnts.push(stmnt);
                         }
                         IfCondition::InlineIfCondition(exp, boxed_stmnt) => {
+                            narrow_condition_types(files, &file, exp, boxed_stmnt.1);
+
                             let exp = analyze_exp(
                                 file_id,
                                 file_version,
@@ -135,11 +147,17 @@ pub fn analyze_stmnt(
             get_stmnt_analysis_result(stmnts, exps)
         }
         Statement::IfCondition(_, if_cond, else_cond) => {
+            if let Some((else_cond, _)) = else_cond {
+                check_nested_if_else(&file, else_cond, files, contexts);
+            }
+
             let mut stmnts = vec![];
             let mut exps = vec![];
 
             match &if_cond.0 {
                 IfCondition::IfCondition(exp, block) => {
+                    narrow_condition_types(files, &file, exp, block.1);
+
                     let exp = analyze_exp(
                         file_id,
                         file_version,
@@ -162,6 +180,8 @@ pub fn analyze_stmnt(
                     exps.push(exp);
                 }
                 IfCondition::InlineIfCondition(exp, boxed_stmnt) => {
+                    narrow_condition_types(files, &file, exp, boxed_stmnt.1);
+
                     let exp = analyze_exp(
                         file_id,
                         file_version,
@@ -233,6 +253,9 @@ pub fn analyze_stmnt(
             )
         }
         Statement::IterLoop(_, (vars, _), _, exp, block) => {
+            // Items of the iterated array come from wherever the array does
+            let item_value = shell_value(files, &file, exp).derived();
+
             let block_span = block.1;
 
             let exp = analyze_exp(
@@ -295,6 +318,7 @@ pub fn analyze_stmnt(
                         block_span.start..=block_span.end,
                         false,
                     );
+                    define_shell_value(&mut symbol_table, var2_span.start, item_value);
                 }
                 IterLoopVars::Single((var, var_span)) => {
                     let mut symbol_table = files
@@ -316,6 +340,7 @@ pub fn analyze_stmnt(
                         block_span.start..=block_span.end,
                         false,
                     );
+                    define_shell_value(&mut symbol_table, var_span.start, item_value);
                 }
                 _ => {}
             }
@@ -335,6 +360,11 @@ pub fn analyze_stmnt(
             get_stmnt_analysis_result(vec![block], vec![exp])
         }
         Statement::VariableInit(_, (var_name, var_span), (value, _)) => {
+            let var_value = match value {
+                VariableInitType::Expression(exp) => shell_value(files, &file, exp),
+                _ => ShellValue::default(),
+            };
+
             let exp = match value {
                 VariableInitType::Expression(exp) => analyze_exp(
                     file_id,
@@ -382,6 +412,7 @@ pub fn analyze_stmnt(
                 span.end..=scope_end,
                 false,
             );
+            define_shell_value(&mut symbol_table, var_span.start, var_value);
 
             StmntAnalysisResult {
                 is_propagating_failure: exp.is_propagating_failure,
@@ -389,6 +420,8 @@ pub fn analyze_stmnt(
             }
         }
         Statement::ConstInit(_, (var_name, var_span), exp) => {
+            let var_value = shell_value(files, &file, exp);
+
             let exp = analyze_exp(
                 file_id,
                 file_version,
@@ -424,6 +457,7 @@ pub fn analyze_stmnt(
                 span.end..=scope_end,
                 false,
             );
+            define_shell_value(&mut symbol_table, var_span.start, var_value);
 
             StmntAnalysisResult {
                 is_propagating_failure: exp.is_propagating_failure,
@@ -548,41 +582,25 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            let default_ty = DataType::Union(vec![
-                DataType::Text,
-                DataType::Number,
-                DataType::Array(Box::new(DataType::Union(vec![
-                    DataType::Text,
-                    DataType::Number,
-                ]))),
-            ]);
-
             let exp_analysis = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                var_ty.clone(),
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
-            if !matches_type(&default_ty, &var_ty, scoped_generic_types)
-                || !matches_type(&exp_analysis.exp_ty, &var_ty, scoped_generic_types)
-            {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot add to variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            if let DataType::Generic(id) = var_ty {
-                scoped_generic_types.constrain_generic_type(id, exp_analysis.exp_ty);
-            }
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha040,
+                Operator::Add,
+                (&var_ty, &exp_analysis.exp_ty),
+                *span,
+                scoped_generic_types,
+            );
 
             insert_symbol_reference(
                 var,
@@ -619,31 +637,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot divide variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            if let DataType::Generic(id) = var_ty {
-                scoped_generic_types.constrain_generic_type(id, DataType::Number);
-            }
-
             let exp_analysis = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha040,
+                Operator::Divide,
+                (&var_ty, &exp_analysis.exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -679,31 +692,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use modulo with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            if let DataType::Generic(id) = var_ty {
-                scoped_generic_types.constrain_generic_type(id, DataType::Number);
-            }
-
             let exp_analysis = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha040,
+                Operator::Modulo,
+                (&var_ty, &exp_analysis.exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -739,31 +747,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use multiply with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            if let DataType::Generic(id) = var_ty {
-                scoped_generic_types.constrain_generic_type(id, DataType::Number);
-            }
-
             let exp_analysis = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha040,
+                Operator::Multiply,
+                (&var_ty, &exp_analysis.exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -799,31 +802,26 @@ pub fn analyze_stmnt(
                 None => DataType::Any,
             };
 
-            if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
-                    &file,
-                    &format!(
-                        "Cannot use subtract with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
-                    *var_span,
-                );
-            }
-
-            if let DataType::Generic(id) = var_ty {
-                scoped_generic_types.constrain_generic_type(id, DataType::Number);
-            }
-
             let exp_analysis = analyze_exp(
                 file_id,
                 file_version,
                 exp,
-                DataType::Number,
+                DataType::Any,
                 files,
                 scoped_generic_types,
                 contexts,
             );
 
+            check_operator(
+                files,
+                &file,
+                &AmberVersion::Alpha040,
+                Operator::Subtract,
+                (&var_ty, &exp_analysis.exp_ty),
+                *span,
+                scoped_generic_types,
+            );
+
             insert_symbol_reference(
                 var,
                 files,
@@ -842,6 +840,8 @@ pub fn analyze_stmnt(
             }
         }
         Statement::VariableSet((var, var_span), exp) => {
+            let var_value = shell_value(files, &file, exp);
+
             let var_ty = match get_symbol_definition_info(files, var, &file, var_span.start) {
                 Some(info) => {
                     match info.symbol_type {
@@ -869,6 +869,9 @@ pub fn analyze_stmnt(
                 contexts,
             );
 
+            assign_shell_value(files, &file, var, var_span.start, var_value);
+            clear_narrowed_type(files, &file, var, var_span.start);
+
             insert_symbol_reference(
                 var,
                 files,
@@ -907,6 +910,8 @@ pub fn analyze_stmnt(
             }
         }
         Statement::Cd(_, exp) => {
+            check_path_argument(files, &file, "cd", shell_value(files, &file, exp), exp.1);
+
             let exp = analyze_exp(
                 file_id,
                 file_version,
@@ -923,6 +928,10 @@ pub fn analyze_stmnt(
             }
         }
         Statement::MoveFiles(modifiers, _, from_exp, to_exp, handler) => {
+            for exp in [from_exp, to_exp] {
+                check_path_argument(files, &file, "mv", shell_value(files, &file, exp), exp.1);
+            }
+
             let exp1 = analyze_exp(
                 file_id,
                 file_version,
@@ -1084,6 +1093,14 @@ pub fn analyze_failure_handler(
                 );
             }
 
+            check_propagation(
+                files,
+                &(file_id, file_version),
+                *span,
+                &contexts,
+                scoped_generic_types,
+            );
+
             is_propagating = true;
         }
     };
@@ -1135,3 +1152,39 @@ fn get_stmnt_analysis_result(
         },
     }
 }
+
+/// Warn about an `else` holding just another condition, which should be an if chain.
+fn check_nested_if_else(
+    file: &(FileId, FileVersion),
+    else_cond: &ElseCondition,
+    files: &Files,
+    contexts: &[Context],
+) {
+    if is_allowed_by_flag(contexts, &CompilerFlag::AllowNestedIfElse) {
+        return;
+    }
+
+    let stmnts = match else_cond {
+        ElseCondition::Else(_, (Block::Block(_, stmnts), _)) => stmnts.iter().collect(),
+        ElseCondition::Else(_, (Block::Error, _)) => vec![],
+        ElseCondition::InlineElse(_, stmnt) => vec![stmnt.as_ref()],
+    };
+
+    let mut stmnts = stmnts
+        .into_iter()
+        .filter(|(stmnt, _)| !matches!(stmnt, Statement::Comment(_)));
+
+    if let (Some((Statement::IfCondition(..) | Statement::IfChain(..), span)), None) =
+        (stmnts.next(), stmnts.next())
+    {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::NestedIfElse,
+            &format!(
+                "Use an if chain instead of nested if else statements, or `#[{}]` to allow it",
+                CompilerFlag::AllowNestedIfElse
+            ),
+            *span,
+        );
+    }
+}
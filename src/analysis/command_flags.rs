@@ -0,0 +1,215 @@
+/// Options of common tools, with their descriptions.
+const COMMAND_FLAGS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "cat",
+        &[
+            ("-n", "Number all output lines"),
+            ("-b", "Number non-empty output lines"),
+            ("-s", "Squeeze repeated empty lines"),
+            ("-A", "Show all non-printing characters"),
+        ],
+    ),
+    (
+        "chmod",
+        &[
+            ("-R", "Change files and directories recursively"),
+            ("-v", "Print each processed file"),
+        ],
+    ),
+    (
+        "cp",
+        &[
+            ("-r", "Copy directories recursively"),
+            ("-f", "Overwrite existing files"),
+            ("-i", "Prompt before overwriting"),
+            ("-n", "Don't overwrite existing files"),
+            ("-p", "Preserve mode, ownership and timestamps"),
+            ("-a", "Archive, preserving everything"),
+            ("-v", "Print each copied file"),
+        ],
+    ),
+    (
+        "curl",
+        &[
+            ("-s", "Silent mode"),
+            ("-S", "Show errors in silent mode"),
+            ("-L", "Follow redirects"),
+            ("-f", "Fail on HTTP errors"),
+            ("-o", "Write output to a file"),
+            ("-O", "Write output to a file named like the remote file"),
+            ("-X", "Request method to use"),
+            ("-H", "Header to send"),
+            ("-d", "Data to send in a POST request"),
+            ("--fail", "Fail on HTTP errors"),
+            ("--silent", "Silent mode"),
+            ("--location", "Follow redirects"),
+            ("--output", "Write output to a file"),
+        ],
+    ),
+    (
+        "find",
+        &[
+            ("-name", "Match the base name against a pattern"),
+            (
+                "-iname",
+                "Match the base name against a pattern, ignoring case",
+            ),
+            ("-type", "Match the file type"),
+            ("-maxdepth", "Descend at most the given number of levels"),
+            ("-mindepth", "Skip the given number of levels"),
+            ("-path", "Match the path against a pattern"),
+            ("-newer", "Match files modified after the given file"),
+            ("-exec", "Run a command on each match"),
+            ("-delete", "Delete the matching files"),
+            ("-print0", "Separate the matches with null characters"),
+        ],
+    ),
+    (
+        "grep",
+        &[
+            ("-i", "Ignore case"),
+            ("-v", "Select non-matching lines"),
+            ("-r", "Search directories recursively"),
+            ("-n", "Print line numbers"),
+            ("-c", "Print the number of matching lines"),
+            ("-l", "Print the names of matching files"),
+            ("-q", "Don't print anything, only exit with the status"),
+            ("-E", "Use extended regular expressions"),
+            ("-F", "Match fixed strings"),
+            ("-o", "Print only the matched parts"),
+            ("-w", "Match whole words"),
+            ("-x", "Match whole lines"),
+        ],
+    ),
+    (
+        "head",
+        &[
+            ("-n", "Number of lines to print"),
+            ("-c", "Number of bytes to print"),
+        ],
+    ),
+    (
+        "ln",
+        &[
+            ("-s", "Make symbolic links"),
+            ("-f", "Remove existing destination files"),
+            ("-n", "Don't follow a symbolic link to a directory"),
+        ],
+    ),
+    (
+        "ls",
+        &[
+            ("-l", "Use the long listing format"),
+            ("-a", "Show entries starting with `.`"),
+            ("-A", "Show entries starting with `.`, without `.` and `..`"),
+            ("-h", "Print sizes in a human readable format"),
+            ("-R", "List subdirectories recursively"),
+            ("-t", "Sort by modification time"),
+            ("-r", "Reverse the order"),
+            ("-1", "List one entry per line"),
+            ("-d", "List directories themselves, not their contents"),
+        ],
+    ),
+    (
+        "mkdir",
+        &[
+            ("-p", "Make parent directories as needed"),
+            ("-m", "Mode of the created directories"),
+            ("-v", "Print each created directory"),
+        ],
+    ),
+    (
+        "mv",
+        &[
+            ("-f", "Overwrite existing files"),
+            ("-i", "Prompt before overwriting"),
+            ("-n", "Don't overwrite existing files"),
+            ("-v", "Print each moved file"),
+        ],
+    ),
+    (
+        "rm",
+        &[
+            ("-r", "Remove directories recursively"),
+            ("-f", "Ignore missing files and never prompt"),
+            ("-i", "Prompt before every removal"),
+            ("-d", "Remove empty directories"),
+            ("-v", "Print each removed file"),
+        ],
+    ),
+    (
+        "sed",
+        &[
+            ("-e", "Script to run"),
+            ("-i", "Edit files in place"),
+            ("-n", "Don't print the pattern space"),
+            ("-E", "Use extended regular expressions"),
+        ],
+    ),
+    (
+        "sort",
+        &[
+            ("-n", "Compare numerical values"),
+            ("-r", "Reverse the result"),
+            ("-u", "Print only unique lines"),
+            ("-k", "Sort by the given key"),
+            ("-t", "Field separator"),
+            ("-h", "Compare human readable numbers"),
+        ],
+    ),
+    (
+        "tail",
+        &[
+            ("-n", "Number of lines to print"),
+            ("-c", "Number of bytes to print"),
+            ("-f", "Print appended data as the file grows"),
+        ],
+    ),
+    (
+        "tar",
+        &[
+            ("-c", "Create an archive"),
+            ("-x", "Extract an archive"),
+            ("-t", "List the contents of an archive"),
+            ("-f", "Archive file to use"),
+            ("-z", "Filter the archive through gzip"),
+            ("-j", "Filter the archive through bzip2"),
+            ("-v", "Print each processed file"),
+            ("-C", "Change to the directory"),
+        ],
+    ),
+    (
+        "touch",
+        &[
+            ("-a", "Change only the access time"),
+            ("-m", "Change only the modification time"),
+            ("-c", "Don't create any files"),
+        ],
+    ),
+    (
+        "wc",
+        &[
+            ("-l", "Print the number of lines"),
+            ("-w", "Print the number of words"),
+            ("-c", "Print the number of bytes"),
+            ("-m", "Print the number of characters"),
+        ],
+    ),
+    (
+        "xargs",
+        &[
+            ("-0", "Input items are separated by null characters"),
+            ("-n", "Number of arguments per command"),
+            ("-I", "Replace the given string with the input items"),
+            ("-r", "Don't run the command if the input is empty"),
+        ],
+    ),
+];
+
+/// Options of the command with their descriptions, empty for unknown commands.
+pub fn command_flags(command: &str) -> &'static [(&'static str, &'static str)] {
+    COMMAND_FLAGS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map_or(&[], |(_, flags)| flags)
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    diagnostics::DiagnosticKind,
+    files::{FileVersion, Files},
+    grammar::{Span, Spanned},
+    paths::FileId,
+};
+
+use super::{types::DataType, SymbolTable, SymbolType};
+
+pub type NodeId = usize;
+
+/// Control flow graph of a function body or the main block.
+///
+/// Each statement is a node, with edges to the statements that can run after it.
+/// Statements leaving the body, like `return` or `fail`, have an edge to [`RETURN`],
+/// the last statements of the body fall through to [`END`].
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    span: Span,
+    successors: Vec<NodeId>,
+    accesses: Accesses,
+}
+
+/// Variables a statement reads and assigns when control passes through it.
+#[derive(Debug, Clone, Default)]
+pub struct Accesses {
+    /// Spans of the code the statement runs itself, without its nested statements.
+    pub reads: Vec<Span>,
+    /// Name of a variable declared without a value.
+    pub declares: Option<Span>,
+    /// Name of a variable assigned a value, after the reads.
+    pub assigns: Option<Spanned<String>>,
+}
+
+/// Node control enters the body through.
+pub const ENTRY: NodeId = 0;
+/// Node reached by falling through the end of the body.
+pub const END: NodeId = 1;
+/// Node reached by leaving the body with `return` or `fail`.
+pub const RETURN: NodeId = 2;
+
+/// Targets of `break` and `continue` statements in a loop.
+#[derive(Debug, Clone)]
+pub struct LoopTargets {
+    pub head: NodeId,
+    pub breaks: Vec<NodeId>,
+}
+
+impl Default for ControlFlowGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn new() -> Self {
+        let node = Node {
+            span: Span::new(0, 0),
+            successors: vec![],
+            accesses: Accesses::default(),
+        };
+
+        Self {
+            nodes: vec![node.clone(), node.clone(), node],
+        }
+    }
+
+    /// Add a statement reached from all the predecessors.
+    pub fn add_node(&mut self, span: Span, predecessors: &[NodeId]) -> NodeId {
+        let id = self.nodes.len();
+
+        self.nodes.push(Node {
+            span,
+            successors: vec![],
+            accesses: Accesses::default(),
+        });
+
+        for predecessor in predecessors {
+            self.add_edge(*predecessor, id);
+        }
+
+        id
+    }
+
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId) {
+        if !self.nodes[from].successors.contains(&to) {
+            self.nodes[from].successors.push(to);
+        }
+    }
+
+    pub fn set_accesses(&mut self, node: NodeId, accesses: Accesses) {
+        self.nodes[node].accesses = accesses;
+    }
+
+    /// Connect the statements the body ends with to [`END`].
+    pub fn finish(&mut self, exits: &[NodeId]) {
+        for exit in exits {
+            self.add_edge(*exit, END);
+        }
+    }
+
+    fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::from([ENTRY]);
+
+        reachable[ENTRY] = true;
+
+        while let Some(node) = queue.pop_front() {
+            for successor in self.nodes[node].successors.iter() {
+                if !reachable[*successor] {
+                    reachable[*successor] = true;
+                    queue.push_back(*successor);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Whether the end of the body can be reached without leaving it.
+    pub fn falls_through(&self) -> bool {
+        self.reachable()[END]
+    }
+
+    /// Spans of statements that can never run.
+    ///
+    /// Statements nested in an unreachable statement are not listed separately.
+    pub fn unreachable_spans(&self) -> Vec<Span> {
+        let reachable = self.reachable();
+
+        let mut spans = self
+            .nodes
+            .iter()
+            .enumerate()
+            .skip(RETURN + 1)
+            .filter(|(id, _)| !reachable[*id])
+            .map(|(_, node)| node.span)
+            .collect::<Vec<_>>();
+
+        spans.sort_by_key(|span| (span.start, std::cmp::Reverse(span.end)));
+
+        let mut outermost: Vec<Span> = vec![];
+
+        for span in spans {
+            match outermost.last() {
+                Some(last) if span.start >= last.start && span.end <= last.end => {}
+                _ => outermost.push(span),
+            }
+        }
+
+        outermost
+    }
+
+    /// Names of variables declared without a value, that are read before being assigned
+    /// on some path, with the span of the read.
+    ///
+    /// Variables are identified by their definition, so shadowing variables are told
+    /// apart.
+    pub fn unassigned_reads(
+        &self,
+        symbol_table: &SymbolTable,
+        file: &(FileId, FileVersion),
+    ) -> Vec<Spanned<String>> {
+        let definition_of = |name: &str, position: usize| {
+            symbol_table
+                .definitions
+                .get(name)
+                .and_then(|definitions| definitions.get(&position))
+                .filter(|definition| definition.file == *file)
+                .map(|definition| definition.start)
+        };
+
+        // Definitions of variables that can be unassigned when control enters each node,
+        // `None` for nodes not reached yet
+        let mut unassigned: Vec<Option<HashSet<usize>>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::from([ENTRY]);
+
+        unassigned[ENTRY] = Some(HashSet::new());
+
+        while let Some(node) = queue.pop_front() {
+            let accesses = &self.nodes[node].accesses;
+            let mut state = unassigned[node].clone().unwrap_or_default();
+
+            if let Some(span) = accesses.declares {
+                state.insert(span.start);
+            }
+
+            if let Some((name, span)) = &accesses.assigns {
+                if let Some(definition) = definition_of(name, span.start) {
+                    state.remove(&definition);
+                }
+            }
+
+            for successor in self.nodes[node].successors.iter() {
+                let is_reached = unassigned[*successor].is_some();
+                let successor_state = unassigned[*successor].get_or_insert_with(HashSet::new);
+                let len = successor_state.len();
+
+                successor_state.extend(state.iter().copied());
+
+                if (!is_reached || successor_state.len() != len) && !queue.contains(successor) {
+                    queue.push_back(*successor);
+                }
+            }
+        }
+
+        let mut reads = vec![];
+
+        for (node, state) in self.nodes.iter().zip(unassigned.iter()) {
+            let state = match state {
+                Some(state) if !state.is_empty() => state,
+                _ => continue,
+            };
+
+            for span in node.accesses.reads.iter() {
+                for (_, symbol_info) in symbol_table.symbols.overlapping(span.start..=span.end) {
+                    if symbol_info.is_definition
+                        || !matches!(symbol_info.symbol_type, SymbolType::Variable(_))
+                        || symbol_info.span.start < span.start
+                        || symbol_info.span.end > span.end
+                    {
+                        continue;
+                    }
+
+                    if definition_of(&symbol_info.name, symbol_info.span.start)
+                        .is_some_and(|definition| state.contains(&definition))
+                    {
+                        reads.push((symbol_info.name.clone(), symbol_info.span));
+                    }
+                }
+            }
+        }
+
+        reads.sort_by_key(|(_, span)| span.start);
+        reads.dedup();
+
+        reads
+    }
+
+    /// Report unreachable statements, reads of unassigned variables, and a missing return
+    /// if the function is declared to return a value but can fall through the end of its
+    /// body.
+    pub fn report(
+        &self,
+        files: &Files,
+        file: &(FileId, FileVersion),
+        declared_return_ty: Option<(&DataType, Span)>,
+    ) {
+        for span in self.unreachable_spans() {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::UnreachableCode,
+                "Unreachable code",
+                span,
+            );
+        }
+
+        if let Some((ty, span)) = declared_return_ty {
+            if returns_value(ty) && self.falls_through() {
+                files.report_diagnostic(
+                    file,
+                    DiagnosticKind::MissingReturn,
+                    "Not all code paths return a value",
+                    span,
+                );
+            }
+        }
+
+        self.report_unassigned_reads(files, file);
+    }
+
+    /// Report reads of variables that might not be assigned a value yet.
+    pub fn report_unassigned_reads(&self, files: &Files, file: &(FileId, FileVersion)) {
+        let reads = match files.symbol_table.get(file) {
+            Some(symbol_table) => self.unassigned_reads(&symbol_table, file),
+            None => return,
+        };
+
+        for (name, span) in reads {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::UnassignedVariable,
+                &format!("Variable `{}` might be used before it's assigned", name),
+                span,
+            );
+        }
+    }
+}
+
+fn returns_value(ty: &DataType) -> bool {
+    match ty {
+        DataType::Null | DataType::Error => false,
+        DataType::Failable(ty) => returns_value(ty),
+        _ => true,
+    }
+}
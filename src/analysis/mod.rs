@@ -1,19 +1,29 @@
 use rangemap::RangeInclusiveMap;
+use serde::{Deserialize, Serialize};
+use shell::ShellValue;
 use std::{collections::HashMap, ops::RangeInclusive};
 use tower_lsp::lsp_types::Url;
+use tracing::warn;
 use types::{DataType, GenericsMap};
 
 use crate::{
     backend::{AmberVersion, Backend},
+    diagnostics::DiagnosticKind,
     files::{FileVersion, Files},
     grammar::{CommandModifier, CompilerFlag, Span, Spanned},
     paths::FileId,
-    stdlib::resolve,
+    stdlib::{is_builtin_file, resolve},
 };
 
 pub mod alpha034;
 pub mod alpha035;
 pub mod alpha040;
+pub mod command_flags;
+pub mod flow;
+pub mod operators;
+pub mod shell;
+pub mod signatures;
+pub mod stdlib_commands;
 pub mod types;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -24,7 +34,7 @@ pub struct FunctionSymbol {
     pub docs: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct FunctionArgument {
     pub name: String,
     pub data_type: DataType,
@@ -75,6 +85,174 @@ pub struct ImportContext {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FunctionContext {
     pub compiler_flags: Vec<CompilerFlag>,
+    /// Return type the function is declared with.
+    pub return_ty: Option<DataType>,
+}
+
+/// Whether the function the code is in has the compiler flag, allowing the behavior
+/// that is reported otherwise.
+pub fn is_allowed_by_flag(contexts: &[Context], flag: &CompilerFlag) -> bool {
+    contexts.iter().any(|context| match context {
+        Context::Function(FunctionContext { compiler_flags, .. }) => compiler_flags.contains(flag),
+        _ => false,
+    })
+}
+
+/// Report a cast between incompatible types, unless the function allows it.
+pub fn check_cast(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (from, to): (&DataType, &DataType),
+    span: Span,
+    contexts: &[Context],
+    generics_map: &GenericsMap,
+) {
+    if is_allowed_by_flag(contexts, &CompilerFlag::AllowAbsurdCast)
+        || !types::is_absurd_cast(from, to, generics_map)
+    {
+        return;
+    }
+
+    files.report_diagnostic(
+        file,
+        DiagnosticKind::AbsurdCast,
+        &format!(
+            "Casting a value of type {} to {} is not recommended, use `#[{}]` to allow it",
+            from.to_string(generics_map),
+            to.to_string(generics_map),
+            CompilerFlag::AllowAbsurdCast
+        ),
+        span,
+    );
+}
+
+/// Report `trust` and `unsafe` modifiers on a call to a function that can't fail.
+pub fn check_unnecessary_trust(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    return_ty: &DataType,
+    modifiers: &[Spanned<CommandModifier>],
+    generics_map: &GenericsMap,
+) {
+    if types::can_fail(return_ty, generics_map) != Some(false) {
+        return;
+    }
+
+    modifiers.iter().for_each(|(modifier, span)| {
+        let name = match modifier {
+            CommandModifier::Trust => "trust",
+            CommandModifier::Unsafe => "unsafe",
+            CommandModifier::Silent => return,
+        };
+
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::UnnecessaryTrust,
+            &format!("Function call can't fail, `{}` has no effect", name),
+            *span,
+        );
+    });
+}
+
+/// Report `?` in a function declared with a return type that can't fail. Functions
+/// without a declared return type are inferred as failable instead.
+pub fn check_propagation(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    span: Span,
+    contexts: &[Context],
+    generics_map: &GenericsMap,
+) {
+    let return_ty = contexts.iter().rev().find_map(|context| match context {
+        Context::Function(FunctionContext { return_ty, .. }) => Some(return_ty),
+        _ => None,
+    });
+
+    if let Some(Some(return_ty)) = return_ty {
+        if types::can_fail(return_ty, generics_map) == Some(false) {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::MisplacedPropagation,
+                &format!(
+                    "Failure can't be propagated from a function returning {}, declare its return type as failable",
+                    return_ty.to_string(generics_map)
+                ),
+                span,
+            );
+        }
+    }
+}
+
+/// Constrain the generic type of a function argument with the type given at a call,
+/// reporting a conflict with the types given at earlier calls.
+pub fn constrain_generic_argument(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (function, argument): (&str, &str),
+    id: usize,
+    (ty, span): Spanned<DataType>,
+    generics_map: &GenericsMap,
+) {
+    let conflicts = generics_map.constrain_at_call(id, (ty.clone(), span));
+
+    if conflicts.is_empty() {
+        return;
+    }
+
+    files.report_diagnostic_with_related(
+        file,
+        DiagnosticKind::Analysis,
+        &format!(
+            "Argument `{}` of `{}` is given type `{}`, conflicting with earlier calls",
+            argument,
+            function,
+            ty.to_string(generics_map)
+        ),
+        span,
+        conflicts
+            .iter()
+            .map(|(ty, span)| {
+                (
+                    format!(
+                        "`{}` is given type `{}` here",
+                        argument,
+                        ty.to_string(generics_map)
+                    ),
+                    *span,
+                )
+            })
+            .collect(),
+    );
+}
+
+/// Report a definition shadowing a symbol imported into the file.
+///
+/// Symbols of the builtin file, imported into every file, can be shadowed freely.
+pub fn check_shadowed_import(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (name, span): (&str, Span),
+) {
+    let definition = files.symbol_table.get(file).and_then(|symbol_table| {
+        symbol_table
+            .definitions
+            .get(name)
+            .and_then(|definitions| definitions.get(&span.start).cloned())
+    });
+
+    match definition {
+        Some(definition)
+            if definition.file != *file && !is_builtin_file(&files.lookup(&definition.file.0)) =>
+        {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::ShadowedImport,
+                &format!("`{}` shadows an imported symbol", name),
+                span,
+            );
+        }
+        _ => {}
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -152,12 +330,21 @@ pub struct SymbolLocation {
 /// `references` map contains references to each symbol.
 ///
 /// `symbols` range map contains information about symbols in the document.
+///
+/// `narrowed_types` map contains the types variables are narrowed to by `is` checks,
+/// along with the definition of the narrowed variable. RangeMap is used to store the
+/// scope the condition holds in.
+///
+/// `shell_values` map contains what is known about the values of variables defined in
+/// the document, keyed by the start of their definition.
 #[derive(Clone, Debug)]
 pub struct SymbolTable {
     pub symbols: RangeInclusiveMap<usize, SymbolInfo>,
     pub definitions: HashMap<String, RangeInclusiveMap<usize, SymbolLocation>>,
     pub references: HashMap<String, Vec<SymbolLocation>>,
     pub public_definitions: HashMap<String, SymbolLocation>,
+    pub narrowed_types: HashMap<String, RangeInclusiveMap<usize, (SymbolLocation, DataType)>>,
+    pub shell_values: HashMap<usize, ShellValue>,
 }
 
 impl Default for SymbolTable {
@@ -167,6 +354,73 @@ impl Default for SymbolTable {
             definitions: HashMap::new(),
             references: HashMap::new(),
             public_definitions: HashMap::new(),
+            narrowed_types: HashMap::new(),
+            shell_values: HashMap::new(),
+        }
+    }
+}
+
+impl SymbolTable {
+    /// Type the variable is narrowed to at the position, if the definition it was
+    /// narrowed for is still the one in scope.
+    pub fn narrowed_type(&self, symbol: &str, position: usize) -> Option<DataType> {
+        let definition = self.definitions.get(symbol)?.get(&position)?;
+
+        match self.narrowed_types.get(symbol)?.get(&position) {
+            Some((narrowed_definition, ty)) if narrowed_definition == definition => {
+                Some(ty.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Narrow the type of the variable referenced at the position within the scope,
+/// where it's known to be of the given type.
+pub fn narrow_variable_type(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    symbol: &str,
+    position: usize,
+    data_type: DataType,
+    scope: RangeInclusive<usize>,
+) {
+    if scope.is_empty() {
+        return;
+    }
+
+    let mut symbol_table = match files.symbol_table.get_mut(file) {
+        Some(symbol_table) => symbol_table,
+        None => return,
+    };
+
+    let definition = match symbol_table
+        .definitions
+        .get(symbol)
+        .and_then(|definitions| definitions.get(&position))
+    {
+        Some(definition) => definition.clone(),
+        None => return,
+    };
+
+    symbol_table
+        .narrowed_types
+        .entry(symbol.to_string())
+        .or_default()
+        .insert(scope, (definition, data_type));
+}
+
+/// Stop narrowing the type of the variable from the position on, as it was assigned
+/// a new value.
+pub fn clear_narrowed_type(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    symbol: &str,
+    position: usize,
+) {
+    if let Some(mut symbol_table) = files.symbol_table.get_mut(file) {
+        if let Some(narrowed_types) = symbol_table.narrowed_types.get_mut(symbol) {
+            narrowed_types.remove(position..=usize::MAX);
         }
     }
 }
@@ -313,7 +567,10 @@ pub fn insert_symbol_reference(
 
             // If generic is already inferred, use the inferred type
             // if not, use generic as a pointer to the inferred type in the map
-            let data_type = match symbol_info.data_type {
+            let data_type = match current_file_symbol_table
+                .narrowed_type(symbol, reference_location.start)
+                .unwrap_or(symbol_info.data_type)
+            {
                 DataType::Generic(id) if scoped_generics.is_inferred(id) => {
                     scoped_generics.get_recursive(id)
                 }
@@ -432,6 +689,23 @@ pub fn insert_symbol_reference(
     symbol_references.push(reference_location.clone());
 }
 
+/// Type of the variable at the position, narrowed by the `is` checks it's in.
+pub fn get_variable_type(
+    files: &Files,
+    symbol: &str,
+    file: &(FileId, FileVersion),
+    position: usize,
+) -> Option<DataType> {
+    let data_type = get_symbol_definition_info(files, symbol, file, position)?.data_type;
+
+    let narrowed_type = files
+        .symbol_table
+        .get(file)
+        .and_then(|symbol_table| symbol_table.narrowed_type(symbol, position));
+
+    Some(narrowed_type.unwrap_or(data_type))
+}
+
 #[tracing::instrument(skip_all)]
 pub fn get_symbol_definition_info(
     files: &Files,
@@ -481,21 +755,46 @@ pub fn get_symbol_definition_info(
 #[tracing::instrument(skip_all)]
 pub async fn map_import_path(uri: &Url, path: &str, backend: &Backend) -> Url {
     if path.starts_with("std/") || path == "std" || path == "builtin" {
-        match backend.amber_version {
+        match backend.amber_version_for(uri) {
             AmberVersion::Alpha034 if path == "std" => {
-                if let Some(url) = resolve(backend, "std/main".to_string()).await {
+                if let Some(url) = resolve(backend, uri, "std/main".to_string()).await {
                     return url;
                 }
             }
             _ => {
-                if let Some(url) = resolve(backend, path.to_string()).await {
+                if let Some(url) = resolve(backend, uri, path.to_string()).await {
                     return url;
                 }
             }
         }
     }
 
-    let path = uri.to_file_path().unwrap().parent().unwrap().join(path);
+    let file_path = match uri.to_file_path() {
+        Ok(file_path) => file_path.parent().unwrap().join(path),
+        // Relative imports of stdlib documents stay in the stdlib
+        Err(_) => return uri.join(path).unwrap(),
+    };
+
+    let import_paths = backend.settings_for(uri).import_paths;
+
+    if !import_paths.is_empty() && !backend.files.fs.exists(&file_path).await {
+        for import_path in import_paths {
+            // Relative import paths outside of a workspace folder can't be resolved
+            if import_path.is_relative() {
+                warn!("Skipping relative import path {}", import_path.display());
+                continue;
+            }
+
+            let import_file_path = import_path.join(path);
+
+            if backend.files.fs.exists(&import_file_path).await {
+                match Url::from_file_path(&import_file_path) {
+                    Ok(url) => return url,
+                    Err(_) => warn!("Invalid import path {}", import_file_path.display()),
+                }
+            }
+        }
+    }
 
-    Url::from_file_path(path).unwrap()
+    Url::from_file_path(file_path).unwrap()
 }
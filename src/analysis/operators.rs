@@ -0,0 +1,248 @@
+use crate::{
+    backend::AmberVersion,
+    files::{FileVersion, Files},
+    grammar::Span,
+    paths::FileId,
+};
+
+use super::types::{make_union_type, matches_type, DataType, GenericsMap};
+
+/// Binary operators, including indexing an array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Neq,
+    And,
+    Or,
+    Range,
+    Index,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Eq => "==",
+            Operator::Neq => "!=",
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Range => "..",
+            Operator::Index => "[]",
+        }
+    }
+}
+
+/// Types of the operands an operator can be applied to, with the type of the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorRule {
+    pub left: DataType,
+    pub right: DataType,
+    pub result: DataType,
+}
+
+fn rule(left: DataType, right: DataType, result: DataType) -> OperatorRule {
+    OperatorRule {
+        left,
+        right,
+        result,
+    }
+}
+
+fn array(ty: DataType) -> DataType {
+    DataType::Array(Box::new(ty))
+}
+
+/// Types arrays can hold.
+fn element_types() -> Vec<DataType> {
+    vec![DataType::Number, DataType::Text, DataType::Boolean]
+}
+
+/// Rules of an operator applied to two operands of the same type.
+fn same_type(types: Vec<DataType>, result: Option<DataType>) -> Vec<OperatorRule> {
+    types
+        .into_iter()
+        .map(|ty| rule(ty.clone(), ty.clone(), result.clone().unwrap_or(ty)))
+        .collect()
+}
+
+/// Typing rules of the operator in the Amber version.
+///
+/// Indexing arrays with a range of numbers is supported since alpha040, as are
+/// comparisons of texts and arrays.
+pub fn operator_rules(version: &AmberVersion, operator: Operator) -> Vec<OperatorRule> {
+    let is_alpha040 = *version == AmberVersion::Alpha040;
+
+    match operator {
+        Operator::Add => same_type(
+            [DataType::Number, DataType::Text]
+                .into_iter()
+                .chain(element_types().into_iter().map(array))
+                .collect(),
+            None,
+        ),
+        Operator::Subtract | Operator::Multiply | Operator::Divide | Operator::Modulo => {
+            same_type(vec![DataType::Number], None)
+        }
+        Operator::Gt | Operator::Ge | Operator::Lt | Operator::Le if is_alpha040 => same_type(
+            vec![DataType::Number, DataType::Text],
+            Some(DataType::Boolean),
+        ),
+        Operator::Gt | Operator::Ge | Operator::Lt | Operator::Le => {
+            same_type(vec![DataType::Number], Some(DataType::Boolean))
+        }
+        Operator::Eq | Operator::Neq => same_type(
+            element_types()
+                .into_iter()
+                .chain([DataType::Null])
+                .chain(
+                    element_types()
+                        .into_iter()
+                        .map(array)
+                        .filter(|_| is_alpha040),
+                )
+                .collect(),
+            Some(DataType::Boolean),
+        ),
+        Operator::And | Operator::Or => same_type(vec![DataType::Boolean], None),
+        Operator::Range => vec![rule(
+            DataType::Number,
+            DataType::Number,
+            array(DataType::Number),
+        )],
+        Operator::Index => element_types()
+            .into_iter()
+            .flat_map(|ty| {
+                let mut rules = vec![rule(array(ty.clone()), DataType::Number, ty.clone())];
+
+                if is_alpha040 {
+                    rules.push(rule(array(ty.clone()), array(DataType::Number), array(ty)));
+                }
+
+                rules
+            })
+            .collect(),
+    }
+}
+
+/// Type of the operator applied to operands of the given types, `None` if none of its
+/// rules applies to them.
+///
+/// Generic operands are constrained with the types the applicable rules accept, except
+/// for equality checks, which can compare a generic value with a value of any type.
+pub fn apply_operator(
+    version: &AmberVersion,
+    operator: Operator,
+    (left, right): (&DataType, &DataType),
+    generics_map: &GenericsMap,
+) -> Option<DataType> {
+    let applicable = operator_rules(version, operator)
+        .into_iter()
+        .filter(|rule| {
+            matches_type(&rule.left, left, generics_map)
+                && matches_type(&rule.right, right, generics_map)
+        })
+        .collect::<Vec<_>>();
+
+    if applicable.is_empty() {
+        return None;
+    }
+
+    if matches!(operator, Operator::Eq | Operator::Neq) {
+        return Some(DataType::Boolean);
+    }
+
+    if let DataType::Generic(id) = left {
+        generics_map.constrain_generic_type(
+            *id,
+            make_union_type(applicable.iter().map(|rule| rule.left.clone()).collect()),
+        );
+    }
+
+    if let DataType::Generic(id) = right {
+        // Both operands have the same type, the right one follows the left one
+        let constraint = match applicable.iter().all(|rule| rule.left == rule.right) {
+            true => left.clone(),
+            false => make_union_type(applicable.iter().map(|rule| rule.right.clone()).collect()),
+        };
+
+        generics_map.constrain_generic_type(*id, constraint);
+    }
+
+    // Keep the type of the operand, which can be generic
+    if applicable.iter().all(|rule| rule.result == rule.left) {
+        return Some(left.clone());
+    }
+
+    Some(make_union_type(
+        applicable.into_iter().map(|rule| rule.result).collect(),
+    ))
+}
+
+/// Check the operator can be applied to the operands, returning the type of the result.
+pub fn check_operator(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    version: &AmberVersion,
+    operator: Operator,
+    (left, right): (&DataType, &DataType),
+    span: Span,
+    generics_map: &GenericsMap,
+) -> DataType {
+    let is_error = |ty: &DataType| matches!(generics_map.deref_type(ty), DataType::Error);
+
+    let result = match is_error(left) || is_error(right) {
+        true => None,
+        false => apply_operator(version, operator, (left, right), generics_map),
+    };
+
+    match result {
+        Some(ty) => ty,
+        None => {
+            if !is_error(left) && !is_error(right) {
+                let (left, right) = (left.to_string(generics_map), right.to_string(generics_map));
+
+                files.report_error(
+                    file,
+                    &match operator {
+                        Operator::Index => format!("Cannot index {} with {}", left, right),
+                        operator => format!(
+                            "Cannot apply `{}` to {} and {}",
+                            operator.symbol(),
+                            left,
+                            right
+                        ),
+                    },
+                    span,
+                );
+            }
+
+            // The result of most operators has the same type whatever the operands are
+            let results = operator_rules(version, operator)
+                .into_iter()
+                .map(|rule| rule.result)
+                .collect::<Vec<_>>();
+
+            match results.first() {
+                Some(result) if results.iter().all(|ty| ty == result) => result.clone(),
+                _ => DataType::Any,
+            }
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{
+    diagnostics::DiagnosticKind,
+    files::{FileVersion, Files},
+    grammar::{
+        shell::{ShellScript, SimpleCommand},
+        CommandModifier, Span, Spanned,
+    },
+    paths::FileId,
+};
+
+use super::{BlockContext, Context, SymbolTable};
+
+/// Commands deleting or overwriting the files they're given.
+const DESTRUCTIVE_COMMANDS: &[&str] = &["rm", "rmdir", "shred", "unlink", "truncate", "dd"];
+
+/// Builtins and keywords of Bash.
+pub const SHELL_BUILTINS: &[&str] = &[
+    ".",
+    ":",
+    "[",
+    "[[",
+    "alias",
+    "bg",
+    "bind",
+    "break",
+    "builtin",
+    "caller",
+    "case",
+    "cd",
+    "command",
+    "compgen",
+    "complete",
+    "compopt",
+    "continue",
+    "coproc",
+    "declare",
+    "dirs",
+    "disown",
+    "do",
+    "done",
+    "echo",
+    "elif",
+    "else",
+    "enable",
+    "esac",
+    "eval",
+    "exec",
+    "exit",
+    "export",
+    "false",
+    "fc",
+    "fg",
+    "fi",
+    "for",
+    "function",
+    "getopts",
+    "hash",
+    "help",
+    "history",
+    "if",
+    "jobs",
+    "kill",
+    "let",
+    "local",
+    "logout",
+    "mapfile",
+    "popd",
+    "printf",
+    "pushd",
+    "pwd",
+    "read",
+    "readarray",
+    "readonly",
+    "return",
+    "select",
+    "set",
+    "shift",
+    "shopt",
+    "source",
+    "suspend",
+    "test",
+    "then",
+    "time",
+    "times",
+    "trap",
+    "true",
+    "type",
+    "typeset",
+    "ulimit",
+    "umask",
+    "unalias",
+    "unset",
+    "until",
+    "wait",
+    "while",
+];
+
+/// What is known about a value interpolated into a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShellValue {
+    /// The value comes from the arguments the script is run with.
+    pub is_tainted: bool,
+    /// The value can't be an empty text.
+    pub is_non_empty: bool,
+}
+
+impl ShellValue {
+    pub const TAINTED: ShellValue = ShellValue {
+        is_tainted: true,
+        is_non_empty: false,
+    };
+
+    pub const NON_EMPTY: ShellValue = ShellValue {
+        is_tainted: false,
+        is_non_empty: true,
+    };
+
+    /// Value built by joining both values together.
+    pub fn join(self, other: ShellValue) -> ShellValue {
+        ShellValue {
+            is_tainted: self.is_tainted || other.is_tainted,
+            is_non_empty: self.is_non_empty || other.is_non_empty,
+        }
+    }
+
+    /// Value that can be either of both values.
+    pub fn either(self, other: ShellValue) -> ShellValue {
+        ShellValue {
+            is_tainted: self.is_tainted || other.is_tainted,
+            is_non_empty: self.is_non_empty && other.is_non_empty,
+        }
+    }
+
+    /// Value derived from this one, that can be empty even if this one isn't.
+    pub fn derived(self) -> ShellValue {
+        ShellValue {
+            is_tainted: self.is_tainted,
+            is_non_empty: false,
+        }
+    }
+}
+
+/// Part of a command literal, with the values of interpolated expressions already known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandPart {
+    Text(String),
+    Value(ShellValue),
+}
+
+/// What is known about the value of the variable referenced at the position.
+pub fn variable_shell_value(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    name: &str,
+    position: usize,
+) -> ShellValue {
+    let symbol_table = match files.symbol_table.get(file) {
+        Some(symbol_table) => symbol_table,
+        None => return ShellValue::default(),
+    };
+
+    symbol_table
+        .definitions
+        .get(name)
+        .and_then(|definitions| definitions.get(&position))
+        .filter(|definition| definition.file == *file)
+        .and_then(|definition| symbol_table.shell_values.get(&definition.start))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Record the value a variable is defined with.
+pub fn define_shell_value(
+    symbol_table: &mut SymbolTable,
+    definition_start: usize,
+    value: ShellValue,
+) {
+    if value == ShellValue::default() {
+        symbol_table.shell_values.remove(&definition_start);
+    } else {
+        symbol_table.shell_values.insert(definition_start, value);
+    }
+}
+
+/// Record a value assigned to the variable referenced at the position.
+///
+/// The variable keeps any of the values assigned to it, it's tainted if any of them is
+/// and known to be non-empty only if all of them are.
+pub fn assign_shell_value(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    name: &str,
+    position: usize,
+    value: ShellValue,
+) {
+    let mut symbol_table = match files.symbol_table.get_mut(file) {
+        Some(symbol_table) => symbol_table,
+        None => return,
+    };
+
+    let definition_start = match symbol_table
+        .definitions
+        .get(name)
+        .and_then(|definitions| definitions.get(&position))
+    {
+        Some(definition) if definition.file == *file => definition.start,
+        _ => return,
+    };
+
+    let current = symbol_table
+        .shell_values
+        .get(&definition_start)
+        .copied()
+        .unwrap_or_default();
+
+    symbol_table
+        .shell_values
+        .insert(definition_start, current.either(value));
+}
+
+/// Split the command into words, using the gaps between its parts.
+///
+/// The `$` delimiters of the command are left out.
+pub fn command_words(parts: &[Spanned<CommandPart>]) -> Vec<Vec<Spanned<CommandPart>>> {
+    let inner = match parts {
+        [_, inner @ .., _] => inner,
+        _ => return vec![],
+    };
+
+    let mut words: Vec<Vec<Spanned<CommandPart>>> = vec![];
+    let mut previous_end = None;
+
+    for (part, span) in inner.iter() {
+        let is_new_word = previous_end.is_none_or(|end| span.start > end);
+        previous_end = Some(span.end);
+
+        match part {
+            CommandPart::Text(text) if text.trim().is_empty() => {
+                previous_end = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        match words.last_mut() {
+            Some(word) if !is_new_word => word.push((part.clone(), *span)),
+            _ => words.push(vec![(part.clone(), *span)]),
+        }
+    }
+
+    words
+}
+
+/// Text of a word without interpolated values.
+pub fn literal_word(word: &[Spanned<CommandPart>]) -> Option<String> {
+    word.iter()
+        .map(|(part, _)| match part {
+            CommandPart::Text(text) => Some(text.as_str()),
+            CommandPart::Value(_) => None,
+        })
+        .collect()
+}
+
+/// Name of the program the command runs, without its directory, [`None`] if it's
+/// interpolated.
+pub fn executable_name(command: &SimpleCommand) -> Option<String> {
+    let name = command.executable()?.literal()?;
+
+    Some(name.rsplit('/').next().unwrap_or(&name).to_string())
+}
+
+/// Whether the name is a plain executable name, looked up on `PATH` by the shell.
+pub fn is_plain_command_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+}
+
+/// Whether the name is a builtin or keyword of Bash, which isn't looked up on `PATH`.
+pub fn is_shell_builtin(name: &str) -> bool {
+    SHELL_BUILTINS.contains(&name)
+}
+
+/// Record the shell script of a command literal, with the span of the whole command
+/// expression.
+pub fn register_shell_script(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    script: ShellScript,
+    exp_span: Span,
+) {
+    files
+        .shell_scripts
+        .entry(*file)
+        .or_default()
+        .push((script, exp_span));
+}
+
+/// Modifiers the command runs with, including modifiers of the blocks it's in.
+///
+/// Modifiers of the blocks are spanned with the given span of the command.
+fn command_modifiers(
+    modifiers: &[Spanned<CommandModifier>],
+    contexts: &[Context],
+    span: Span,
+) -> Vec<Spanned<CommandModifier>> {
+    modifiers
+        .iter()
+        .cloned()
+        .chain(contexts.iter().flat_map(|context| {
+            match context {
+                Context::Block(BlockContext { modifiers }) => modifiers
+                    .iter()
+                    .map(|modifier| (modifier.clone(), span))
+                    .collect(),
+                _ => vec![],
+            }
+        }))
+        .collect()
+}
+
+fn modifier_keyword(modifier: &CommandModifier) -> &'static str {
+    match modifier {
+        CommandModifier::Unsafe => "unsafe",
+        CommandModifier::Trust => "trust",
+        CommandModifier::Silent => "silent",
+    }
+}
+
+/// Check a command literal is safe to run.
+///
+/// Reports values from the script arguments interpolated into the command, and
+/// destructive commands run on values that may be empty or with their failures and
+/// output hidden.
+pub fn check_command(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    parts: &[Spanned<CommandPart>],
+    script: &ShellScript,
+    modifiers: &[Spanned<CommandModifier>],
+    contexts: &[Context],
+) {
+    for (part, part_span) in parts.iter() {
+        if let CommandPart::Value(value) = part {
+            if value.is_tainted {
+                files.report_diagnostic(
+                    file,
+                    DiagnosticKind::TaintedCommand,
+                    "Command is given a value taken from the script arguments",
+                    *part_span,
+                );
+            }
+        }
+    }
+
+    let destructive_commands = script
+        .simple_commands()
+        .into_iter()
+        .filter_map(|(command, span)| match executable_name(command) {
+            Some(name) if DESTRUCTIVE_COMMANDS.contains(&name.as_str()) => Some((name, span)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let name = match destructive_commands.first() {
+        Some((name, _)) => name.clone(),
+        None => return,
+    };
+
+    for (part, part_span) in parts.iter() {
+        if !matches!(part, CommandPart::Value(value) if !value.is_non_empty) {
+            continue;
+        }
+
+        let command_name = destructive_commands
+            .iter()
+            .find(|(_, span)| span.start <= part_span.start && part_span.end <= span.end);
+
+        if let Some((command_name, _)) = command_name {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::EmptyDestructiveArgument,
+                &format!(
+                    "Value given to `{}` may be empty, check it before running the command",
+                    command_name
+                ),
+                *part_span,
+            );
+        }
+    }
+
+    let span = match (parts.first(), parts.last()) {
+        (Some((_, first)), Some((_, last))) => Span::new(first.start, last.end),
+        _ => return,
+    };
+
+    let mut reported = vec![];
+
+    for (modifier, modifier_span) in command_modifiers(modifiers, contexts, span) {
+        if reported.contains(&modifier) {
+            continue;
+        }
+
+        let message = match modifier {
+            CommandModifier::Silent => {
+                format!("`{}` is run with `silent`, hiding what it removes", name)
+            }
+            CommandModifier::Unsafe | CommandModifier::Trust => format!(
+                "`{}` is run with `{}`, ignoring its failure",
+                name,
+                modifier_keyword(&modifier)
+            ),
+        };
+
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::SilencedDestructiveCommand,
+            &message,
+            modifier_span,
+        );
+        reported.push(modifier);
+    }
+}
+
+/// Report a `cd` or `mv` target taken from the script arguments.
+pub fn check_path_argument(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    keyword: &str,
+    value: ShellValue,
+    span: Span,
+) {
+    if value.is_tainted {
+        files.report_diagnostic(
+            file,
+            DiagnosticKind::TaintedCommand,
+            &format!(
+                "`{}` is given a path taken from the script arguments",
+                keyword
+            ),
+            span,
+        );
+    }
+}
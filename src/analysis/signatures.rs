@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    diagnostics::DiagnosticKind,
+    files::{FileVersion, Files},
+    grammar::{Span, Spanned},
+    paths::FileId,
+};
+
+use super::{
+    insert_symbol_definition,
+    types::{make_union_type, DataType, GenericsMap},
+    FunctionArgument, FunctionSymbol, SymbolInfo, SymbolType,
+};
+
+/// Signature of a function defined in the file, known before its body is analyzed.
+#[derive(Debug, Clone)]
+pub struct FunctionSignature {
+    pub name: Spanned<String>,
+    pub arguments: Vec<Spanned<FunctionArgument>>,
+    pub return_ty: Option<DataType>,
+    /// Span of the whole function definition.
+    pub span: Span,
+}
+
+/// Report functions defined more than once in the file, and arguments repeated in a
+/// function signature.
+///
+/// Returns the start of the name of each repeated function definition, calls resolve
+/// to the first definition.
+pub fn check_duplicate_signatures(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    signatures: &[FunctionSignature],
+) -> HashSet<usize> {
+    let mut first_definitions = HashMap::new();
+    let mut duplicates = HashSet::new();
+
+    for signature in signatures.iter() {
+        let (name, name_span) = &signature.name;
+
+        if name.is_empty() {
+            continue;
+        }
+
+        match first_definitions.get(name) {
+            Some(first_span) => {
+                files.report_diagnostic_with_related(
+                    file,
+                    DiagnosticKind::Analysis,
+                    &format!("Function `{}` is already defined", name),
+                    *name_span,
+                    vec![(format!("`{}` is first defined here", name), *first_span)],
+                );
+
+                duplicates.insert(name_span.start);
+            }
+            None => {
+                first_definitions.insert(name, *name_span);
+            }
+        }
+
+        let mut argument_names = HashSet::new();
+
+        for (argument, span) in signature.arguments.iter() {
+            if !argument_names.insert(&argument.name) {
+                files.report_error(
+                    file,
+                    &format!("Argument `{}` is already defined", argument.name),
+                    *span,
+                );
+            }
+        }
+    }
+
+    duplicates
+}
+
+/// Register the signatures of all functions in the file before any body is analyzed.
+///
+/// The compiler parses the body of a function only once the function is called, so
+/// function bodies can call the function itself and functions defined after it.
+/// Code outside of function bodies can only call functions defined before it, which
+/// is covered by the definitions inserted once each function is analyzed.
+///
+/// Functions without a declared return type return a placeholder generic type,
+/// until it's solved with [`ReturnTypes::solve`].
+pub fn register_signatures(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    signatures: &[FunctionSignature],
+) -> ReturnTypes {
+    let mut return_types = ReturnTypes::default();
+
+    let mut symbol_table = files
+        .symbol_table
+        .entry(*file)
+        .or_insert_with(Default::default);
+
+    for signature in signatures.iter() {
+        let (name, name_span) = &signature.name;
+
+        let data_type = match signature.return_ty {
+            Some(ref ty) => ty.clone(),
+            None => {
+                let placeholder = files.generic_types.new_generic_id();
+
+                files
+                    .generic_types
+                    .constrain_generic_type(placeholder, DataType::Any);
+                return_types
+                    .placeholders
+                    .insert(name_span.start, placeholder);
+
+                DataType::Generic(placeholder)
+            }
+        };
+
+        let symbol_info = SymbolInfo {
+            name: name.clone(),
+            symbol_type: SymbolType::Function(FunctionSymbol {
+                arguments: signature.arguments.clone(),
+                is_public: false,
+                compiler_flags: vec![],
+                docs: None,
+            }),
+            data_type,
+            is_definition: true,
+            undefined: false,
+            span: *name_span,
+            contexts: vec![],
+        };
+
+        for body in signatures.iter() {
+            insert_symbol_definition(
+                &mut symbol_table,
+                &symbol_info,
+                *file,
+                body.name.1.end..=body.span.end,
+                false,
+            );
+        }
+    }
+
+    return_types
+}
+
+/// Return types of the functions in a file without a declared return type.
+///
+/// Each of them has a placeholder generic type, which recursive calls return while
+/// the body is analyzed.
+#[derive(Debug, Default)]
+pub struct ReturnTypes {
+    /// Placeholder of each function, by the start of the function name.
+    placeholders: HashMap<usize, usize>,
+    /// Return types of the functions already analyzed, by their placeholder.
+    solved: HashMap<usize, DataType>,
+}
+
+impl ReturnTypes {
+    /// Solve the return type of the function from the types its body returns.
+    ///
+    /// A recursive call returns one of the values returned elsewhere in the function,
+    /// so the least solution is the inferred type without the placeholder of the
+    /// function. Placeholders of functions analyzed before are replaced with their
+    /// solutions, which makes mutually recursive functions converge to a fixed point
+    /// once the last of them is solved.
+    pub fn solve(
+        &mut self,
+        name_start: usize,
+        inferred_ty: DataType,
+        generics_map: &GenericsMap,
+    ) -> DataType {
+        let placeholder = match self.placeholders.get(&name_start) {
+            Some(placeholder) => *placeholder,
+            None => return inferred_ty,
+        };
+
+        let ty = without_generic(&self.resolve(&inferred_ty), placeholder);
+
+        self.solved.insert(placeholder, ty.clone());
+        generics_map.constrain_generic_type(placeholder, ty.clone());
+        generics_map.mark_as_inferred(placeholder);
+
+        ty
+    }
+
+    /// Replace placeholders of solved functions with their return types.
+    pub fn resolve(&self, ty: &DataType) -> DataType {
+        match ty {
+            DataType::Generic(id) => match self.solved.get(id) {
+                Some(ty) => self.resolve(ty),
+                None => ty.clone(),
+            },
+            DataType::Union(types) => {
+                make_union_type(types.iter().map(|ty| self.resolve(ty)).collect())
+            }
+            DataType::Array(ty) => DataType::Array(Box::new(self.resolve(ty))),
+            DataType::Failable(ty) => match self.resolve(ty) {
+                DataType::Failable(ty) => DataType::Failable(ty),
+                ty => DataType::Failable(Box::new(ty)),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Whether the type depends on the return type of a function not analyzed yet.
+    pub fn is_pending(&self, ty: &DataType) -> bool {
+        match ty {
+            DataType::Generic(id) => {
+                self.placeholders
+                    .values()
+                    .any(|placeholder| placeholder == id)
+                    && !self.solved.contains_key(id)
+            }
+            DataType::Union(types) => types.iter().any(|ty| self.is_pending(ty)),
+            DataType::Array(ty) | DataType::Failable(ty) => self.is_pending(ty),
+            _ => false,
+        }
+    }
+
+    /// Whether the type holds the placeholder of any function in the file.
+    fn has_placeholder(&self, ty: &DataType) -> bool {
+        match ty {
+            DataType::Generic(id) => self
+                .placeholders
+                .values()
+                .any(|placeholder| placeholder == id),
+            DataType::Union(types) => types.iter().any(|ty| self.has_placeholder(ty)),
+            DataType::Array(ty) | DataType::Failable(ty) => self.has_placeholder(ty),
+            _ => false,
+        }
+    }
+
+    /// Replace the placeholders left in the types of the symbols, like return types of
+    /// mutually recursive functions, once all functions of the file are analyzed.
+    ///
+    /// The placeholders are dropped from the generic types afterwards, they're only
+    /// needed while the file is analyzed.
+    pub fn finish(&self, files: &Files, file: &(FileId, FileVersion)) {
+        if let Some(mut symbol_table) = files.symbol_table.get_mut(file) {
+            let resolved_symbols = symbol_table
+                .symbols
+                .iter()
+                .filter(|(_, symbol_info)| self.has_placeholder(&symbol_info.data_type))
+                .map(|(range, symbol_info)| {
+                    let data_type = self
+                        .placeholders
+                        .values()
+                        .fold(self.resolve(&symbol_info.data_type), |ty, placeholder| {
+                            without_generic(&ty, *placeholder)
+                        });
+
+                    (
+                        range.clone(),
+                        SymbolInfo {
+                            data_type,
+                            ..symbol_info.clone()
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            for (range, symbol_info) in resolved_symbols {
+                symbol_table.symbols.insert(range, symbol_info);
+            }
+        }
+
+        for placeholder in self.placeholders.values() {
+            files.generic_types.remove(*placeholder);
+        }
+    }
+}
+
+fn without_generic(ty: &DataType, id: usize) -> DataType {
+    match ty {
+        DataType::Generic(generic_id) if *generic_id == id => DataType::Any,
+        DataType::Union(types) => make_union_type(
+            types
+                .iter()
+                .filter(|ty| **ty != DataType::Generic(id))
+                .map(|ty| without_generic(ty, id))
+                .collect(),
+        ),
+        DataType::Array(ty) => DataType::Array(Box::new(without_generic(ty, id))),
+        DataType::Failable(ty) => DataType::Failable(Box::new(without_generic(ty, id))),
+        ty => ty.clone(),
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    backend::AmberVersion,
+    diagnostics::{DiagnosticKind, QuickFix},
+    files::{FileVersion, Files},
+    grammar::{Span, Spanned},
+    paths::FileId,
+};
+
+use super::{
+    get_symbol_definition_info,
+    shell::{command_words, literal_word, CommandPart},
+    SymbolType,
+};
+
+/// Word of a command pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternWord {
+    /// Any of the given words.
+    Literal(Vec<&'static str>),
+    /// A single interpolated value, which can be quoted.
+    Value,
+}
+
+/// Argument of the stdlib call replacing a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallArgument {
+    /// The interpolated value of the command at the index.
+    Value(usize),
+    /// Amber code given as is.
+    Code(&'static str),
+}
+
+/// A command doing what a function of the standard library already does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdlibAlternative {
+    pub command: Vec<PatternWord>,
+    /// Import path of the module defining the function.
+    pub module: &'static str,
+    pub function: &'static str,
+    pub arguments: Vec<CallArgument>,
+    pub is_failable: bool,
+}
+
+fn literal(words: &[&'static str]) -> PatternWord {
+    PatternWord::Literal(words.to_vec())
+}
+
+fn alternative(
+    command: Vec<PatternWord>,
+    (module, function): (&'static str, &'static str),
+    arguments: Vec<CallArgument>,
+    is_failable: bool,
+) -> StdlibAlternative {
+    StdlibAlternative {
+        command,
+        module,
+        function,
+        arguments,
+        is_failable,
+    }
+}
+
+/// Commands that can be replaced with a function of the standard library of the Amber
+/// version, following the functions in `resources/alpha0xx/std`.
+pub fn stdlib_alternatives(version: &AmberVersion) -> Vec<StdlibAlternative> {
+    use CallArgument::*;
+    use PatternWord::Value as V;
+
+    let (fs, text) = match version {
+        AmberVersion::Alpha034 => ("std", "std"),
+        AmberVersion::Alpha035 | AmberVersion::Alpha040 => ("std/fs", "std/text"),
+    };
+
+    let names = match version {
+        AmberVersion::Alpha034 | AmberVersion::Alpha035 => [
+            "create_dir",
+            "file_exist",
+            "dir_exist",
+            "upper",
+            "lower",
+            "create_symbolic_link",
+        ],
+        AmberVersion::Alpha040 => [
+            "dir_create",
+            "file_exists",
+            "dir_exists",
+            "uppercase",
+            "lowercase",
+            "symlink_create",
+        ],
+    };
+    let [create_dir, file_exist, dir_exist, upper, lower, symlink] = names;
+
+    let test = |flag: &'static str, function: &'static str| {
+        [
+            alternative(
+                vec![literal(&["test"]), literal(&[flag]), V],
+                (fs, function),
+                vec![Value(0)],
+                false,
+            ),
+            alternative(
+                vec![literal(&["["]), literal(&[flag]), V, literal(&["]"])],
+                (fs, function),
+                vec![Value(0)],
+                false,
+            ),
+        ]
+    };
+
+    let tr = |from: &[&'static str], to: &[&'static str], function: &'static str| {
+        alternative(
+            vec![
+                literal(&["echo"]),
+                V,
+                literal(&["|"]),
+                literal(&["tr"]),
+                literal(from),
+                literal(to),
+            ],
+            (text, function),
+            vec![Value(0)],
+            false,
+        )
+    };
+
+    let mut alternatives = vec![
+        alternative(
+            vec![literal(&["mkdir"]), literal(&["-p"]), V],
+            (fs, create_dir),
+            vec![Value(0)],
+            false,
+        ),
+        alternative(
+            vec![literal(&["cat"]), V],
+            (fs, "file_read"),
+            vec![Value(0)],
+            true,
+        ),
+        alternative(
+            vec![literal(&["ln"]), literal(&["-s"]), V, V],
+            (fs, symlink),
+            vec![Value(0), Value(1)],
+            false,
+        ),
+        tr(&["[:lower:]", "a-z"], &["[:upper:]", "A-Z"], upper),
+        tr(&["[:upper:]", "A-Z"], &["[:lower:]", "a-z"], lower),
+    ];
+
+    alternatives.extend(test("-f", file_exist));
+    alternatives.extend(test("-d", dir_exist));
+
+    alternatives.push(match version {
+        AmberVersion::Alpha040 => alternative(
+            vec![literal(&["chmod"]), literal(&["+x"]), V],
+            (fs, "file_chmod"),
+            vec![Value(0), Code("\"+x\"")],
+            false,
+        ),
+        _ => alternative(
+            vec![literal(&["chmod"]), literal(&["+x"]), V],
+            (fs, "make_executable"),
+            vec![Value(0)],
+            false,
+        ),
+    });
+
+    alternatives
+}
+
+/// Span of the interpolated expression the word consists of, without its braces.
+fn value_span(word: &[Spanned<CommandPart>]) -> Option<Span> {
+    let is_quote = |part: &CommandPart| match part {
+        CommandPart::Text(text) => text == "\"" || text == "'",
+        CommandPart::Value(_) => false,
+    };
+
+    let word = match word {
+        [first, inner @ .., last] if is_quote(&first.0) && is_quote(&last.0) => inner,
+        word => word,
+    };
+
+    match word {
+        [(CommandPart::Value(_), span)] if span.end > span.start + 1 => {
+            Some(Span::new(span.start + 1, span.end - 1))
+        }
+        _ => None,
+    }
+}
+
+/// Spans of the interpolated values, if the command matches the pattern.
+fn match_command(parts: &[Spanned<CommandPart>], pattern: &[PatternWord]) -> Option<Vec<Span>> {
+    let words = command_words(parts);
+
+    if words.len() != pattern.len() {
+        return None;
+    }
+
+    let mut values = vec![];
+
+    for (word, pattern_word) in words.iter().zip(pattern) {
+        match pattern_word {
+            PatternWord::Literal(literals) => {
+                let word = literal_word(word)?;
+                let word = word.trim_matches(|c| c == '"' || c == '\'');
+
+                if !literals.contains(&word) {
+                    return None;
+                }
+            }
+            PatternWord::Value => values.push(value_span(word)?),
+        }
+    }
+
+    Some(values)
+}
+
+/// Edit adding an import of the function to the file, placed after its last import.
+fn import_edit(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    (module, function): (&str, &str),
+) -> Option<(Span, String)> {
+    let import = format!("import {{ {} }} from \"{}\"", function, module);
+
+    let last_import_end = files.symbol_table.get(file).and_then(|symbol_table| {
+        symbol_table
+            .symbols
+            .iter()
+            .filter(|(_, symbol_info)| symbol_info.symbol_type == SymbolType::ImportPath)
+            .map(|(span, _)| *span.end())
+            .max()
+    });
+
+    if let Some(end) = last_import_end {
+        return Some((Span::new(end, end), format!("\n{}", import)));
+    }
+
+    // Keep the shebang on the first line
+    let document = files.document_map.get(file)?;
+    let start = match document.chars().take(2).eq("#!".chars()) {
+        true => document.line_to_char(1.min(document.len_lines())),
+        false => 0,
+    };
+
+    Some((Span::new(start, start), format!("{}\n\n", import)))
+}
+
+/// Report a command doing what a function of the standard library does, with a fix
+/// replacing it with a call to the function.
+///
+/// Commands with a failure handler are replaced only with failable functions, keeping
+/// the handler and modifiers of the command.
+pub fn check_stdlib_alternative(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    version: &AmberVersion,
+    parts: &[Spanned<CommandPart>],
+    has_failure_handler: bool,
+    exp_span: Span,
+) {
+    let literal_span = match (parts.first(), parts.last()) {
+        (Some((_, first)), Some((_, last))) => Span::new(first.start, last.end),
+        _ => return,
+    };
+
+    let (alternative, values) =
+        match stdlib_alternatives(version)
+            .into_iter()
+            .find_map(|alternative| {
+                match_command(parts, &alternative.command).map(|values| (alternative, values))
+            }) {
+            Some(found) => found,
+            None => return,
+        };
+
+    let message = format!(
+        "Command can be replaced with `{}` from `{}`",
+        alternative.function, alternative.module
+    );
+
+    let replaced_span = match (alternative.is_failable, has_failure_handler) {
+        (true, _) => literal_span,
+        (false, false) => exp_span,
+        (false, true) => {
+            files.report_diagnostic(
+                file,
+                DiagnosticKind::StdlibAlternative,
+                &message,
+                literal_span,
+            );
+            return;
+        }
+    };
+
+    let document = match files.document_map.get(file) {
+        Some(document) => document.clone(),
+        None => return,
+    };
+
+    let arguments = alternative
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            CallArgument::Value(index) => values
+                .get(*index)
+                .and_then(|span| document.get_slice(span.start..span.end))
+                .map(|code| code.to_string().trim().to_string()),
+            CallArgument::Code(code) => Some(code.to_string()),
+        })
+        .collect::<Option<Vec<_>>>();
+
+    let arguments = match arguments {
+        Some(arguments) => arguments,
+        None => return,
+    };
+
+    let mut edits = vec![(
+        replaced_span,
+        format!("{}({})", alternative.function, arguments.join(", ")),
+    )];
+
+    let is_defined = matches!(
+        get_symbol_definition_info(files, alternative.function, file, exp_span.start),
+        Some(symbol_info) if matches!(symbol_info.symbol_type, SymbolType::Function(_))
+    );
+
+    if !is_defined {
+        edits.extend(import_edit(
+            files,
+            file,
+            (alternative.module, alternative.function),
+        ));
+    }
+
+    files.report_diagnostic_with_fix(
+        file,
+        DiagnosticKind::StdlibAlternative,
+        &message,
+        literal_span,
+        QuickFix {
+            title: format!("Replace with `{}`", alternative.function),
+            edits,
+        },
+    );
+}
@@ -2,13 +2,16 @@ use std::fmt::{self, Display};
 use std::sync::atomic::Ordering::SeqCst;
 use std::{collections::HashSet, sync::atomic::AtomicUsize};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     files::FileVersion,
+    grammar::Spanned,
     paths::FileId,
     utils::{FastDashMap, FastDashSet},
 };
 
-#[derive(PartialEq, Eq, Clone, Hash)]
+#[derive(PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub enum DataType {
     Any,
     Number,
@@ -61,6 +64,16 @@ pub struct GenericsMap {
     map: FastDashMap<usize, DataType>,
     inferred: FastDashSet<usize>,
     generics_per_file: FastDashMap<(FileId, FileVersion), Vec<usize>>,
+    call_sites: FastDashMap<usize, CallSites>,
+}
+
+/// Types a generic function argument was given at the calls analyzed so far.
+#[derive(Debug, Clone)]
+struct CallSites {
+    /// Constraint of the generic type before the first call.
+    base: DataType,
+    /// Type of the argument at each call, with its span.
+    sites: Vec<Spanned<DataType>>,
 }
 
 static ATOMIC_COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -77,6 +90,7 @@ impl GenericsMap {
             map: FastDashMap::default(),
             inferred: FastDashSet::default(),
             generics_per_file: FastDashMap::default(),
+            call_sites: FastDashMap::default(),
         }
     }
 
@@ -90,25 +104,71 @@ impl GenericsMap {
     }
 
     pub fn constrain_generic_type(&self, id: usize, constraint: DataType) {
-        if self.has_ref_to_generic(&constraint, id) {
-            return;
-        }
-
         self.constrain(id, constraint);
     }
 
-    fn constrain(&self, id: usize, constraint: DataType) {
+    /// Returns whether the constraint is compatible with the current one, it's dropped
+    /// otherwise.
+    fn constrain(&self, id: usize, constraint: DataType) -> bool {
+        if self.has_ref_to_generic(&constraint, id) {
+            return true;
+        }
+
         match self.get(id) {
-            DataType::Generic(id) => {
-                self.constrain_generic_type(id, constraint);
-            }
+            DataType::Generic(id) => self.constrain(id, constraint),
             ty if self.is_more_or_equally_specific(&ty, &constraint) => {
                 self.map.insert(id, constraint);
+
+                true
             }
-            _ => {}
+            _ => false,
         }
     }
 
+    /// Type the argument of a generic function is expected to have at a call.
+    ///
+    /// Once the function was called, the generic type holds the types given at the
+    /// calls, so later calls are checked against the constraint it had before them.
+    pub fn argument_type(&self, id: usize) -> DataType {
+        match self.call_sites.get(&id) {
+            Some(call_sites) => call_sites.base.clone(),
+            None => DataType::Generic(id),
+        }
+    }
+
+    /// Constrain the generic type of a function argument with the type given at a call.
+    ///
+    /// Returns the earlier calls given a type conflicting with this one.
+    pub fn constrain_at_call(
+        &self,
+        id: usize,
+        (constraint, span): Spanned<DataType>,
+    ) -> Vec<Spanned<DataType>> {
+        let base = self.get(id);
+        let is_compatible = self.constrain(id, constraint.clone());
+
+        let mut call_sites = self.call_sites.entry(id).or_insert(CallSites {
+            base,
+            sites: vec![],
+        });
+
+        let conflicts = match is_compatible {
+            true => vec![],
+            false => call_sites
+                .sites
+                .iter()
+                .filter(|(ty, _)| {
+                    !matches_type(ty, &constraint, self) && !matches_type(&constraint, ty, self)
+                })
+                .cloned()
+                .collect(),
+        };
+
+        call_sites.sites.push((constraint, span));
+
+        conflicts
+    }
+
     #[inline]
     pub fn mark_as_inferred(&self, id: usize) {
         self.inferred.insert(id);
@@ -170,11 +230,19 @@ impl GenericsMap {
                 for id in ids {
                     self.map.remove(id);
                     self.inferred.remove(id);
+                    self.call_sites.remove(id);
                 }
             });
         self.generics_per_file.remove(&(file_id, file_version));
     }
 
+    /// Drop a generic type only needed while its file is analyzed.
+    pub fn remove(&self, id: usize) {
+        self.map.remove(&id);
+        self.inferred.remove(&id);
+        self.call_sites.remove(&id);
+    }
+
     pub fn insert(&self, file_id: FileId, file_version: FileVersion, generics: Vec<usize>) {
         self.generics_per_file
             .insert((file_id, file_version), generics);
@@ -293,6 +361,60 @@ pub fn matches_type(expected: &DataType, given: &DataType, generics_map: &Generi
     }
 }
 
+/// Whether casting a value of type `from` to type `to` is most likely a mistake,
+/// following the rules of the compiler.
+pub fn is_absurd_cast(from: &DataType, to: &DataType, generics_map: &GenericsMap) -> bool {
+    match (from, to) {
+        (DataType::Generic(id), to) => {
+            is_absurd_cast(&generics_map.get_recursive(*id), to, generics_map)
+        }
+        (from, DataType::Generic(id)) => {
+            is_absurd_cast(from, &generics_map.get_recursive(*id), generics_map)
+        }
+        (DataType::Any | DataType::Error, _) | (_, DataType::Any | DataType::Error) => false,
+        // Missing failure handlers are reported on their own
+        (DataType::Failable(from), to) => is_absurd_cast(from, to, generics_map),
+        (DataType::Union(types), to) => types
+            .iter()
+            .any(|from| is_absurd_cast(from, to, generics_map)),
+        (from, to) if from == to => false,
+        (DataType::Array(from), DataType::Array(to)) => !is_scalar(from) && !is_scalar(to),
+        (DataType::Array(_) | DataType::Null, DataType::Array(_) | DataType::Null) => true,
+        (DataType::Boolean | DataType::Number, DataType::Boolean | DataType::Number) => false,
+        (DataType::Text, DataType::Number) => false,
+        _ => true,
+    }
+}
+
+fn is_scalar(ty: &DataType) -> bool {
+    matches!(ty, DataType::Boolean | DataType::Number)
+}
+
+/// Whether a function returning the type can fail, `None` if it isn't known yet.
+pub fn can_fail(ty: &DataType, generics_map: &GenericsMap) -> Option<bool> {
+    match ty {
+        DataType::Failable(_) => Some(true),
+        DataType::Generic(id) => can_fail(&generics_map.get_recursive(*id), generics_map),
+        DataType::Any | DataType::Error => None,
+        _ => Some(false),
+    }
+}
+
+/// Whether the type refers to a generic type that isn't narrowed down to any type.
+pub fn has_unresolved_generic(ty: &DataType, generics_map: &GenericsMap) -> bool {
+    match ty {
+        DataType::Generic(id) => match generics_map.get(*id) {
+            DataType::Any => true,
+            ty => has_unresolved_generic(&ty, generics_map),
+        },
+        DataType::Array(ty) | DataType::Failable(ty) => has_unresolved_generic(ty, generics_map),
+        DataType::Union(types) => types
+            .iter()
+            .any(|ty| has_unresolved_generic(ty, generics_map)),
+        _ => false,
+    }
+}
+
 fn flatten_types(types: Vec<DataType>) -> Vec<DataType> {
     types
         .into_iter()
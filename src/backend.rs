@@ -1,46 +1,107 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 use chumsky::container::Seq;
 use ropey::Rope;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::sync::RwLock;
 use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::analysis::{
-    self, get_symbol_definition_info, Context, FunctionSymbol, SymbolInfo, SymbolTable, SymbolType,
-    VariableSymbol,
+    self, command_flags::command_flags, get_symbol_definition_info, shell, Context, FunctionSymbol,
+    SymbolInfo, SymbolTable, SymbolType, VariableSymbol,
 };
+use crate::compiler::{
+    self, CompiledBash, CompilerError, CompilerMessage, MessageLevel, ScriptExit, RUN_COMMAND,
+    RUN_WITH_ARGUMENTS_COMMAND, SHOW_COMPILED_BASH_COMMAND,
+};
+use crate::detection::{self, DetectedVersion, CONFIG_FILE};
+use crate::diagnostics::{DiagnosticKind, FileDiagnostic};
 use crate::files::{FileVersion, Files, DEFAULT_VERSION};
 use crate::fs::{LocalFs, FS};
-use crate::grammar::{self, Grammar, LSPAnalysis, ParserResponse};
+use crate::grammar::shell::ShellCompletion;
+use crate::grammar::{self, Grammar, LSPAnalysis, ParserResponse, Span, Spanned};
 use crate::paths::FileId;
-use crate::stdlib::find_in_stdlib;
+use crate::settings::{Settings, VersionSetting, WorkspaceSettings, SETTINGS_SECTION};
+use crate::stdlib::{
+    external_stdlib_dir_of, find_in_stdlib, stdlib_contents, stdlib_version, STDLIB_SCHEME,
+};
+use crate::utils::FastDashMap;
 
 type PinnedFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AmberVersion {
     Alpha034,
     Alpha035,
     Alpha040,
 }
 
+impl AmberVersion {
+    /// The most recent supported version.
+    pub const LATEST: AmberVersion = AmberVersion::Alpha040;
+
+    fn lsp_analysis(&self) -> Arc<dyn LSPAnalysis> {
+        match self {
+            AmberVersion::Alpha034 => Arc::new(grammar::alpha034::AmberCompiler::new()),
+            AmberVersion::Alpha035 => Arc::new(grammar::alpha035::AmberCompiler::new()),
+            AmberVersion::Alpha040 => Arc::new(grammar::alpha040::AmberCompiler::new()),
+        }
+    }
+}
+
+impl fmt::Display for AmberVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmberVersion::Alpha034 => write!(f, "0.3.4-alpha"),
+            AmberVersion::Alpha035 => write!(f, "0.3.5-alpha"),
+            AmberVersion::Alpha040 => write!(f, "0.4.0-alpha"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Backend {
     pub client: Client,
     pub files: Files,
-    /// The LSP analysis implementation.
-    pub lsp_analysis: Box<dyn LSPAnalysis>,
+    /// The LSP analysis implementations of Amber versions in use.
+    lsp_analysis: FastDashMap<AmberVersion, Arc<dyn LSPAnalysis>>,
     pub token_types: Box<[SemanticTokenType]>,
-    pub amber_version: AmberVersion,
+    /// Version chosen on the command line, used when the settings leave it on `auto`.
+    default_version: VersionSetting,
+    settings: std::sync::RwLock<WorkspaceSettings>,
+    /// Versions reported by compiler binaries, [`None`] if the binary couldn't be run,
+    /// along with the modification time of the binary they were reported by.
+    compiler_versions: FastDashMap<PathBuf, (Option<SystemTime>, Option<AmberVersion>)>,
+    /// Load imported stdlib modules from the precomputed index instead of analyzing them.
+    pub(crate) use_stdlib_index: bool,
+    /// Directories executables are looked up in, `PATH` of the server by default.
+    search_path: std::sync::RwLock<Option<OsString>>,
+    /// Whether each command name was found in the search path, and when it was looked up.
+    path_lookups: FastDashMap<String, (bool, Instant)>,
 }
 
+/// How long a command name found or not found in the search path is remembered, so that
+/// newly installed programs are picked up.
+const PATH_LOOKUP_TTL: Duration = Duration::from_secs(30);
+
 impl Backend {
-    pub fn new(client: Client, amber_version: AmberVersion, fs: Option<Arc<dyn FS>>) -> Self {
+    pub fn new(
+        client: Client,
+        amber_version: impl Into<VersionSetting>,
+        fs: Option<Arc<dyn FS>>,
+    ) -> Self {
         let fs = if let Some(fs) = fs {
             fs
         } else {
@@ -54,20 +115,369 @@ impl Backend {
         Self {
             client,
             files,
-            lsp_analysis: match amber_version {
-                AmberVersion::Alpha034 => Box::new(grammar::alpha034::AmberCompiler::new()),
-                AmberVersion::Alpha035 => Box::new(grammar::alpha035::AmberCompiler::new()),
-                AmberVersion::Alpha040 => Box::new(grammar::alpha040::AmberCompiler::new()),
-            },
-            token_types: match amber_version {
-                AmberVersion::Alpha034 => Box::new(grammar::alpha034::semantic_tokens::LEGEND_TYPE),
-                AmberVersion::Alpha035 => Box::new(grammar::alpha035::semantic_tokens::LEGEND_TYPE),
-                AmberVersion::Alpha040 => Box::new(grammar::alpha040::semantic_tokens::LEGEND_TYPE),
-            },
-            amber_version,
+            lsp_analysis: FastDashMap::default(),
+            // All versions share the same legend
+            token_types: Box::new(grammar::alpha040::semantic_tokens::LEGEND_TYPE),
+            default_version: amber_version.into(),
+            settings: std::sync::RwLock::new(WorkspaceSettings::default()),
+            compiler_versions: FastDashMap::default(),
+            use_stdlib_index: true,
+            search_path: std::sync::RwLock::new(std::env::var_os("PATH")),
+            path_lookups: FastDashMap::default(),
+        }
+    }
+
+    /// Look up executables in the directories of `search_path` instead of `PATH`.
+    pub fn set_search_path(&self, search_path: Option<OsString>) {
+        *self.search_path.write().unwrap() = search_path;
+        self.path_lookups.clear();
+    }
+
+    /// Directories executables are looked up in.
+    fn search_path(&self) -> Vec<PathBuf> {
+        match &*self.search_path.read().unwrap() {
+            Some(paths) => std::env::split_paths(paths).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Settings that apply to the document.
+    pub fn settings_for(&self, uri: &Url) -> Settings {
+        self.settings.read().unwrap().get(uri).clone()
+    }
+
+    /// Amber version the document is analyzed with.
+    pub fn amber_version_for(&self, uri: &Url) -> AmberVersion {
+        if let Some(amber_version) = self.configured_amber_version(uri) {
+            return amber_version;
+        }
+
+        self.files
+            .get(uri)
+            .and_then(|file_id| self.files.amber_versions.get(&file_id).map(|v| v.clone()))
+            .unwrap_or(AmberVersion::LATEST)
+    }
+
+    /// Amber version of the document that doesn't need to be detected.
+    fn configured_amber_version(&self, uri: &Url) -> Option<AmberVersion> {
+        if let Some(amber_version) = stdlib_version(uri) {
+            return Some(amber_version);
+        }
+
+        match self.settings_for(uri).version {
+            VersionSetting::Auto => self.default_version.amber_version(),
+            version => version.amber_version(),
+        }
+    }
+
+    /// Pick the Amber version to analyze the document with.
+    ///
+    /// Detected versions are reported to the client whenever they change.
+    #[tracing::instrument(skip_all)]
+    async fn select_amber_version(&self, file_id: FileId, text: &str) -> AmberVersion {
+        let uri = self.files.lookup(&file_id);
+
+        if let Some(amber_version) = self.configured_amber_version(&uri) {
+            return amber_version;
+        }
+
+        let DetectedVersion { version, reason } = self.detect_amber_version(&uri, text).await;
+
+        if self.files.amber_versions.insert(file_id, version.clone()) != Some(version.clone()) {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("Using Amber {} for {}: {}", version, uri, reason),
+                )
+                .await;
+        }
+
+        version
+    }
+
+    /// Detect the Amber version of the document, in order of priority from
+    /// the project config file, shebang, installed compiler and the syntax used.
+    async fn detect_amber_version(&self, uri: &Url, text: &str) -> DetectedVersion {
+        if let Ok(file_path) = uri.to_file_path() {
+            if let Some(detected) =
+                detection::from_config_file(self.files.fs.as_ref(), &file_path).await
+            {
+                return detected;
+            }
+        }
+
+        if let Some(detected) = detection::from_shebang(text) {
+            return detected;
+        }
+
+        if let Some(compiler) = self.compiler_path(uri).await {
+            if let Some(version) = self.compiler_version(&compiler).await {
+                return DetectedVersion {
+                    version,
+                    reason: format!("reported by `{} -V`", compiler.display()),
+                };
+            }
+        }
+
+        let tokens = self.lsp_analysis(&AmberVersion::LATEST).tokenize(text);
+
+        if let Some(detected) = detection::from_syntax(&tokens) {
+            return detected;
+        }
+
+        DetectedVersion {
+            version: AmberVersion::LATEST,
+            reason: "no version hints found, using the latest supported version".to_string(),
+        }
+    }
+
+    /// Path to the `amber` compiler used for the document, either the configured one
+    /// or the first one found on `PATH`.
+    pub async fn compiler_path(&self, uri: &Url) -> Option<PathBuf> {
+        if let Some(amber_path) = self.settings_for(uri).amber_path {
+            return Some(amber_path);
+        }
+
+        let binary = format!("amber{}", std::env::consts::EXE_SUFFIX);
+
+        for dir in self.search_path() {
+            let compiler = dir.join(&binary);
+
+            if self.files.fs.exists(&compiler).await {
+                return Some(compiler);
+            }
+        }
+
+        None
+    }
+
+    /// Version reported by the compiler binary, [`None`] if it couldn't be run.
+    ///
+    /// The version is asked for again once the binary is modified, e.g. by an update.
+    pub async fn compiler_version(&self, compiler: &Path) -> Option<AmberVersion> {
+        let modified = self.files.fs.modified(compiler).await.ok();
+
+        if let Some(cached) = self.compiler_versions.get(compiler) {
+            let (cached_modified, version) = &*cached;
+
+            if *cached_modified == modified {
+                return version.clone();
+            }
+        }
+
+        let version = detection::from_compiler(compiler).await;
+
+        self.compiler_versions
+            .insert(compiler.to_path_buf(), (modified, version.clone()));
+
+        version
+    }
+
+    pub fn lsp_analysis(&self, amber_version: &AmberVersion) -> Arc<dyn LSPAnalysis> {
+        self.lsp_analysis
+            .entry(amber_version.clone())
+            .or_insert_with(|| amber_version.lsp_analysis())
+            .clone()
+    }
+
+    /// Apply new global settings.
+    pub async fn update_settings(&self, settings: Settings) {
+        self.change_settings(|workspace_settings| workspace_settings.global = settings)
+            .await;
+    }
+
+    /// Apply new settings of a workspace folder or a document.
+    pub async fn update_scope_settings(&self, scope: Url, settings: Settings) {
+        self.change_settings(|workspace_settings| {
+            workspace_settings.set_scope(scope, Some(settings))
+        })
+        .await;
+    }
+
+    /// Change the settings, re-analyzing only the documents affected by the change.
+    #[tracing::instrument(skip_all)]
+    async fn change_settings(&self, change: impl FnOnce(&mut WorkspaceSettings)) {
+        let open_files = self
+            .files
+            .get_open_files()
+            .into_iter()
+            .map(|file_id| {
+                let uri = self.files.lookup(&file_id);
+                let settings = self.settings_for(&uri);
+
+                (file_id, uri, settings)
+            })
+            .collect::<Vec<_>>();
+
+        change(&mut self.settings.write().unwrap());
+        // Programs may have been installed since, e.g. to fix a missing command
+        self.path_lookups.clear();
+
+        let mut needs_reanalysis = false;
+        let mut needs_hints_refresh = false;
+        let mut files_to_publish = vec![];
+
+        for (file_id, uri, old_settings) in open_files {
+            let settings = self.settings_for(&uri);
+
+            if old_settings == settings {
+                continue;
+            }
+
+            info!("Settings of {} changed: {:?}", uri, settings);
+
+            if old_settings.amber_path != settings.amber_path {
+                self.compiler_versions.clear();
+            }
+
+            if old_settings.version != settings.version
+                || old_settings.import_paths != settings.import_paths
+                || old_settings.stdlib_path != settings.stdlib_path
+                || old_settings.amber_path != settings.amber_path
+                || old_settings.use_installed_stdlib != settings.use_installed_stdlib
+                || old_settings.known_commands != settings.known_commands
+            {
+                needs_reanalysis = true;
+            } else if old_settings.diagnostics != settings.diagnostics
+                || old_settings.max_number_of_problems != settings.max_number_of_problems
+            {
+                files_to_publish.push(file_id);
+            }
+
+            if old_settings.compiler_diagnostics.enabled && !settings.compiler_diagnostics.enabled {
+                self.files
+                    .compiler_diagnostics
+                    .retain(|(compiled_file_id, _), _| *compiled_file_id != file_id);
+                files_to_publish.push(file_id);
+            }
+
+            if old_settings.inlay_hints != settings.inlay_hints {
+                needs_hints_refresh = true;
+            }
+        }
+
+        if needs_reanalysis {
+            self.reanalyze_open_documents().await;
+        } else {
+            for file_id in files_to_publish {
+                let version = self.files.get_latest_version(file_id);
+                self.publish_syntax_errors(file_id, version).await;
+            }
+        }
+
+        if needs_hints_refresh {
+            let _ = self.client.inlay_hint_refresh().await;
         }
     }
 
+    /// Ask the client for the current settings of the workspace and all scopes, and apply them.
+    #[tracing::instrument(skip_all)]
+    async fn pull_settings(&self) {
+        let scopes = self
+            .settings
+            .read()
+            .unwrap()
+            .scopes
+            .iter()
+            .map(|(scope, _)| scope.clone())
+            .collect::<Vec<_>>();
+
+        let items = std::iter::once(None)
+            .chain(scopes.iter().cloned().map(Some))
+            .map(|scope_uri| ConfigurationItem {
+                scope_uri,
+                section: Some(SETTINGS_SECTION.to_string()),
+            })
+            .collect::<Vec<_>>();
+
+        let values = match self.client.configuration(items).await {
+            Ok(values) if values.len() == scopes.len() + 1 => values,
+            _ => return,
+        };
+
+        let mut values = self
+            .parse_settings(
+                std::iter::once(None)
+                    .chain(scopes.iter().cloned().map(Some))
+                    .zip(values)
+                    .collect(),
+            )
+            .await
+            .into_iter();
+
+        let global = values.next().unwrap_or_default();
+
+        self.change_settings(|workspace_settings| {
+            workspace_settings.global = global;
+
+            for (scope, settings) in scopes.into_iter().zip(values) {
+                workspace_settings.set_scope(scope, Some(settings));
+            }
+        })
+        .await;
+    }
+
+    /// Parse settings sent by the client for the given scopes, showing the user what's
+    /// wrong with them.
+    ///
+    /// Relative paths are resolved against the workspace folder of the scope.
+    async fn parse_settings(&self, values: Vec<(Option<Url>, Value)>) -> Vec<Settings> {
+        let mut all_problems = vec![];
+
+        let settings = values
+            .into_iter()
+            .map(|(scope, value)| {
+                let (mut settings, problems) = Settings::parse(value);
+                all_problems.extend(problems);
+
+                let workspace_folder = self
+                    .settings
+                    .read()
+                    .unwrap()
+                    .folder_of(scope.as_ref())
+                    .and_then(|folder| folder.to_file_path().ok());
+                settings.resolve_import_paths(workspace_folder.as_deref());
+
+                settings
+            })
+            .collect();
+
+        all_problems.dedup();
+
+        for problem in all_problems {
+            warn!("{}", problem);
+            self.client
+                .show_message(MessageType::WARNING, problem)
+                .await;
+        }
+
+        settings
+    }
+
+    /// Ask the client for settings of a single document, without re-analyzing it.
+    #[tracing::instrument(skip_all)]
+    async fn pull_document_settings(&self, uri: &Url) {
+        let settings = match self
+            .client
+            .configuration(vec![ConfigurationItem {
+                scope_uri: Some(uri.clone()),
+                section: Some(SETTINGS_SECTION.to_string()),
+            }])
+            .await
+        {
+            Ok(values) if values.len() == 1 => self
+                .parse_settings(vec![(Some(uri.clone()), values[0].clone())])
+                .await
+                .remove(0),
+            _ => return,
+        };
+
+        self.settings
+            .write()
+            .unwrap()
+            .set_scope(uri.clone(), Some(settings));
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn open_document<'a>(&'a self, uri: &'a Url) -> PinnedFuture<'a, (FileId, FileVersion)> {
         Box::pin(async move {
@@ -76,12 +486,19 @@ impl Backend {
                 return Ok((file_id, version));
             }
 
-            let file_path = uri.to_file_path().map_err(|_| Error::internal_error())?;
+            let text = if uri.scheme() == STDLIB_SCHEME {
+                match stdlib_contents(uri) {
+                    Some(text) => Rope::from_str(&text),
+                    None => return Err(Error::internal_error()),
+                }
+            } else {
+                let file_path = uri.to_file_path().map_err(|_| Error::internal_error())?;
 
-            let text = match self.files.fs.read(&file_path).await {
-                Ok(text) => Rope::from_str(&text),
-                Err(_) => {
-                    return Err(Error::internal_error());
+                match self.files.fs.read(&file_path).await {
+                    Ok(text) => Rope::from_str(&text),
+                    Err(_) => {
+                        return Err(Error::internal_error());
+                    }
                 }
             };
 
@@ -112,30 +529,402 @@ impl Backend {
 
     #[tracing::instrument(skip_all)]
     pub async fn publish_syntax_errors(&self, file_id: FileId, file_version: FileVersion) {
-        let errors = match self.files.errors.get(&(file_id, file_version)) {
-            Some(errors) => errors.clone(),
+        let diagnostics = match self.document_diagnostics(file_id, file_version) {
+            Some(diagnostics) => diagnostics,
             None => return,
         };
 
-        let (rope, version) = match self.files.get_document_latest_version(file_id) {
-            Some(document) => document,
-            None => return,
+        let version = self.files.get_latest_version(file_id);
+
+        self.publish_diagnostics(&file_id, diagnostics, Some(version))
+            .await;
+    }
+
+    /// Diagnostics of the document to send to the client.
+    ///
+    /// Diagnostics of the compiler are included, unless the language server already
+    /// reported the same problem.
+    pub fn document_diagnostics(
+        &self,
+        file_id: FileId,
+        file_version: FileVersion,
+    ) -> Option<Vec<Diagnostic>> {
+        let file = (file_id, file_version);
+
+        let settings = self.settings_for(&self.files.lookup(&file_id));
+
+        let compiler_diagnostics = match self.files.compiler_diagnostics.get(&file) {
+            Some(diagnostics) => diagnostics.clone(),
+            None => vec![],
+        };
+
+        let file_diagnostics = match self.files.diagnostics.get(&file) {
+            Some(diagnostics) => diagnostics.clone(),
+            None if !compiler_diagnostics.is_empty() => vec![],
+            None => return None,
         };
 
-        let diagnostics = errors
+        let (rope, _) = self.files.get_document_latest_version(file_id)?;
+        let uri = self.files.lookup(&file_id);
+
+        let compiler_diagnostics = compiler_diagnostics.into_iter().filter(|diagnostic| {
+            !file_diagnostics
+                .iter()
+                .any(|file_diagnostic| diagnostic.is_duplicate_of(file_diagnostic))
+        });
+
+        let diagnostics = file_diagnostics
             .iter()
-            .map(|(msg, span)| {
-                let start_position = self.offset_to_position(span.start, &rope);
-                let end_position = self.offset_to_position(span.end, &rope);
+            .cloned()
+            .chain(compiler_diagnostics)
+            .filter_map(|diagnostic| {
+                let severity = settings.severity(diagnostic.kind)?;
 
-                Diagnostic::new_simple(Range::new(start_position, end_position), msg.to_string())
+                Some(self.lsp_diagnostic(&diagnostic, severity, &uri, &rope))
             })
+            .take(settings.max_number_of_problems)
             .collect::<Vec<_>>();
 
-        self.publish_diagnostics(&file_id, diagnostics, Some(version))
+        Some(diagnostics)
+    }
+
+    /// Convert the diagnostic to the LSP representation.
+    fn lsp_diagnostic(
+        &self,
+        diagnostic: &FileDiagnostic,
+        severity: DiagnosticSeverity,
+        uri: &Url,
+        rope: &Rope,
+    ) -> Diagnostic {
+        let range = |span: &Span| {
+            Range::new(
+                self.offset_to_position(span.start, rope),
+                self.offset_to_position(span.end, rope),
+            )
+        };
+
+        let related_information = match diagnostic.related.is_empty() {
+            true => None,
+            false => Some(
+                diagnostic
+                    .related
+                    .iter()
+                    .map(|(message, span)| DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), range(span)),
+                        message: message.clone(),
+                    })
+                    .collect(),
+            ),
+        };
+
+        Diagnostic {
+            range: range(&diagnostic.span),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(diagnostic.kind.code().to_string())),
+            source: Some("amber-lsp".to_string()),
+            message: diagnostic.message.clone(),
+            related_information,
+            tags: diagnostic.kind.tags(),
+            ..Diagnostic::default()
+        }
+    }
+
+    /// Check the saved document with the `amber` compiler, if it's enabled in the settings.
+    #[tracing::instrument(skip_all)]
+    pub async fn check_with_compiler(&self, file_id: FileId) {
+        let uri = self.files.lookup(&file_id);
+        let settings = self.settings_for(&uri).compiler_diagnostics;
+
+        if !settings.enabled {
+            return;
+        }
+
+        let file_path = match uri.to_file_path() {
+            Ok(file_path) => file_path,
+            Err(_) => return,
+        };
+
+        let compiler = match self.compiler_path(&uri).await {
+            Some(compiler) => compiler,
+            None => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        "Can't check the document, amber compiler not found",
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        let (rope, version) = match self.files.get_document_latest_version(file_id) {
+            Some(document) => document,
+            None => return,
+        };
+
+        let messages = match compiler::check(
+            &compiler,
+            &self.amber_version_for(&uri),
+            &file_path,
+            Duration::from_millis(settings.timeout),
+        )
+        .await
+        {
+            Ok(messages) => messages,
+            Err(err) => {
+                self.client
+                    .log_message(MessageType::WARNING, err.to_string())
+                    .await;
+                return;
+            }
+        };
+
+        self.publish_compiler_messages(file_id, version, &rope, &file_path, messages)
             .await;
     }
 
+    /// Compile the document, including unsaved changes, to Bash.
+    ///
+    /// Returns [`None`] if the compilation failed, its errors are published as diagnostics
+    /// of the document.
+    #[tracing::instrument(skip_all)]
+    pub async fn show_compiled_bash(&self, uri: Url) -> Result<Option<CompiledBash>> {
+        let file_id = self
+            .files
+            .get(&uri)
+            .ok_or_else(|| Error::invalid_params(format!("{} is not open", uri)))?;
+
+        let file_path = uri
+            .to_file_path()
+            .map_err(|_| Error::invalid_params(format!("{} is not a file", uri)))?;
+
+        let compiler = self
+            .compiler_path(&uri)
+            .await
+            .ok_or_else(|| Error::invalid_params("Amber compiler not found"))?;
+
+        let (rope, version) = self
+            .files
+            .get_document_latest_version(file_id)
+            .ok_or_else(Error::internal_error)?;
+
+        let timeout = self.settings_for(&uri).compiler_diagnostics.timeout;
+
+        let compilation = compiler::compile(
+            &compiler,
+            &self.amber_version_for(&uri),
+            &file_path,
+            &rope.to_string(),
+            Duration::from_millis(timeout),
+        )
+        .await
+        .map_err(|err| Error {
+            message: err.to_string().into(),
+            ..Error::internal_error()
+        })?;
+
+        self.publish_compiler_messages(file_id, version, &rope, &file_path, compilation.messages)
+            .await;
+
+        if compilation.bash.is_none() {
+            self.client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("Failed to compile {}", file_path.display()),
+                )
+                .await;
+        }
+
+        Ok(compilation.bash.map(|text| CompiledBash {
+            uri: compiler::compiled_bash_uri(&uri),
+            text,
+        }))
+    }
+
+    /// Run the saved document with the configured compiler.
+    ///
+    /// Output of the script is streamed to the client as log messages, and reported
+    /// through the progress if the client sent a token.
+    #[tracing::instrument(skip_all)]
+    pub async fn run_script(
+        &self,
+        uri: Url,
+        args: Vec<String>,
+        work_done_token: Option<ProgressToken>,
+    ) -> Result<ScriptExit> {
+        let file_path = uri
+            .to_file_path()
+            .map_err(|_| Error::invalid_params(format!("{} is not a file", uri)))?;
+
+        let compiler = self
+            .compiler_path(&uri)
+            .await
+            .ok_or_else(|| Error::invalid_params("Amber compiler not found"))?;
+
+        let command_error = |err: CompilerError| Error {
+            message: err.to_string().into(),
+            ..Error::internal_error()
+        };
+
+        let mut child =
+            compiler::script_command(&compiler, &self.amber_version_for(&uri), &file_path, &args)
+                .map_err(|err| Error::invalid_params(err.to_string()))?
+                .spawn()
+                .map_err(|err| command_error(CompilerError::Spawn(compiler.clone(), err)))?;
+
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        self.report_progress(
+            &work_done_token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: format!("Running {}", file_name),
+                ..WorkDoneProgressBegin::default()
+            }),
+        )
+        .await;
+
+        let stream_output = |output: Option<Box<dyn AsyncRead + Send + Unpin>>,
+                             message_type: MessageType| {
+            let work_done_token = &work_done_token;
+
+            async move {
+                let mut output = match output {
+                    Some(output) => BufReader::new(output),
+                    None => return,
+                };
+                let mut buffer = Vec::new();
+
+                // Lines that are not valid UTF-8 are still read, so the pipe keeps draining
+                while let Ok(1..) = output.read_until(b'\n', &mut buffer).await {
+                    let line = String::from_utf8_lossy(&buffer)
+                        .trim_end_matches('\n')
+                        .trim_end_matches('\r')
+                        .to_string();
+                    buffer.clear();
+
+                    self.client.log_message(message_type, &line).await;
+                    self.report_progress(
+                        work_done_token,
+                        WorkDoneProgress::Report(WorkDoneProgressReport {
+                            message: Some(line),
+                            ..WorkDoneProgressReport::default()
+                        }),
+                    )
+                    .await;
+                }
+            }
+        };
+
+        tokio::join!(
+            stream_output(
+                child
+                    .stdout
+                    .take()
+                    .map(|stdout| Box::new(stdout) as Box<dyn AsyncRead + Send + Unpin>),
+                MessageType::LOG,
+            ),
+            stream_output(
+                child
+                    .stderr
+                    .take()
+                    .map(|stderr| Box::new(stderr) as Box<dyn AsyncRead + Send + Unpin>),
+                MessageType::ERROR,
+            ),
+        );
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|err| command_error(CompilerError::Spawn(compiler.clone(), err)))?;
+
+        let message = match status.code() {
+            Some(code) => format!("{} exited with code {}", file_name, code),
+            None => format!("{} was terminated", file_name),
+        };
+
+        self.report_progress(
+            &work_done_token,
+            WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: Some(message.clone()),
+            }),
+        )
+        .await;
+
+        self.client
+            .show_message(
+                if status.success() {
+                    MessageType::INFO
+                } else {
+                    MessageType::ERROR
+                },
+                message,
+            )
+            .await;
+
+        Ok(ScriptExit {
+            exit_code: status.code(),
+            success: status.success(),
+        })
+    }
+
+    async fn report_progress(&self, token: &Option<ProgressToken>, progress: WorkDoneProgress) {
+        let token = match token {
+            Some(token) => token.clone(),
+            None => return,
+        };
+
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    /// Publish messages of the compiler as diagnostics of the document version.
+    async fn publish_compiler_messages(
+        &self,
+        file_id: FileId,
+        version: FileVersion,
+        rope: &Rope,
+        file_path: &Path,
+        messages: Vec<CompilerMessage>,
+    ) {
+        let diagnostics = messages
+            .into_iter()
+            .filter_map(|message| {
+                let span = match message.location {
+                    Some((path, line, column)) if file_path.ends_with(&path) => {
+                        compiler::location_span(rope, line, column)
+                    }
+                    // Problems of imported files are reported in them
+                    Some(_) => return None,
+                    None => Span::new(0, 0),
+                };
+
+                Some(FileDiagnostic {
+                    kind: match message.level {
+                        MessageLevel::Error => DiagnosticKind::CompilerError,
+                        MessageLevel::Warning => DiagnosticKind::CompilerWarning,
+                    },
+                    message: message.message,
+                    span,
+                    related: vec![],
+                    fix: None,
+                })
+            })
+            .collect();
+
+        self.files
+            .compiler_diagnostics
+            .insert((file_id, version), diagnostics);
+
+        self.publish_syntax_errors(file_id, version).await;
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn analize_document(&self, file_id: FileId) {
         let (rope, version) = match self.files.get_document_latest_version(file_id) {
@@ -160,19 +949,30 @@ impl Backend {
 
         info!("Inserted lock");
 
-        let tokens = self.lsp_analysis.tokenize(&rope.to_string());
+        let text = rope.to_string();
+
+        let amber_version = self.select_amber_version(file_id, &text).await;
+        let lsp_analysis = self.lsp_analysis(&amber_version);
+
+        let tokens = lsp_analysis.tokenize(&text);
 
         let ParserResponse {
             ast,
             errors,
             semantic_tokens,
-        } = self.lsp_analysis.parse(&tokens);
+        } = lsp_analysis.parse(&tokens);
 
-        self.files.errors.insert(
+        self.files.diagnostics.insert(
             (file_id, version),
             errors
                 .iter()
-                .map(|err| (err.to_string(), *err.span()))
+                .map(|err| FileDiagnostic {
+                    kind: DiagnosticKind::Syntax,
+                    message: err.to_string(),
+                    span: *err.span(),
+                    related: vec![],
+                    fix: None,
+                })
                 .collect(),
         );
         self.files.ast_map.insert((file_id, version), ast.clone());
@@ -199,16 +999,291 @@ impl Backend {
                 analysis::alpha040::global::analyze_global_stmnt(file_id, version, &ast, self)
                     .await;
             }
-            _ => {}
+            _ => {}
+        }
+
+        self.check_command_names(&(file_id, version)).await;
+
+        *lock_w = true;
+        drop(lock_w);
+    }
+
+    /// Report commands running programs that are neither found on `PATH`, nor shell
+    /// builtins, nor known commands from the settings.
+    async fn check_command_names(&self, file: &(FileId, FileVersion)) {
+        let command_names = match self.files.shell_scripts.get(file) {
+            Some(scripts) => scripts
+                .iter()
+                .flat_map(|(script, _)| script.simple_commands())
+                .filter_map(|(command, _)| {
+                    let executable = command.executable()?;
+
+                    Some((executable.literal()?, executable.span))
+                })
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+
+        let known_commands = self
+            .settings_for(&self.files.lookup(&file.0))
+            .known_commands;
+        let mut found = HashMap::new();
+
+        for (name, span) in command_names {
+            if !shell::is_plain_command_name(&name)
+                || shell::is_shell_builtin(&name)
+                || known_commands.contains(&name)
+            {
+                continue;
+            }
+
+            let is_found = match found.get(&name) {
+                Some(is_found) => *is_found,
+                None => {
+                    let is_found = self.is_on_path(&name).await;
+                    found.insert(name.clone(), is_found);
+                    is_found
+                }
+            };
+
+            if !is_found {
+                self.files.report_diagnostic(
+                    file,
+                    DiagnosticKind::MissingCommand,
+                    &format!("Command `{}` is not found on `PATH`", name),
+                    span,
+                );
+            }
+        }
+    }
+
+    /// Whether an executable with the name is in one of the directories on `PATH`.
+    ///
+    /// Lookups are remembered for [`PATH_LOOKUP_TTL`], or until the settings change.
+    async fn is_on_path(&self, name: &str) -> bool {
+        if let Some(lookup) = self.path_lookups.get(name) {
+            let (is_found, looked_up_at) = *lookup;
+
+            if looked_up_at.elapsed() < PATH_LOOKUP_TTL {
+                return is_found;
+            }
+        }
+
+        let binary = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+        let mut is_found = false;
+
+        for dir in self.search_path() {
+            if self.files.fs.exists(&dir.join(&binary)).await {
+                is_found = true;
+                break;
+            }
+        }
+
+        self.path_lookups
+            .insert(name.to_string(), (is_found, Instant::now()));
+
+        is_found
+    }
+
+    /// Completions inside of a command literal, replacing the text typed before the
+    /// position.
+    async fn command_completions(
+        &self,
+        uri: &Url,
+        file_id: FileId,
+        position: Position,
+    ) -> Option<Vec<CompletionItem>> {
+        let (rope, version) = self.files.get_document_latest_version(file_id)?;
+
+        if !self.files.is_file_analyzed(&(file_id, version)).await {
+            return None;
+        }
+
+        let char = rope
+            .try_line_to_char(position.line as usize)
+            .ok()
+            .unwrap_or(rope.len_chars());
+        let offset = char + position.character as usize;
+
+        // Only the innermost command can be completed inside of interpolations
+        let (completion, (prefix, prefix_span)) = self
+            .files
+            .shell_scripts
+            .get(&(file_id, version))?
+            .iter()
+            .find_map(|(script, _)| script.completion_at(offset))?;
+
+        let completions = match completion {
+            ShellCompletion::Executable => self.executable_completions(uri, &prefix).await,
+            ShellCompletion::Argument(Some(executable)) if prefix.starts_with('-') => {
+                let name = executable.rsplit('/').next().unwrap_or(&executable);
+
+                command_flags(name)
+                    .iter()
+                    .filter(|(flag, _)| flag.starts_with(&prefix))
+                    .map(|(flag, description)| {
+                        (
+                            (*flag).to_string(),
+                            CompletionItemKind::PROPERTY,
+                            Some(*description),
+                        )
+                    })
+                    .collect()
+            }
+            ShellCompletion::Argument(_) => {
+                let completions = self.path_completions(uri, &prefix, prefix_span).await?;
+
+                return Some(self.completion_items(completions, &rope));
+            }
+            ShellCompletion::Variable => {
+                let mut names = std::env::vars_os()
+                    .filter_map(|(name, _)| name.into_string().ok())
+                    .filter(|name| name.starts_with(&prefix))
+                    .collect::<Vec<_>>();
+                names.sort();
+
+                names
+                    .into_iter()
+                    .map(|name| (name, CompletionItemKind::VARIABLE, None))
+                    .collect()
+            }
+        };
+
+        Some(self.completion_items((completions, prefix_span), &rope))
+    }
+
+    /// Completion items with their labels replacing the text of the span.
+    fn completion_items(
+        &self,
+        (completions, span): Spanned<Vec<(String, CompletionItemKind, Option<&str>)>>,
+        rope: &Rope,
+    ) -> Vec<CompletionItem> {
+        let range = Range::new(
+            self.offset_to_position(span.start, rope),
+            self.offset_to_position(span.end, rope),
+        );
+
+        completions
+            .into_iter()
+            .map(|(label, kind, detail)| CompletionItem {
+                label: label.clone(),
+                kind: Some(kind),
+                detail: detail.map(str::to_string),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: label,
+                })),
+                ..CompletionItem::default()
+            })
+            .collect()
+    }
+
+    /// Shell builtins, known commands from the settings and executables on `PATH`
+    /// starting with the prefix.
+    async fn executable_completions(
+        &self,
+        uri: &Url,
+        prefix: &str,
+    ) -> Vec<(String, CompletionItemKind, Option<&'static str>)> {
+        let mut completions = shell::SHELL_BUILTINS
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                (
+                    name.to_string(),
+                    CompletionItemKind::KEYWORD,
+                    Some("shell builtin"),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut names = self
+            .settings_for(uri)
+            .known_commands
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .collect::<Vec<_>>();
+
+        for dir in self.search_path() {
+            for entry in self.files.fs.read_dir(&dir).await {
+                let name = match entry.strip_prefix(&dir).ok().and_then(|path| path.to_str()) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let name = name
+                    .strip_suffix(std::env::consts::EXE_SUFFIX)
+                    .unwrap_or(name);
+
+                if name.starts_with(prefix) && shell::is_plain_command_name(name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+        names.dedup();
+
+        completions.extend(
+            names
+                .into_iter()
+                .filter(|name| !shell::is_shell_builtin(name))
+                .map(|name| (name, CompletionItemKind::FUNCTION, None)),
+        );
+
+        completions
+    }
+
+    /// Entries of the directory the path typed so far points to, relative to the
+    /// directory of the script. Only the last segment of the path is replaced.
+    async fn path_completions(
+        &self,
+        uri: &Url,
+        prefix: &str,
+        prefix_span: Span,
+    ) -> Option<Spanned<Vec<(String, CompletionItemKind, Option<&'static str>)>>> {
+        let (dir_prefix, name_prefix) = match prefix.rfind('/') {
+            Some(index) => (&prefix[..=index], &prefix[index + 1..]),
+            None => ("", prefix),
+        };
+
+        let dir = if dir_prefix.starts_with('/') {
+            PathBuf::from(dir_prefix)
+        } else {
+            // Stdlib documents have no directory to complete paths in
+            uri.to_file_path().ok()?.parent()?.join(dir_prefix)
+        };
+
+        let mut entries = vec![];
+
+        for entry in self.files.fs.read_dir(&dir).await {
+            let mut components = match entry.strip_prefix(&dir) {
+                Ok(path) => path.components(),
+                Err(_) => continue,
+            };
+            let name = match components.next() {
+                Some(name) => name.as_os_str().to_string_lossy().to_string(),
+                None => continue,
+            };
+
+            if !name.starts_with(name_prefix) || (name_prefix.is_empty() && name.starts_with('.')) {
+                continue;
+            }
+
+            let is_dir = components.next().is_some() || entry.is_dir();
+
+            entries.push(if is_dir {
+                (format!("{}/", name), CompletionItemKind::FOLDER, None)
+            } else {
+                (name, CompletionItemKind::FILE, None)
+            });
         }
 
-        *lock_w = true;
-        drop(lock_w);
+        entries.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+        entries.dedup_by(|(a, _, _), (b, _, _)| a == b);
 
-        Box::pin(async {
-            self.analyze_dependencies(file_id).await;
-        })
-        .await;
+        let name_start = prefix_span.end - name_prefix.chars().count();
+
+        Some((entries, Span::new(name_start, prefix_span.end)))
     }
 
     pub fn offset_to_position(&self, offset: usize, rope: &Rope) -> Position {
@@ -222,17 +1297,66 @@ impl Backend {
         Position::new(line as u32, column as u32)
     }
 
-    async fn analyze_dependencies(&self, file_id: FileId) {
-        let deps = self.files.get_files_dependant_on(file_id);
+    /// Drop analysis results of the latest version of the document and analyze it again.
+    #[tracing::instrument(skip_all)]
+    pub async fn reanalyze_document(&self, file_id: FileId) {
+        let version = match self.files.get_document_latest_version(file_id) {
+            Some((_, version)) => version,
+            None => return,
+        };
+
+        self.files.invalidate(&(file_id, version));
+
+        self.analize_document(file_id).await;
+    }
+
+    /// Analyze again every file importing the document, directly or not.
+    pub async fn analyze_dependencies(&self, file_id: FileId) {
+        let deps = self.files.get_files_dependant_on_transitively(file_id);
 
         for (dep_file_id, dep_file_version) in deps {
-            if dep_file_id == file_id {
+            self.reanalyze_document(dep_file_id).await;
+            self.publish_syntax_errors(dep_file_id, dep_file_version)
+                .await;
+        }
+    }
+
+    /// Analyze all open documents from scratch and drop every other loaded file,
+    /// so that imports are resolved again.
+    #[tracing::instrument(skip_all)]
+    pub async fn reanalyze_open_documents(&self) {
+        for file_id in self.files.get_loaded_files() {
+            if !self.files.is_open(file_id) {
+                self.files.remove_file(file_id);
+                self.publish_diagnostics(&file_id, vec![], None).await;
+            }
+        }
+
+        for file_id in self.files.get_open_files() {
+            self.reanalyze_document(file_id).await;
+
+            let version = self.files.get_latest_version(file_id);
+            self.publish_syntax_errors(file_id, version).await;
+        }
+
+        self.release_unused_files().await;
+    }
+
+    /// Evict every file that is neither open in the editor nor imported
+    /// by an open file, and clear its diagnostics.
+    #[tracing::instrument(skip_all)]
+    pub async fn release_unused_files(&self) {
+        let files_in_use = self.files.get_files_in_use();
+
+        for file_id in self.files.get_loaded_files() {
+            if files_in_use.contains(&file_id) {
                 continue;
             }
 
-            self.analize_document(dep_file_id).await;
-            self.publish_syntax_errors(dep_file_id, dep_file_version)
-                .await;
+            info!("Releasing file {:?}", file_id);
+
+            self.files.remove_file(file_id);
+            self.publish_diagnostics(&file_id, vec![], None).await;
         }
     }
 
@@ -270,16 +1394,57 @@ impl Backend {
 
         Some((symbol_info, offset))
     }
+
+    /// Handler of the [`STDLIB_CONTENTS_REQUEST`], returning contents of an embedded stdlib document.
+    pub async fn stdlib_contents(&self, params: TextDocumentIdentifier) -> Result<String> {
+        stdlib_contents(&params.uri).ok_or_else(|| {
+            Error::invalid_params(format!("{} is not a stdlib document", params.uri))
+        })
+    }
+
+    /// File the symbol at the offset is defined in. For import paths, it's the imported file.
+    fn get_symbol_definition_file(
+        &self,
+        file_id: FileId,
+        symbol_info: &SymbolInfo,
+        offset: usize,
+    ) -> Option<Url> {
+        let version = self.files.get_latest_version(file_id);
+        let symbol_table = self.files.symbol_table.get(&(file_id, version))?;
+
+        let definition = symbol_table
+            .definitions
+            .get(&symbol_info.name)?
+            .get(&offset)?
+            .clone();
+
+        Some(self.files.lookup(&definition.file.0))
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        if let Some(workspace_folders) = params.workspace_folders {
+            let mut settings = self.settings.write().unwrap();
+
+            for folder in workspace_folders {
+                settings.add_folder(folder.uri);
+            }
+        }
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::FULL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(false),
+                        })),
+                        ..TextDocumentSyncOptions::default()
+                    },
                 )),
                 workspace: Some(WorkspaceServerCapabilities {
                     workspace_folders: Some(WorkspaceFoldersServerCapabilities {
@@ -293,11 +1458,16 @@ impl LanguageServer for Backend {
                         SemanticTokensRegistrationOptions {
                             text_document_registration_options: {
                                 TextDocumentRegistrationOptions {
-                                    document_selector: Some(vec![DocumentFilter {
-                                        language: Some("amber".to_string()),
-                                        scheme: Some("file".to_string()),
-                                        pattern: None,
-                                    }]),
+                                    document_selector: Some(
+                                        ["file", STDLIB_SCHEME]
+                                            .into_iter()
+                                            .map(|scheme| DocumentFilter {
+                                                language: Some("amber".to_string()),
+                                                scheme: Some(scheme.to_string()),
+                                                pattern: None,
+                                            })
+                                            .collect(),
+                                    ),
                                 }
                             },
                             semantic_tokens_options: SemanticTokensOptions {
@@ -313,10 +1483,21 @@ impl LanguageServer for Backend {
                         },
                     ),
                 ),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        SHOW_COMPILED_BASH_COMMAND.to_string(),
+                        RUN_COMMAND.to_string(),
+                        RUN_WITH_ARGUMENTS_COMMAND.to_string(),
+                    ],
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Left(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
                     trigger_characters: Some(vec![":".to_string(), ".".to_string()]),
@@ -338,22 +1519,37 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         let options = serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-            watchers: vec![FileSystemWatcher {
-                glob_pattern: GlobPattern::String("**/*.ab".to_string()),
-                kind: None, // Default is 7 - Create | Change | Delete
-            }],
+            watchers: vec![
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String("**/*.ab".to_string()),
+                    kind: None, // Default is 7 - Create | Change | Delete
+                },
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{}", CONFIG_FILE)),
+                    kind: None,
+                },
+            ],
         })
         .unwrap();
 
         let _ = self
             .client
-            .register_capability(vec![Registration {
-                id: "did_change_watched_files".to_string(),
-                method: "workspace/didChangeWatchedFiles".to_string(),
-                register_options: Some(options),
-            }])
+            .register_capability(vec![
+                Registration {
+                    id: "did_change_watched_files".to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: Some(options),
+                },
+                Registration {
+                    id: "did_change_configuration".to_string(),
+                    method: "workspace/didChangeConfiguration".to_string(),
+                    register_options: None,
+                },
+            ])
             .await;
 
+        self.pull_settings().await;
+
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
@@ -367,8 +1563,20 @@ impl LanguageServer for Backend {
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let version = FileVersion(params.text_document.version);
 
+        // Settings of the workspace folders are pulled again once they change
+        if !self
+            .settings
+            .read()
+            .unwrap()
+            .has_scope_settings(&params.text_document.uri)
+        {
+            self.pull_document_settings(&params.text_document.uri).await;
+        }
+
         let file_id = self.files.insert(params.text_document.uri, version);
 
+        self.files.mark_as_open(file_id);
+
         self.files.document_map.insert(
             (file_id, version),
             Rope::from_str(&params.text_document.text),
@@ -460,20 +1668,104 @@ impl LanguageServer for Backend {
         self.publish_syntax_errors(file_id, new_version).await;
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
-        // TODO: Invalidate the file and re-analyze dependencies
+    #[tracing::instrument(skip_all)]
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in params.changes {
+            if change.uri.path().ends_with(&format!("/{}", CONFIG_FILE)) {
+                info!("Project config file changed: {}", change.uri);
+
+                self.reanalyze_open_documents().await;
+                continue;
+            }
+
+            let file_id = match self.files.get_id(&change.uri) {
+                Some(file_id) => file_id,
+                None => continue,
+            };
+
+            // Open documents are kept in sync by the editor
+            if self.files.is_open(file_id) {
+                continue;
+            }
+
+            info!("File {:?} changed on disk: {:?}", file_id, change.typ);
+
+            // Dependent files load the new content from disk when analyzed again
+            self.files.remove_file(file_id);
+            self.analyze_dependencies(file_id).await;
+        }
+
+        self.release_unused_files().await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "document closed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let file_id = match self.files.get(&params.text_document.uri) {
+            Some(file_id) => file_id,
+            None => return,
+        };
+
+        self.files.mark_as_closed(file_id);
+        self.settings
+            .write()
+            .unwrap()
+            .remove_scope(&params.text_document.uri);
+
+        // Unsaved changes are discarded on close, so files that still depend on
+        // this one have to see its content from disk again.
+        if !self.files.get_files_dependant_on(file_id).is_empty() {
+            self.files.remove_file(file_id);
+            self.publish_diagnostics(&file_id, vec![], None).await;
+            self.analyze_dependencies(file_id).await;
+        }
+
+        self.release_unused_files().await;
     }
 
-    async fn did_save(&self, _: DidSaveTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file saved!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let file_id = match self.files.get(&params.text_document.uri) {
+            Some(file_id) => file_id,
+            None => return,
+        };
+
+        self.analyze_dependencies(file_id).await;
+
+        self.check_with_compiler(file_id).await;
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let arguments = params.arguments;
+
+        match params.command.as_str() {
+            SHOW_COMPILED_BASH_COMMAND => {
+                let uri = command_argument(&arguments, 0, "the document URI")?;
+
+                let compiled_bash = self.show_compiled_bash(uri).await?;
+
+                Ok(compiled_bash.map(|compiled_bash| serde_json::to_value(compiled_bash).unwrap()))
+            }
+            RUN_COMMAND | RUN_WITH_ARGUMENTS_COMMAND => {
+                let uri = command_argument(&arguments, 0, "the document URI")?;
+                let args = match params.command.as_str() {
+                    RUN_WITH_ARGUMENTS_COMMAND => {
+                        command_argument(&arguments, 1, "an array of the script arguments")?
+                    }
+                    _ => vec![],
+                };
+
+                let exit = self
+                    .run_script(uri, args, params.work_done_progress_params.work_done_token)
+                    .await?;
+
+                Ok(Some(serde_json::to_value(exit).unwrap()))
+            }
+            command => Err(Error::invalid_params(format!(
+                "Unknown command {}",
+                command
+            ))),
+        }
     }
 
     #[tracing::instrument(skip_all)]
@@ -745,16 +2037,36 @@ impl LanguageServer for Backend {
         Ok(definition)
     }
 
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
-        self.client
-            .log_message(MessageType::INFO, "configuration changed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Clients using the pull model don't send the settings with the notification
+        match params.settings.get(SETTINGS_SECTION) {
+            Some(settings) => {
+                let settings = self
+                    .parse_settings(vec![(None, settings.clone())])
+                    .await
+                    .remove(0);
+
+                self.update_settings(settings).await
+            }
+            None => self.pull_settings().await,
+        }
     }
 
-    async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
-        self.client
-            .log_message(MessageType::INFO, "workspace folders changed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.change_settings(|workspace_settings| {
+            for folder in params.event.removed {
+                workspace_settings.remove_folder(&folder.uri);
+            }
+
+            for folder in params.event.added {
+                workspace_settings.add_folder(folder.uri);
+            }
+        })
+        .await;
+
+        self.pull_settings().await;
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -770,24 +2082,36 @@ impl LanguageServer for Backend {
 
         let position = params.text_document_position_params.position;
 
-        let symbol_info = match self.get_symbol_at_position(file_id, position).await {
-            Some((symbol_info, _)) if !symbol_info.undefined => symbol_info,
+        let (symbol_info, offset) = match self.get_symbol_at_position(file_id, position).await {
+            Some((symbol_info, offset)) if !symbol_info.undefined => (symbol_info, offset),
             _ => {
                 return Ok(None);
             }
         };
 
+        let uri = &params.text_document_position_params.text_document.uri;
+
+        let external_stdlib = match self.get_symbol_definition_file(file_id, &symbol_info, offset) {
+            Some(definition_uri) => external_stdlib_dir_of(self, uri, &definition_uri).await,
+            None => None,
+        };
+
         Ok(Some(Hover {
             contents: HoverContents::Markup(MarkupContent {
                 kind: MarkupKind::Markdown,
                 value: format!(
-                    "```amber\n{}\n```{}",
+                    "```amber\n{}\n```{}{}",
                     symbol_info.to_string(&self.files.generic_types),
                     match symbol_info.symbol_type {
                         SymbolType::Function(FunctionSymbol { ref docs, .. }) if docs.is_some() =>
                             format!("\n{}", docs.clone().unwrap()),
                         _ => "".to_string(),
                     },
+                    match external_stdlib {
+                        Some(dir) =>
+                            format!("\n\n*From the external stdlib at `{}`*", dir.display()),
+                        None => "".to_string(),
+                    },
                 ),
             }),
             range: Some(Range {
@@ -803,6 +2127,231 @@ impl LanguageServer for Backend {
         }))
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let settings = self.settings_for(&params.text_document.uri).inlay_hints;
+
+        if !settings.enabled {
+            return Ok(None);
+        }
+
+        let file_id = match self.files.get(&params.text_document.uri) {
+            Some(file_id) => file_id,
+            None => return Ok(None),
+        };
+
+        let (rope, version) = match self.files.get_document_latest_version(file_id) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        if !self.files.is_file_analyzed(&(file_id, version)).await {
+            return Ok(None);
+        }
+
+        let symbol_table = match self.files.symbol_table.get(&(file_id, version)) {
+            Some(symbol_table) => symbol_table.clone(),
+            None => return Ok(None),
+        };
+
+        let mut hints = vec![];
+
+        if settings.variable_types {
+            for (_, symbol_info) in symbol_table.symbols.iter() {
+                if !matches!(symbol_info.symbol_type, SymbolType::Variable(_))
+                    || symbol_info.span.end > rope.len_chars()
+                {
+                    continue;
+                }
+
+                let position = self.offset_to_position(symbol_info.span.end, &rope);
+
+                if position < params.range.start || position > params.range.end {
+                    continue;
+                }
+
+                let data_type = if symbol_info.is_definition {
+                    // Only variables initialized with a value, as others already have their type written
+                    if rope
+                        .chars_at(symbol_info.span.end)
+                        .find(|c| !c.is_whitespace())
+                        != Some('=')
+                    {
+                        continue;
+                    }
+
+                    symbol_info.data_type.clone()
+                } else {
+                    // References only where an `is` check narrows the type of the variable
+                    match symbol_table.narrowed_type(&symbol_info.name, symbol_info.span.start) {
+                        Some(data_type) => data_type,
+                        None => continue,
+                    }
+                };
+
+                hints.push(InlayHint {
+                    position,
+                    label: InlayHintLabel::String(format!(
+                        ": {}",
+                        data_type.to_string(&self.files.generic_types)
+                    )),
+                    kind: Some(InlayHintKind::TYPE),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: None,
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+
+        Ok(Some(hints))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let file_id = match self.files.get(&uri) {
+            Some(file_id) => file_id,
+            None => return Ok(None),
+        };
+
+        let (rope, version) = match self.files.get_document_latest_version(file_id) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        if !self.files.is_file_analyzed(&(file_id, version)).await {
+            return Ok(None);
+        }
+
+        let diagnostics = match self.files.diagnostics.get(&(file_id, version)) {
+            Some(diagnostics) => diagnostics.clone(),
+            None => return Ok(None),
+        };
+
+        let settings = self.settings_for(&uri);
+
+        let actions = diagnostics
+            .iter()
+            .filter_map(|diagnostic| {
+                let fix = diagnostic.fix.as_ref()?;
+                let severity = settings.severity(diagnostic.kind)?;
+                let lsp_diagnostic = self.lsp_diagnostic(diagnostic, severity, &uri, &rope);
+
+                if lsp_diagnostic.range.end < params.range.start
+                    || lsp_diagnostic.range.start > params.range.end
+                {
+                    return None;
+                }
+
+                let edits = fix
+                    .edits
+                    .iter()
+                    .map(|(span, text)| {
+                        TextEdit::new(
+                            Range::new(
+                                self.offset_to_position(span.start, &rope),
+                                self.offset_to_position(span.end, &rope),
+                            ),
+                            text.clone(),
+                        )
+                    })
+                    .collect();
+
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.title.clone(),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![lsp_diagnostic]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some([(uri.clone(), edits)].into_iter().collect()),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(actions))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let file_id = match self.files.get(&params.text_document.uri) {
+            Some(file_id) => file_id,
+            None => return Ok(None),
+        };
+
+        let (rope, version) = match self.files.get_document_latest_version(file_id) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        if !self.files.is_file_analyzed(&(file_id, version)).await {
+            return Ok(None);
+        }
+
+        let scripts = self
+            .files
+            .shell_scripts
+            .get(&(file_id, version))
+            .map(|scripts| scripts.clone())
+            .unwrap_or_default();
+
+        let ranges = params
+            .positions
+            .iter()
+            .map(|position| {
+                let char = rope
+                    .try_line_to_char(position.line as usize)
+                    .ok()
+                    .unwrap_or(rope.len_chars());
+                let offset = char + position.character as usize;
+
+                // Commands can be nested in interpolations of other commands
+                let mut spans = scripts
+                    .iter()
+                    .flat_map(|(script, exp_span)| {
+                        let mut spans = script.selection_spans(offset);
+
+                        if !spans.is_empty() {
+                            spans.push(*exp_span);
+                        }
+
+                        spans
+                    })
+                    .collect::<Vec<_>>();
+
+                spans.sort_by_key(|span| (span.end - span.start, std::cmp::Reverse(span.start)));
+                spans.dedup();
+
+                let mut selection_range: Option<SelectionRange> = None;
+
+                for span in spans.iter().rev() {
+                    selection_range = Some(SelectionRange {
+                        range: Range::new(
+                            self.offset_to_position(span.start, &rope),
+                            self.offset_to_position(span.end, &rope),
+                        ),
+                        parent: selection_range.map(Box::new),
+                    });
+                }
+
+                selection_range.unwrap_or(SelectionRange {
+                    range: Range::new(*position, *position),
+                    parent: None,
+                })
+            })
+            .collect();
+
+        Ok(Some(ranges))
+    }
+
     #[tracing::instrument(skip_all)]
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         let uri = params.text_document_position.text_document.uri;
@@ -819,7 +2368,10 @@ impl LanguageServer for Backend {
         let symbol_info = match self.get_symbol_at_position(file_id, position).await {
             Some((symbol_info, _)) => symbol_info,
             None => {
-                return Ok(None);
+                return Ok(self
+                    .command_completions(&uri, file_id, position)
+                    .await
+                    .map(CompletionResponse::Array));
             }
         };
 
@@ -832,7 +2384,7 @@ impl LanguageServer for Backend {
 
         let completions = match symbol_info.symbol_type {
             SymbolType::ImportPath => {
-                let stdlib_paths = find_in_stdlib(self, &symbol_info.name).await;
+                let stdlib_paths = find_in_stdlib(self, &uri, &symbol_info.name).await;
 
                 if stdlib_paths.contains(&symbol_info.name) {
                     return Ok(None);
@@ -847,7 +2399,11 @@ impl LanguageServer for Backend {
                     })
                     .collect();
 
-                let file_path = uri.to_file_path().unwrap().canonicalize().unwrap();
+                let file_path = match uri.to_file_path() {
+                    Ok(file_path) => file_path.canonicalize().unwrap(),
+                    // Stdlib documents can only import other stdlib files
+                    Err(_) => return Ok(Some(CompletionResponse::Array(completions))),
+                };
                 let mut searched_path = file_path.parent().unwrap().to_path_buf();
                 searched_path.push(symbol_info.name.clone());
 
@@ -1090,3 +2646,16 @@ impl LanguageServer for Backend {
         }
     }
 }
+
+/// Deserialize the argument of an executed command.
+fn command_argument<T: DeserializeOwned>(
+    arguments: &[Value],
+    index: usize,
+    expected: &str,
+) -> Result<T> {
+    arguments
+        .get(index)
+        .cloned()
+        .and_then(|argument| serde_json::from_value(argument).ok())
+        .ok_or_else(|| Error::invalid_params(format!("Expected {}", expected)))
+}
@@ -0,0 +1,398 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::Command;
+use tower_lsp::lsp_types::Url;
+
+use crate::{backend::AmberVersion, grammar::Span};
+
+/// Command compiling a document to Bash. Takes the URI of the document as the argument
+/// and returns [`CompiledBash`].
+pub const SHOW_COMPILED_BASH_COMMAND: &str = "amber.showCompiledBash";
+
+/// Scheme of the virtual documents holding the compiled Bash.
+pub const COMPILED_BASH_SCHEME: &str = "amber-bash";
+
+/// Command running a saved document. Takes the URI of the document as the argument
+/// and returns [`ScriptExit`].
+pub const RUN_COMMAND: &str = "amber.run";
+
+/// Command running a saved document with arguments passed to its `main(args)` block.
+/// Takes the URI of the document and an array of the arguments, and returns [`ScriptExit`].
+pub const RUN_WITH_ARGUMENTS_COMMAND: &str = "amber.runWithArguments";
+
+#[derive(Debug, Error)]
+pub enum CompilerError {
+    #[error("Failed to run {0}: {1}")]
+    Spawn(PathBuf, std::io::Error),
+    #[error("{0} didn't finish within {1} ms")]
+    Timeout(PathBuf, u128),
+    #[error("Failed to write {0}: {1}")]
+    Write(PathBuf, std::io::Error),
+    #[error("Amber {0} can't pass arguments to the script")]
+    ArgumentsNotSupported(AmberVersion),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageLevel {
+    Error,
+    Warning,
+}
+
+/// An error or warning printed by the compiler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilerMessage {
+    pub level: MessageLevel,
+    pub message: String,
+    /// File the message points at, with 1-based line and column.
+    pub location: Option<(PathBuf, usize, usize)>,
+}
+
+/// Result of the [`SHOW_COMPILED_BASH_COMMAND`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompiledBash {
+    /// URI of the virtual document, in the [`COMPILED_BASH_SCHEME`].
+    pub uri: Url,
+    pub text: String,
+}
+
+/// Result of the [`RUN_COMMAND`] and [`RUN_WITH_ARGUMENTS_COMMAND`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptExit {
+    /// Exit code of the script, [`None`] if it was terminated by a signal.
+    pub exit_code: Option<i32>,
+    pub success: bool,
+}
+
+/// Output of a compilation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compilation {
+    /// The generated script, [`None`] if the compilation failed.
+    pub bash: Option<String>,
+    pub messages: Vec<CompilerMessage>,
+}
+
+/// URI of the virtual document with the Bash compiled from the document.
+pub fn compiled_bash_uri(uri: &Url) -> Url {
+    let path = uri.path();
+    let path = path.strip_suffix(".ab").unwrap_or(path);
+
+    Url::parse(&format!("{}:{}.sh", COMPILED_BASH_SCHEME, path)).unwrap()
+}
+
+/// Arguments that make the compiler check the file without running it.
+fn check_args(amber_version: &AmberVersion, file_path: &Path) -> Vec<OsString> {
+    match amber_version {
+        AmberVersion::Alpha040 => vec!["check".into(), file_path.into()],
+        // Older versions run the script unless it's compiled into a file
+        AmberVersion::Alpha034 | AmberVersion::Alpha035 => {
+            let null_device = if cfg!(windows) { "NUL" } else { "/dev/null" };
+
+            vec![file_path.into(), null_device.into()]
+        }
+    }
+}
+
+/// Arguments that make the compiler write the Bash script to the output file.
+fn build_args(amber_version: &AmberVersion, input: &Path, output: &Path) -> Vec<OsString> {
+    match amber_version {
+        AmberVersion::Alpha040 => vec!["build".into(), input.into(), output.into()],
+        AmberVersion::Alpha034 | AmberVersion::Alpha035 => vec![input.into(), output.into()],
+    }
+}
+
+/// Command running the script with the arguments, with its output piped.
+pub fn script_command(
+    compiler: &Path,
+    amber_version: &AmberVersion,
+    file_path: &Path,
+    args: &[String],
+) -> Result<Command, CompilerError> {
+    let mut command = Command::new(compiler);
+
+    match amber_version {
+        AmberVersion::Alpha040 => {
+            command.arg("run").arg(file_path).args(args);
+        }
+        // Older versions run the compiled script without any arguments
+        AmberVersion::Alpha034 | AmberVersion::Alpha035 if !args.is_empty() => {
+            return Err(CompilerError::ArgumentsNotSupported(amber_version.clone()));
+        }
+        AmberVersion::Alpha034 | AmberVersion::Alpha035 => {
+            command.arg(file_path);
+        }
+    }
+
+    if let Some(dir) = file_path.parent() {
+        command.current_dir(dir);
+    }
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    Ok(command)
+}
+
+async fn run(
+    compiler: &Path,
+    args: Vec<OsString>,
+    current_dir: Option<&Path>,
+    timeout: Duration,
+) -> Result<Output, CompilerError> {
+    let mut command = Command::new(compiler);
+    command
+        .args(args)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    if let Some(dir) = current_dir {
+        command.current_dir(dir);
+    }
+
+    tokio::time::timeout(timeout, command.output())
+        .await
+        .map_err(|_| CompilerError::Timeout(compiler.to_path_buf(), timeout.as_millis()))?
+        .map_err(|err| CompilerError::Spawn(compiler.to_path_buf(), err))
+}
+
+fn output_messages(output: &Output) -> Vec<CompilerMessage> {
+    let mut text = String::from_utf8_lossy(&output.stderr).to_string();
+    text.push('\n');
+    text.push_str(&String::from_utf8_lossy(&output.stdout));
+
+    parse_output(&text)
+}
+
+/// Check the file with the compiler, returning the messages it printed.
+pub async fn check(
+    compiler: &Path,
+    amber_version: &AmberVersion,
+    file_path: &Path,
+    timeout: Duration,
+) -> Result<Vec<CompilerMessage>, CompilerError> {
+    let output = run(
+        compiler,
+        check_args(amber_version, file_path),
+        None,
+        timeout,
+    )
+    .await?;
+
+    Ok(output_messages(&output))
+}
+
+/// Compile the text of the document at `file_path` to Bash.
+///
+/// The text doesn't have to be saved. It's written to a hidden file next to the document,
+/// so that relative imports resolve as they would for the document itself, and removed
+/// afterwards. Documents without a directory on disk are written to a temporary directory
+/// of their own. Messages about the written file point at `file_path` instead.
+pub async fn compile(
+    compiler: &Path,
+    amber_version: &AmberVersion,
+    file_path: &Path,
+    text: &str,
+    timeout: Duration,
+) -> Result<Compilation, CompilerError> {
+    static COMPILATION_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let compilation_id = format!(
+        "amber-lsp-{}-{}",
+        std::process::id(),
+        COMPILATION_ID.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "main.ab".to_string());
+
+    let document_dir = match file_path.parent() {
+        Some(dir)
+            if tokio::fs::metadata(dir)
+                .await
+                .is_ok_and(|meta| meta.is_dir()) =>
+        {
+            Some(dir)
+        }
+        _ => None,
+    };
+
+    let (temp_dir, input) = match document_dir {
+        Some(dir) => (
+            None,
+            dir.join(format!(".{}.{}.ab", file_name, compilation_id)),
+        ),
+        None => {
+            let dir = std::env::temp_dir().join(compilation_id);
+            let input = dir.join(&file_name);
+
+            (Some(dir), input)
+        }
+    };
+    let output_path = input.with_extension("sh");
+
+    let remove_files = || async {
+        let _ = tokio::fs::remove_file(&input).await;
+        let _ = tokio::fs::remove_file(&output_path).await;
+
+        if let Some(dir) = &temp_dir {
+            let _ = tokio::fs::remove_dir_all(dir).await;
+        }
+    };
+
+    let written = async {
+        if let Some(dir) = &temp_dir {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        tokio::fs::write(&input, text).await
+    }
+    .await;
+
+    if let Err(err) = written {
+        remove_files().await;
+
+        return Err(CompilerError::Write(input, err));
+    }
+
+    let output = run(
+        compiler,
+        build_args(amber_version, &input, &output_path),
+        document_dir,
+        timeout,
+    )
+    .await;
+
+    let bash = tokio::fs::read_to_string(&output_path).await.ok();
+    remove_files().await;
+
+    let output = output?;
+
+    let messages = output_messages(&output)
+        .into_iter()
+        .map(|message| match message.location {
+            Some((path, line, column)) if input.ends_with(&path) => CompilerMessage {
+                location: Some((file_path.to_path_buf(), line, column)),
+                ..message
+            },
+            _ => message,
+        })
+        .collect();
+
+    Ok(Compilation {
+        bash: bash.filter(|_| output.status.success()),
+        messages,
+    })
+}
+
+/// Parse messages printed by the compiler.
+///
+/// Each message starts with a ` ERROR ` or ` WARN ` header, followed by an `at file:line:column`
+/// line. The code snippet and comments printed after them are skipped.
+pub fn parse_output(output: &str) -> Vec<CompilerMessage> {
+    let mut messages: Vec<CompilerMessage> = vec![];
+
+    for line in strip_ansi_codes(output).lines() {
+        let line = line.trim();
+
+        let header = [
+            ("ERROR", MessageLevel::Error),
+            ("WARNING", MessageLevel::Warning),
+            ("WARN", MessageLevel::Warning),
+        ]
+        .into_iter()
+        .find_map(|(prefix, level)| {
+            let message = line.strip_prefix(prefix)?;
+
+            match message.chars().next() {
+                None | Some(' ') | Some(':') => Some((level, message)),
+                _ => None,
+            }
+        });
+
+        if let Some((level, message)) = header {
+            messages.push(CompilerMessage {
+                level,
+                message: message.trim_start_matches(':').trim().to_string(),
+                location: None,
+            });
+            continue;
+        }
+
+        let location = match line.strip_prefix("at ").and_then(parse_location) {
+            Some(location) => location,
+            None => continue,
+        };
+
+        if let Some(message) = messages.last_mut() {
+            if message.location.is_none() {
+                message.location = Some(location);
+            }
+        }
+    }
+
+    messages
+}
+
+fn parse_location(location: &str) -> Option<(PathBuf, usize, usize)> {
+    let mut parts = location.rsplitn(3, ':');
+
+    let column = parts.next()?.trim().parse().ok()?;
+    let line = parts.next()?.trim().parse().ok()?;
+    let path = parts.next()?;
+
+    Some((PathBuf::from(path), line, column))
+}
+
+fn strip_ansi_codes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+
+        // Skip the escape sequence up to its final letter
+        for c in chars.by_ref() {
+            if c.is_ascii_alphabetic() {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+/// Span of the word at the 1-based line and column of the document.
+pub fn location_span(rope: &Rope, line: usize, column: usize) -> Span {
+    let line = line
+        .saturating_sub(1)
+        .min(rope.len_lines().saturating_sub(1));
+    let line_start = rope.line_to_char(line);
+    let line_len = rope.line(line).len_chars();
+
+    let start = line_start + column.saturating_sub(1).min(line_len);
+
+    let word_len = rope
+        .chars_at(start)
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .count();
+
+    Span::new(start, start + word_len.max(1))
+}
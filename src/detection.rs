@@ -0,0 +1,204 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::{
+    backend::AmberVersion,
+    fs::FS,
+    grammar::{Spanned, Token},
+};
+
+/// Name of the file pinning the Amber version of a project.
+///
+/// It contains just the version, e.g. `0.4.0-alpha`, and applies to every file
+/// in its directory and subdirectories.
+pub const CONFIG_FILE: &str = ".amber-version";
+
+/// How long the compiler binary gets to print its version.
+const COMPILER_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Amber version picked for a document, with the reason it was chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedVersion {
+    pub version: AmberVersion,
+    pub reason: String,
+}
+
+/// Parse a version string such as `0.3.5-alpha` or `amber-0.4.0-alpha`.
+pub fn parse_version(text: &str) -> Option<AmberVersion> {
+    if text.contains("0.3.4") {
+        Some(AmberVersion::Alpha034)
+    } else if text.contains("0.3.5") {
+        Some(AmberVersion::Alpha035)
+    } else if text.contains("0.4.0") {
+        Some(AmberVersion::Alpha040)
+    } else {
+        None
+    }
+}
+
+/// Look for the [`CONFIG_FILE`] in the directory of the document and its ancestors.
+pub async fn from_config_file(fs: &dyn FS, file_path: &Path) -> Option<DetectedVersion> {
+    for dir in file_path.ancestors().skip(1) {
+        let config_path = dir.join(CONFIG_FILE);
+
+        if !fs.exists(&config_path).await {
+            continue;
+        }
+
+        let content = fs.read(&config_path).await.ok()?;
+
+        return match parse_version(content.trim()) {
+            Some(version) => Some(DetectedVersion {
+                version,
+                reason: format!("pinned in {}", config_path.display()),
+            }),
+            None => {
+                warn!("Unknown Amber version in {}", config_path.display());
+                None
+            }
+        };
+    }
+
+    None
+}
+
+/// Read the version from a shebang naming a versioned binary, e.g. `#!/usr/bin/env amber-0.3.5-alpha`.
+pub fn from_shebang(text: &str) -> Option<DetectedVersion> {
+    let shebang = text.lines().next()?.strip_prefix("#!")?;
+
+    let version = shebang
+        .split_whitespace()
+        .filter(|word| word.contains("amber"))
+        .find_map(parse_version)?;
+
+    Some(DetectedVersion {
+        version,
+        reason: format!("named in the shebang `#!{}`", shebang.trim()),
+    })
+}
+
+/// Ask the compiler binary for its version.
+pub async fn from_compiler(binary: &Path) -> Option<AmberVersion> {
+    let mut command = Command::new(binary);
+    command.arg("-V").stdin(Stdio::null()).kill_on_drop(true);
+
+    let output = match tokio::time::timeout(COMPILER_VERSION_TIMEOUT, command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => {
+            warn!("Failed to execute {}: {}", binary.display(), err);
+            return None;
+        }
+        Err(_) => {
+            warn!("{} didn't print its version in time", binary.display());
+            return None;
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .last()
+        .and_then(parse_version)
+}
+
+/// Guess the version from language features used in the document.
+///
+/// Every feature rules out the versions it's not available in, the latest of
+/// the remaining versions is picked.
+pub fn from_syntax(tokens: &[Spanned<Token>]) -> Option<DetectedVersion> {
+    let mut candidates = vec![
+        AmberVersion::Alpha034,
+        AmberVersion::Alpha035,
+        AmberVersion::Alpha040,
+    ];
+    let mut reasons = vec![];
+
+    let mut in_command = false;
+    let mut in_text = false;
+
+    for (idx, (Token(word), _)) in tokens.iter().enumerate() {
+        match word.as_str() {
+            "$" if !in_text => in_command = !in_command,
+            "\"" if !in_command => in_text = !in_text,
+            _ => {}
+        }
+
+        if in_command || in_text {
+            continue;
+        }
+
+        let (feature, versions) = match word.as_str() {
+            "exit" | "trust" => (format!("`{}`", word), vec![AmberVersion::Alpha040]),
+            "const" | "cd" | "mv" => (
+                format!("`{}`", word),
+                vec![AmberVersion::Alpha035, AmberVersion::Alpha040],
+            ),
+            "from" => {
+                let path = match import_path(&tokens[idx + 1..]) {
+                    Some(path) => path,
+                    None => continue,
+                };
+
+                if path == "std" {
+                    (
+                        "`import * from \"std\"`".to_string(),
+                        vec![AmberVersion::Alpha034],
+                    )
+                } else if path.starts_with("std/") {
+                    (
+                        format!("`import ... from \"{}\"`", path),
+                        vec![AmberVersion::Alpha035, AmberVersion::Alpha040],
+                    )
+                } else {
+                    continue;
+                }
+            }
+            _ => continue,
+        };
+
+        let narrowed = candidates
+            .iter()
+            .filter(|version| versions.contains(version))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Conflicting features, keep what was found so far
+        if narrowed.is_empty() || narrowed.len() == candidates.len() {
+            continue;
+        }
+
+        candidates = narrowed;
+        reasons.push(feature);
+    }
+
+    if reasons.is_empty() {
+        return None;
+    }
+
+    Some(DetectedVersion {
+        version: candidates.last()?.clone(),
+        reason: format!("the document uses {}", reasons.join(", ")),
+    })
+}
+
+/// Join the tokens of the string literal at the start of `tokens`.
+fn import_path(tokens: &[Spanned<Token>]) -> Option<String> {
+    let (Token(quote), _) = tokens.first()?;
+
+    if quote != "\"" {
+        return None;
+    }
+
+    let end = tokens
+        .iter()
+        .skip(1)
+        .position(|(Token(word), _)| word == "\"")?;
+
+    Some(
+        tokens[1..=end]
+            .iter()
+            .map(|(Token(word), _)| word.as_str())
+            .collect(),
+    )
+}
@@ -0,0 +1,147 @@
+use serde::Deserialize;
+use tower_lsp::lsp_types::{DiagnosticSeverity, DiagnosticTag};
+
+use crate::grammar::Span;
+
+/// Kind of a diagnostic reported by the server.
+///
+/// Each kind can have its severity configured by the user, see
+/// [`crate::settings::Settings::diagnostics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticKind {
+    /// Errors reported by the parser.
+    Syntax,
+    /// Errors reported during semantic analysis.
+    Analysis,
+    /// Errors reported by the `amber` compiler.
+    CompilerError,
+    /// Warnings reported by the `amber` compiler.
+    CompilerWarning,
+    /// Statements that can never run.
+    UnreachableCode,
+    /// Functions declared to return a value that can end without returning one.
+    MissingReturn,
+    /// `else` blocks holding just another condition, allowed by `#[allow_nested_if_else]`.
+    NestedIfElse,
+    /// Functions returning a generic type, allowed by `#[allow_generic_return]`.
+    GenericReturn,
+    /// Casts between incompatible types, allowed by `#[allow_absurd_cast]`.
+    AbsurdCast,
+    /// `trust` and `unsafe` modifiers on calls to functions that can't fail.
+    UnnecessaryTrust,
+    /// `?` used in functions declared with a return type that isn't failable.
+    MisplacedPropagation,
+    /// Variables declared without a value, read before they're assigned one.
+    UnassignedVariable,
+    /// Imports of files with a `main` block or statements running once they're imported.
+    ImportSideEffects,
+    /// Definitions shadowing a symbol imported into the file.
+    ShadowedImport,
+    /// Values taken from the script arguments run in commands or used as `cd` and `mv` paths.
+    TaintedCommand,
+    /// Values that may be empty given to commands removing files, like `rm -rf`.
+    EmptyDestructiveArgument,
+    /// Commands removing files run with `silent`, `unsafe` or `trust`.
+    SilencedDestructiveCommand,
+    /// Commands doing what a function of the standard library already does.
+    StdlibAlternative,
+    /// Commands running programs that aren't found on `PATH`.
+    MissingCommand,
+}
+
+impl DiagnosticKind {
+    /// Code attached to the diagnostic sent to the client.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Syntax => "syntax",
+            DiagnosticKind::Analysis => "analysis",
+            DiagnosticKind::CompilerError => "compilerError",
+            DiagnosticKind::CompilerWarning => "compilerWarning",
+            DiagnosticKind::UnreachableCode => "unreachableCode",
+            DiagnosticKind::MissingReturn => "missingReturn",
+            DiagnosticKind::NestedIfElse => "nestedIfElse",
+            DiagnosticKind::GenericReturn => "genericReturn",
+            DiagnosticKind::AbsurdCast => "absurdCast",
+            DiagnosticKind::UnnecessaryTrust => "unnecessaryTrust",
+            DiagnosticKind::MisplacedPropagation => "misplacedPropagation",
+            DiagnosticKind::UnassignedVariable => "unassignedVariable",
+            DiagnosticKind::ImportSideEffects => "importSideEffects",
+            DiagnosticKind::ShadowedImport => "shadowedImport",
+            DiagnosticKind::TaintedCommand => "taintedCommand",
+            DiagnosticKind::EmptyDestructiveArgument => "emptyDestructiveArgument",
+            DiagnosticKind::SilencedDestructiveCommand => "silencedDestructiveCommand",
+            DiagnosticKind::StdlibAlternative => "stdlibAlternative",
+            DiagnosticKind::MissingCommand => "missingCommand",
+        }
+    }
+
+    pub fn default_severity(&self) -> DiagnosticSeverity {
+        match self {
+            DiagnosticKind::Syntax => DiagnosticSeverity::ERROR,
+            DiagnosticKind::Analysis => DiagnosticSeverity::ERROR,
+            DiagnosticKind::CompilerError => DiagnosticSeverity::ERROR,
+            DiagnosticKind::CompilerWarning => DiagnosticSeverity::WARNING,
+            DiagnosticKind::UnreachableCode => DiagnosticSeverity::WARNING,
+            DiagnosticKind::MissingReturn => DiagnosticSeverity::ERROR,
+            DiagnosticKind::NestedIfElse => DiagnosticSeverity::WARNING,
+            DiagnosticKind::GenericReturn => DiagnosticSeverity::WARNING,
+            DiagnosticKind::AbsurdCast => DiagnosticSeverity::WARNING,
+            DiagnosticKind::UnnecessaryTrust => DiagnosticSeverity::WARNING,
+            DiagnosticKind::MisplacedPropagation => DiagnosticSeverity::WARNING,
+            DiagnosticKind::UnassignedVariable => DiagnosticSeverity::WARNING,
+            DiagnosticKind::ImportSideEffects => DiagnosticSeverity::WARNING,
+            DiagnosticKind::ShadowedImport => DiagnosticSeverity::WARNING,
+            DiagnosticKind::TaintedCommand => DiagnosticSeverity::WARNING,
+            DiagnosticKind::EmptyDestructiveArgument => DiagnosticSeverity::WARNING,
+            DiagnosticKind::SilencedDestructiveCommand => DiagnosticSeverity::WARNING,
+            DiagnosticKind::StdlibAlternative => DiagnosticSeverity::INFORMATION,
+            DiagnosticKind::MissingCommand => DiagnosticSeverity::WARNING,
+        }
+    }
+
+    /// Tags attached to the diagnostic sent to the client.
+    pub fn tags(&self) -> Option<Vec<DiagnosticTag>> {
+        match self {
+            DiagnosticKind::UnreachableCode | DiagnosticKind::UnnecessaryTrust => {
+                Some(vec![DiagnosticTag::UNNECESSARY])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A diagnostic found in a file, before it's converted to the LSP representation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub span: Span,
+    /// Other places in the file involved in the problem, with a message for each.
+    pub related: Vec<(String, Span)>,
+    /// Edit of the file fixing the problem.
+    pub fix: Option<QuickFix>,
+}
+
+/// Edits of a file offered to fix a diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickFix {
+    pub title: String,
+    /// Text replacing each span of the file.
+    pub edits: Vec<(Span, String)>,
+}
+
+impl FileDiagnostic {
+    /// Whether both diagnostics most likely report the same problem.
+    ///
+    /// Used to drop compiler diagnostics already reported by the language server. Both have
+    /// to start at the same place with the same severity, or have the same message and
+    /// overlapping spans.
+    pub fn is_duplicate_of(&self, other: &FileDiagnostic) -> bool {
+        let same_severity = self.kind.default_severity() == other.kind.default_severity();
+        let overlaps = self.span.start < other.span.end && other.span.start < self.span.end;
+
+        (self.span.start == other.span.start && same_severity)
+            || (self.message.eq_ignore_ascii_case(&other.message) && overlaps)
+    }
+}
@@ -8,10 +8,12 @@ use tracing::info;
 
 use crate::{
     analysis::{types::GenericsMap, SymbolTable},
+    backend::AmberVersion,
+    diagnostics::{DiagnosticKind, FileDiagnostic, QuickFix},
     fs::FS,
-    grammar::{Grammar, Spanned, SpannedSemanticToken},
+    grammar::{shell::ShellScript, Grammar, Spanned, SpannedSemanticToken},
     paths::{FileId, PathInterner},
-    utils::FastDashMap,
+    utils::{FastDashMap, FastDashSet},
 };
 
 #[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
@@ -38,14 +40,22 @@ pub struct Files {
     paths: PathInterner,
     file_versions: FastDashMap<FileId, FileVersion>,
     file_dependencies: FastDashMap<(FileId, FileVersion), Vec<FileId>>,
+    /// Files that are currently open in the editor.
+    open_files: FastDashSet<FileId>,
     pub analyze_lock: FastDashMap<(FileId, FileVersion), Arc<RwLock<bool>>>,
     pub fs: Arc<dyn FS>,
     pub ast_map: FastDashMap<(FileId, FileVersion), Grammar>,
-    pub errors: FastDashMap<(FileId, FileVersion), Vec<Spanned<String>>>,
+    pub diagnostics: FastDashMap<(FileId, FileVersion), Vec<FileDiagnostic>>,
+    /// Diagnostics reported by the compiler for saved versions of files.
+    pub compiler_diagnostics: FastDashMap<(FileId, FileVersion), Vec<FileDiagnostic>>,
     pub document_map: FastDashMap<(FileId, FileVersion), Rope>,
     pub semantic_token_map: FastDashMap<(FileId, FileVersion), Vec<SpannedSemanticToken>>,
     pub symbol_table: FastDashMap<(FileId, FileVersion), SymbolTable>,
+    /// Shell scripts of the command literals, with the spans of their command expressions.
+    pub shell_scripts: FastDashMap<(FileId, FileVersion), Vec<Spanned<ShellScript>>>,
     pub generic_types: GenericsMap,
+    /// Amber versions detected for files.
+    pub amber_versions: FastDashMap<FileId, AmberVersion>,
 }
 
 pub const DEFAULT_VERSION: FileVersion = FileVersion(1);
@@ -57,12 +67,16 @@ impl Files {
             fs,
             file_versions: FastDashMap::default(),
             file_dependencies: FastDashMap::default(),
+            open_files: FastDashSet::default(),
             ast_map: FastDashMap::default(),
-            errors: FastDashMap::default(),
+            diagnostics: FastDashMap::default(),
+            compiler_diagnostics: FastDashMap::default(),
             document_map: FastDashMap::default(),
             semantic_token_map: FastDashMap::default(),
             symbol_table: FastDashMap::default(),
+            shell_scripts: FastDashMap::default(),
             generic_types: GenericsMap::new(),
+            amber_versions: FastDashMap::default(),
             analyze_lock: FastDashMap::default(),
         }
     }
@@ -78,10 +92,47 @@ impl Files {
         self.paths.lookup(file_id)
     }
 
+    /// Get the id of a file that is currently loaded.
+    ///
+    /// Returns [`None`] if the file was never loaded or has been evicted.
     pub fn get(&self, url: &Url) -> Option<FileId> {
+        self.paths
+            .get(url)
+            .filter(|file_id| self.file_versions.contains_key(file_id))
+    }
+
+    /// Get the id of a file, whether it is loaded or not.
+    ///
+    /// Returns [`None`] if the file was never referenced.
+    pub fn get_id(&self, url: &Url) -> Option<FileId> {
         self.paths.get(url)
     }
 
+    pub fn mark_as_open(&self, file_id: FileId) {
+        self.open_files.insert(file_id);
+    }
+
+    pub fn mark_as_closed(&self, file_id: FileId) {
+        self.open_files.remove(&file_id);
+    }
+
+    pub fn is_open(&self, file_id: FileId) -> bool {
+        self.open_files.contains(&file_id)
+    }
+
+    /// Returns ids of all files that are currently open in the editor.
+    pub fn get_open_files(&self) -> Vec<FileId> {
+        self.open_files.iter().map(|file_id| *file_id).collect()
+    }
+
+    /// Returns ids of all files that are currently loaded.
+    pub fn get_loaded_files(&self) -> Vec<FileId> {
+        self.file_versions
+            .iter()
+            .map(|entry| *entry.key())
+            .collect()
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn add_new_file_version(&self, file_id: FileId, version: FileVersion) {
         if let Some(old_version) = self.file_versions.insert(file_id, version) {
@@ -95,13 +146,49 @@ impl Files {
 
     #[tracing::instrument(skip_all)]
     fn remove_file_version(&self, file_id: FileId, version: FileVersion) {
-        self.ast_map.remove(&(file_id, version));
-        self.errors.remove(&(file_id, version));
+        self.invalidate(&(file_id, version));
         self.document_map.remove(&(file_id, version));
-        self.semantic_token_map.remove(&(file_id, version));
-        self.symbol_table.remove(&(file_id, version));
-        self.generic_types.clean(file_id, version);
-        self.file_dependencies.remove(&(file_id, version));
+        self.compiler_diagnostics.remove(&(file_id, version));
+    }
+
+    /// Drop analysis results of a file version, keeping its document.
+    ///
+    /// The next call to analyze this version will start from scratch.
+    #[tracing::instrument(skip_all)]
+    pub fn invalidate(&self, file: &(FileId, FileVersion)) {
+        self.ast_map.remove(file);
+        self.diagnostics.remove(file);
+        self.semantic_token_map.remove(file);
+        self.symbol_table.remove(file);
+        self.shell_scripts.remove(file);
+        self.generic_types.clean(file.0, file.1);
+        self.file_dependencies.remove(file);
+        self.analyze_lock.remove(file);
+    }
+
+    /// Remove every version of the file from memory.
+    #[tracing::instrument(skip_all)]
+    pub fn remove_file(&self, file_id: FileId) {
+        let mut versions = self
+            .document_map
+            .iter()
+            .map(|entry| *entry.key())
+            .chain(self.symbol_table.iter().map(|entry| *entry.key()))
+            .chain(self.analyze_lock.iter().map(|entry| *entry.key()))
+            .filter(|(id, _)| *id == file_id)
+            .map(|(_, version)| version)
+            .collect::<Vec<_>>();
+
+        versions.sort_unstable_by_key(|version| version.0);
+        versions.dedup();
+
+        for version in versions {
+            self.remove_file_version(file_id, version);
+        }
+
+        self.file_versions.remove(&file_id);
+        self.open_files.remove(&file_id);
+        self.amber_versions.remove(&file_id);
     }
 
     pub fn get_latest_version(&self, file_id: FileId) -> FileVersion {
@@ -109,7 +196,7 @@ impl Files {
     }
 
     pub fn get_document_latest_version(&self, file_id: FileId) -> Option<(Rope, FileVersion)> {
-        let file_version = self.get_latest_version(file_id);
+        let file_version = *self.file_versions.get(&file_id)?;
 
         self.document_map
             .get(&(file_id, file_version))
@@ -117,12 +204,57 @@ impl Files {
     }
 
     pub fn report_error(&self, file: &(FileId, FileVersion), msg: &str, span: SimpleSpan) {
-        let mut errors = match self.errors.get(file) {
-            Some(errors) => errors.clone(),
-            None => vec![],
-        };
-        errors.push((msg.to_string(), span));
-        self.errors.insert(*file, errors);
+        self.report_diagnostic(file, DiagnosticKind::Analysis, msg, span);
+    }
+
+    pub fn report_diagnostic(
+        &self,
+        file: &(FileId, FileVersion),
+        kind: DiagnosticKind,
+        msg: &str,
+        span: SimpleSpan,
+    ) {
+        self.report_diagnostic_with_related(file, kind, msg, span, vec![]);
+    }
+
+    pub fn report_diagnostic_with_related(
+        &self,
+        file: &(FileId, FileVersion),
+        kind: DiagnosticKind,
+        msg: &str,
+        span: SimpleSpan,
+        related: Vec<(String, SimpleSpan)>,
+    ) {
+        self.diagnostics
+            .entry(*file)
+            .or_default()
+            .push(FileDiagnostic {
+                kind,
+                message: msg.to_string(),
+                span,
+                related,
+                fix: None,
+            });
+    }
+
+    pub fn report_diagnostic_with_fix(
+        &self,
+        file: &(FileId, FileVersion),
+        kind: DiagnosticKind,
+        msg: &str,
+        span: SimpleSpan,
+        fix: QuickFix,
+    ) {
+        self.diagnostics
+            .entry(*file)
+            .or_default()
+            .push(FileDiagnostic {
+                kind,
+                message: msg.to_string(),
+                span,
+                related: vec![],
+                fix: Some(fix),
+            });
     }
 
     #[tracing::instrument(skip_all)]
@@ -139,6 +271,7 @@ impl Files {
         }
     }
 
+    /// Returns latest versions of files that import `file_id`.
     pub fn get_files_dependant_on(&self, file_id: FileId) -> Vec<(FileId, FileVersion)> {
         let mut dependant_files = self
             .file_dependencies
@@ -147,7 +280,9 @@ impl Files {
                 let file = file_ref.key();
                 let file_deps = file_ref.value();
 
-                if file_deps.contains(&file_id) {
+                if file_deps.contains(&file_id)
+                    && self.file_versions.get(&file.0).map(|version| *version) == Some(file.1)
+                {
                     Some(*file)
                 } else {
                     None
@@ -155,22 +290,111 @@ impl Files {
             })
             .collect::<Vec<_>>();
 
+        dependant_files.sort_unstable_by_key(|(file_id, _)| file_id.0);
         dependant_files.dedup_by_key(|(file_id, _)| *file_id);
 
         dependant_files
     }
 
+    /// Returns latest versions of files that import `file_id`, directly or not.
+    ///
+    /// Every file is listed once, after the files it imports from the same list,
+    /// so analyzing them in order sees the updated imports.
+    pub fn get_files_dependant_on_transitively(
+        &self,
+        file_id: FileId,
+    ) -> Vec<(FileId, FileVersion)> {
+        let mut dependant_files = vec![];
+        let mut queue = vec![file_id];
+
+        while let Some(file_id) = queue.pop() {
+            for file in self.get_files_dependant_on(file_id) {
+                if !dependant_files.contains(&file) {
+                    dependant_files.push(file);
+                    queue.push(file.0);
+                }
+            }
+        }
+
+        dependant_files.retain(|(dep_file_id, _)| *dep_file_id != file_id);
+
+        let imports = |file: &(FileId, FileVersion)| {
+            self.file_dependencies
+                .get(file)
+                .map(|deps| deps.clone())
+                .unwrap_or_default()
+        };
+
+        let mut ordered_files = vec![];
+
+        while !dependant_files.is_empty() {
+            // Files importing each other in a cycle are taken in any order
+            let next = dependant_files
+                .iter()
+                .position(|file| {
+                    !imports(file)
+                        .iter()
+                        .any(|dep| dependant_files.iter().any(|(id, _)| id == dep))
+                })
+                .unwrap_or(0);
+
+            ordered_files.push(dependant_files.remove(next));
+        }
+
+        ordered_files
+    }
+
+    /// Returns open files together with every file they import, directly or not.
+    pub fn get_files_in_use(&self) -> Vec<FileId> {
+        let mut files_in_use = vec![];
+        let mut queue = self
+            .open_files
+            .iter()
+            .map(|file_id| *file_id)
+            .collect::<Vec<_>>();
+
+        while let Some(file_id) = queue.pop() {
+            if files_in_use.contains(&file_id) {
+                continue;
+            }
+
+            files_in_use.push(file_id);
+
+            let version = match self.file_versions.get(&file_id) {
+                Some(version) => *version,
+                None => continue,
+            };
+
+            if let Some(deps) = self.file_dependencies.get(&(file_id, version)) {
+                queue.extend(deps.iter().copied());
+            }
+        }
+
+        files_in_use
+    }
+
     pub fn add_file_dependency(&self, file: &(FileId, FileVersion), dependency: FileId) {
         let mut dependencies = self.file_dependencies.entry(*file).or_insert(vec![]);
 
         dependencies.push(dependency);
     }
 
+    /// Record an import of a file that doesn't exist yet, so that the importing
+    /// file is analyzed again once it gets created.
+    pub fn add_missing_file_dependency(&self, file: &(FileId, FileVersion), url: Url) {
+        let dependency = self.paths.insert(url);
+
+        self.add_file_dependency(file, dependency);
+    }
+
     pub fn is_depending_on(&self, file: &(FileId, FileVersion), dependency: FileId) -> bool {
         match self.file_dependencies.get(file) {
             Some(deps) => deps.iter().any(|dep| {
                 *dep == dependency
-                    || self.is_depending_on(&(*dep, self.get_latest_version(*dep)), dependency)
+                    || match self.file_versions.get(dep).map(|version| *version) {
+                        Some(version) => self.is_depending_on(&(*dep, version), dependency),
+                        None => false,
+                    }
             }),
             None => false,
         }
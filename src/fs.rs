@@ -2,10 +2,11 @@ use std::{
     collections::HashMap,
     fmt::Debug,
     future::Future,
-    io::Result,
+    io::{Error, ErrorKind, Result},
     path::{Path, PathBuf},
     pin::Pin,
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
 use tokio::fs::{create_dir_all, metadata, read_dir, read_to_string, write};
@@ -29,11 +30,17 @@ pub trait FS: Sync + Send + Debug {
         &'a self,
         path: &'a Path,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+    /// Time the file was last modified.
+    fn modified<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = Result<SystemTime>> + Send + 'a>>;
 }
 
 #[derive(Debug)]
 pub struct MemoryFS {
     files: Arc<Mutex<HashMap<String, String>>>,
+    modified: Arc<Mutex<HashMap<String, SystemTime>>>,
 }
 
 impl Default for MemoryFS {
@@ -46,8 +53,19 @@ impl MemoryFS {
     pub fn new() -> Self {
         MemoryFS {
             files: Arc::new(Mutex::new(HashMap::new())),
+            modified: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// Remove the file, failing if it doesn't exist.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        self.modified.lock().unwrap().remove(path.to_str().unwrap());
+        files
+            .remove(path.to_str().unwrap())
+            .map(|_| ())
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
 }
 
 impl FS for MemoryFS {
@@ -57,7 +75,10 @@ impl FS for MemoryFS {
     ) -> Pin<Box<(dyn Future<Output = Result<String>> + Send + 'a)>> {
         Box::pin(async move {
             let files = self.files.lock().unwrap();
-            Ok(files.get(path.to_str().unwrap()).unwrap().clone())
+            files
+                .get(path.to_str().unwrap())
+                .cloned()
+                .ok_or_else(|| Error::from(ErrorKind::NotFound))
         })
     }
 
@@ -69,6 +90,10 @@ impl FS for MemoryFS {
         Box::pin(async move {
             let mut files = self.files.lock().unwrap();
             files.insert(path.to_string_lossy().to_string(), content.to_string());
+            self.modified
+                .lock()
+                .unwrap()
+                .insert(path.to_string_lossy().to_string(), SystemTime::now());
 
             Ok(())
         })
@@ -77,7 +102,10 @@ impl FS for MemoryFS {
     fn exists<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
         Box::pin(async move {
             let files = self.files.lock().unwrap();
+
+            // Directories exist as long as they contain a file
             files.contains_key(path.to_str().unwrap())
+                || files.keys().any(|file| Path::new(file).starts_with(path))
         })
     }
 
@@ -105,6 +133,19 @@ impl FS for MemoryFS {
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move { Ok(()) })
     }
+
+    fn modified<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = Result<SystemTime>> + Send + 'a>> {
+        Box::pin(async move {
+            let modified = self.modified.lock().unwrap();
+            modified
+                .get(path.to_str().unwrap())
+                .copied()
+                .ok_or_else(|| Error::from(ErrorKind::NotFound))
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -167,4 +208,11 @@ impl FS for LocalFs {
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move { create_dir_all(path).await })
     }
+
+    fn modified<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = Result<SystemTime>> + Send + 'a>> {
+        Box::pin(async move { metadata(path).await?.modified() })
+    }
 }
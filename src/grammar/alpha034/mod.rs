@@ -1,7 +1,10 @@
 use crate::analysis::types::DataType;
 
 pub use super::Spanned;
-use super::{CommandModifier, CompilerFlag, Grammar, LSPAnalysis, ParserResponse, Span};
+use super::{
+    shell::{ShellInput, ShellScript},
+    CommandModifier, CompilerFlag, Grammar, LSPAnalysis, ParserResponse, Span,
+};
 use chumsky::{
     error::Rich,
     extra::Err,
@@ -48,6 +51,26 @@ pub enum InterpolatedCommand {
     Text(String),
 }
 
+/// Parse the parts of a command literal, with its `$` delimiters, as a shell script.
+pub fn shell_script(cmd: &[Spanned<InterpolatedCommand>]) -> ShellScript {
+    let input = cmd
+        .iter()
+        .map(|(part, span)| {
+            let part = match part {
+                InterpolatedCommand::Text(text) | InterpolatedCommand::CommandOption(text) => {
+                    ShellInput::Text(text.clone())
+                }
+                InterpolatedCommand::Escape(text) => ShellInput::Escape(text.clone()),
+                InterpolatedCommand::Expression(_) => ShellInput::Interpolation,
+            };
+
+            (part, *span)
+        })
+        .collect::<Vec<_>>();
+
+    ShellScript::parse(&input)
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expression {
     Number(Spanned<f32>),
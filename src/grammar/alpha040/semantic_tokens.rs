@@ -634,21 +634,27 @@ fn semantic_tokens_from_expr((expr, span): &Spanned<Expression>) -> Vec<SpannedS
                 tokens.push((hash_semantic_token_type(SemanticTokenType::KEYWORD), *span));
             });
 
-            cmd.iter().for_each(|(inter_cmd, span)| match inter_cmd {
-                InterpolatedCommand::Text(_) => {
-                    tokens.push((hash_semantic_token_type(SemanticTokenType::STRING), *span));
-                }
-                InterpolatedCommand::Expression(expr) => {
-                    tokens.extend(semantic_tokens_from_expr(expr));
-                }
-                InterpolatedCommand::CommandOption(_) => {
-                    tokens.push((hash_semantic_token_type(CONSTANT), *span));
-                }
-                InterpolatedCommand::Escape(_) => {
-                    tokens.push((hash_semantic_token_type(ESCAPE_SEQUENCE), *span));
+            let mut cmd_tokens = shell_script(cmd)
+                .semantic_tokens()
+                .into_iter()
+                .map(|(token_type, span)| (hash_semantic_token_type(token_type), span))
+                .collect::<Vec<_>>();
+
+            // The `$` delimiters
+            if let [(_, begin), .., (_, end)] = cmd.as_slice() {
+                cmd_tokens.push((hash_semantic_token_type(SemanticTokenType::STRING), *begin));
+                cmd_tokens.push((hash_semantic_token_type(SemanticTokenType::STRING), *end));
+            }
+
+            cmd.iter().for_each(|(inter_cmd, _)| {
+                if let InterpolatedCommand::Expression(expr) = inter_cmd {
+                    cmd_tokens.extend(semantic_tokens_from_expr(expr));
                 }
             });
 
+            cmd_tokens.sort_by_key(|(_, span)| span.start);
+            tokens.extend(cmd_tokens);
+
             if let Some((failure_handler, failure_span)) = failure_handler {
                 match failure_handler {
                     FailureHandler::Handle((_, failed_span), stmnts) => {
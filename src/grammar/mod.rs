@@ -1,9 +1,11 @@
 use chumsky::{error::Rich, span::SimpleSpan};
+use serde::{Deserialize, Serialize};
 use std::fmt::{self, Debug, Display};
 
 pub mod alpha034;
 pub mod alpha035;
 pub mod alpha040;
+pub mod shell;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Grammar {
@@ -63,7 +65,7 @@ pub enum CommandModifier {
     Silent,
 }
 
-#[derive(PartialEq, Debug, Clone, Eq)]
+#[derive(PartialEq, Debug, Clone, Eq, Serialize, Deserialize)]
 pub enum CompilerFlag {
     AllowNestedIfElse,
     AllowGenericReturn,
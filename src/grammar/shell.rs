@@ -0,0 +1,1172 @@
+use tower_lsp::lsp_types::SemanticTokenType;
+
+use super::{Span, Spanned};
+
+const ESCAPE_SEQUENCE: SemanticTokenType = SemanticTokenType::new("escapeSequence");
+const CONSTANT: SemanticTokenType = SemanticTokenType::new("constant");
+
+/// Commands running the command given after them.
+pub const COMMAND_PREFIXES: &[&str] = &["sudo", "doas", "command", "exec", "nohup", "time"];
+
+/// Redirection operators, the longest ones first.
+const REDIRECTION_OPERATORS: &[&str] = &[
+    "&>>", "&>", "<<<", "<<", "<>", "<&", "<", ">>", ">&", ">|", ">",
+];
+
+/// Part of a command literal, as given by the parser of the Amber version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellInput {
+    Text(String),
+    /// Token escaped with a backslash, without the backslash.
+    Escape(String),
+    /// Interpolated Amber expression.
+    Interpolation,
+}
+
+/// Command literal parsed as a shell script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellScript {
+    pub list: Spanned<ShellList>,
+    /// Span of the command literal, with its `$` delimiters.
+    pub span: Span,
+}
+
+/// Pipelines separated by `&&`, `||`, `;` or `&`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShellList {
+    pub pipelines: Vec<Spanned<ShellPipeline>>,
+    pub operators: Vec<Spanned<ListOperator>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListOperator {
+    And,
+    Or,
+    Sequence,
+    Background,
+}
+
+/// Commands separated by `|`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShellPipeline {
+    pub commands: Vec<Spanned<ShellCommand>>,
+    /// Spans of the `|` operators.
+    pub pipes: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellCommand {
+    Simple(SimpleCommand),
+    /// Commands run in a subshell
+    ///
+    /// "(", commands, ")", redirections
+    Subshell(
+        Span,
+        Box<Spanned<ShellList>>,
+        Option<Span>,
+        Vec<Redirection>,
+    ),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SimpleCommand {
+    /// Variables set for the command, `NAME=value`.
+    pub assignments: Vec<ShellWord>,
+    /// Name of the program, followed by its arguments.
+    pub words: Vec<ShellWord>,
+    pub redirections: Vec<Redirection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirection {
+    /// Operator with the file descriptor before it, like `2>`.
+    pub operator: Spanned<String>,
+    pub target: Option<ShellWord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellWord {
+    pub parts: Vec<Spanned<WordPart>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    /// Text outside or inside quotes, without whitespace.
+    Text(String),
+    /// Quote starting or ending a quoted text.
+    Quote(char),
+    /// Character escaped with a backslash.
+    Escape(char),
+    /// Shell variable, `\$NAME`.
+    Variable(String),
+    /// Command substitution
+    ///
+    /// "\$(", commands, ")"
+    Substitution(Span, Box<Spanned<ShellList>>, Option<Span>),
+    /// Interpolated Amber expression.
+    Interpolation,
+}
+
+/// What can be completed at a position of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellCompletion {
+    /// Name of the program run by a command.
+    Executable,
+    /// Argument given to a command, with the name of its program if it's known.
+    Argument(Option<String>),
+    /// Name of a shell variable, after `\$`.
+    Variable,
+}
+
+impl ShellScript {
+    /// Parse the parts of a command literal, with its `$` delimiters.
+    pub fn parse(input: &[Spanned<ShellInput>]) -> ShellScript {
+        let span = match (input.first(), input.last()) {
+            (Some((_, first)), Some((_, last))) => Span::new(first.start, last.end),
+            _ => Span::new(0, 0),
+        };
+
+        let content = match input {
+            [_, content @ .., _] => content,
+            _ => &[],
+        };
+
+        let mut parser = ShellParser {
+            chars: shell_chars(content),
+            position: 0,
+            depth: 0,
+        };
+
+        let list = parser.list();
+
+        ShellScript { list, span }
+    }
+
+    /// Simple commands of the script, including the ones in subshells and command
+    /// substitutions.
+    pub fn simple_commands(&self) -> Vec<Spanned<&SimpleCommand>> {
+        let mut commands = vec![];
+        list_simple_commands(&self.list.0, &mut commands);
+
+        commands
+    }
+
+    /// Word naming the program run by the first command of the script.
+    pub fn executable(&self) -> Option<&ShellWord> {
+        self.list
+            .0
+            .pipelines
+            .first()
+            .and_then(|(pipeline, _)| pipeline.commands.first())
+            .and_then(|(command, _)| match command {
+                ShellCommand::Simple(command) => command.executable(),
+                ShellCommand::Subshell(_, _, _, _) => None,
+            })
+    }
+
+    /// Semantic tokens of the script, without the ones of interpolated expressions and
+    /// the `$` delimiters.
+    pub fn semantic_tokens(&self) -> Vec<Spanned<SemanticTokenType>> {
+        let mut tokens = vec![];
+        list_semantic_tokens(&self.list.0, &mut tokens);
+
+        tokens
+    }
+
+    /// Spans of the script containing the position, from the innermost one to the whole
+    /// command literal.
+    pub fn selection_spans(&self, position: usize) -> Vec<Span> {
+        if !contains(&self.span, position) {
+            return vec![];
+        }
+
+        let mut spans = vec![self.span];
+        list_selection_spans(&self.list, position, &mut spans);
+
+        // Keep only the spans nested in the previous ones
+        let mut nested: Vec<Span> = vec![];
+
+        for span in spans {
+            let is_nested = nested.last().is_none_or(|last| {
+                *last != span && last.start <= span.start && span.end <= last.end
+            });
+
+            if is_nested {
+                nested.push(span);
+            }
+        }
+
+        nested.reverse();
+
+        nested
+    }
+}
+
+impl ShellScript {
+    /// What can be completed at the position, with the text typed before it.
+    ///
+    /// [`None`] outside of the command literal and inside of interpolated expressions.
+    pub fn completion_at(&self, position: usize) -> Option<(ShellCompletion, Spanned<String>)> {
+        if position <= self.span.start || position >= self.span.end {
+            return None;
+        }
+
+        let mut markers = vec![];
+        list_completion_markers(&self.list.0, &mut markers);
+
+        let word = markers
+            .iter()
+            .filter_map(|marker| marker.word.map(|word| (word, &marker.inside)))
+            .filter(|(word, _)| is_in_word(word, position))
+            .min_by_key(|(word, _)| word.span.end - word.span.start);
+
+        if let Some((word, inside)) = word {
+            return word_completion(word, inside.clone(), position);
+        }
+
+        let completion = markers
+            .into_iter()
+            .filter(|marker| marker.span.end <= position)
+            .max_by_key(|marker| marker.span.end)
+            .map_or(ShellCompletion::Executable, |marker| marker.after);
+
+        Some((completion, (String::new(), Span::new(position, position))))
+    }
+}
+
+impl SimpleCommand {
+    /// Word naming the program the command runs, skipping command prefixes like `sudo`
+    /// and their options.
+    pub fn executable(&self) -> Option<&ShellWord> {
+        let mut is_after_prefix = false;
+
+        self.words.iter().find(|word| match word.literal() {
+            Some(text) if COMMAND_PREFIXES.contains(&text.as_str()) => {
+                is_after_prefix = true;
+                false
+            }
+            Some(text) if is_after_prefix && (text.starts_with('-') || word.is_assignment()) => {
+                false
+            }
+            _ => true,
+        })
+    }
+}
+
+impl ShellWord {
+    /// Text of the word without its quotes, [`None`] if it has interpolated values.
+    pub fn literal(&self) -> Option<String> {
+        let mut text = String::new();
+        let mut previous_end = None;
+
+        for (part, span) in self.parts.iter() {
+            // Words are split on whitespace, so a gap inside the word is a quoted one
+            if previous_end.is_some_and(|end| span.start > end) {
+                text.push(' ');
+            }
+            previous_end = Some(span.end);
+
+            match part {
+                WordPart::Text(part) => text.push_str(part),
+                WordPart::Quote(_) => {}
+                WordPart::Escape(c) => text.push(*c),
+                WordPart::Variable(name) => {
+                    text.push('$');
+                    text.push_str(name);
+                }
+                WordPart::Substitution(_, _, _) | WordPart::Interpolation => return None,
+            }
+        }
+
+        Some(text)
+    }
+
+    /// Whether the word sets a variable, `NAME=value`.
+    fn is_assignment(&self) -> bool {
+        match self.parts.first() {
+            Some((WordPart::Text(text), _)) => match text.split_once('=') {
+                Some((name, _)) => {
+                    !name.is_empty()
+                        && !name.starts_with(|c: char| c.is_ascii_digit())
+                        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+fn contains(span: &Span, position: usize) -> bool {
+    span.start <= position && position <= span.end
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellChar {
+    Char(char),
+    Escaped(char),
+    Interpolation,
+    /// Whitespace between the tokens of the command.
+    Gap,
+}
+
+/// Characters of the command, with the gaps between its tokens.
+fn shell_chars(input: &[Spanned<ShellInput>]) -> Vec<Spanned<ShellChar>> {
+    let mut chars = vec![];
+    let mut previous_end = None;
+
+    for (part, span) in input.iter() {
+        if let Some(end) = previous_end.filter(|end| span.start > *end) {
+            chars.push((ShellChar::Gap, Span::new(end, span.start)));
+        }
+        previous_end = Some(span.end);
+
+        match part {
+            ShellInput::Text(text) => {
+                chars.extend(text.chars().enumerate().map(|(index, c)| {
+                    let char = match c.is_whitespace() {
+                        true => ShellChar::Gap,
+                        false => ShellChar::Char(c),
+                    };
+
+                    (char, Span::new(span.start + index, span.start + index + 1))
+                }));
+            }
+            ShellInput::Escape(text) => {
+                let mut text_chars = text.chars();
+                let escaped = match text_chars.next() {
+                    Some(escaped) => escaped,
+                    None => continue,
+                };
+
+                // The backslash and the escaped character
+                let escape_end = span.end.saturating_sub(text_chars.clone().count());
+                chars.push((
+                    ShellChar::Escaped(escaped),
+                    Span::new(span.start, escape_end),
+                ));
+
+                chars.extend(text_chars.enumerate().map(|(index, c)| {
+                    (
+                        ShellChar::Char(c),
+                        Span::new(escape_end + index, escape_end + index + 1),
+                    )
+                }));
+            }
+            ShellInput::Interpolation => chars.push((ShellChar::Interpolation, *span)),
+        }
+    }
+
+    chars
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+struct ShellParser {
+    chars: Vec<Spanned<ShellChar>>,
+    position: usize,
+    /// Number of subshells and command substitutions the parser is in.
+    depth: usize,
+}
+
+impl ShellParser {
+    fn peek(&self, offset: usize) -> Option<ShellChar> {
+        self.chars
+            .get(self.position + offset)
+            .map(|(char, _)| *char)
+    }
+
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        match self.peek(offset) {
+            Some(ShellChar::Char(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    fn starts_with(&self, offset: usize, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(index, c)| self.peek_char(offset + index) == Some(c))
+    }
+
+    fn skip_gaps(&mut self) {
+        while self.peek(0) == Some(ShellChar::Gap) {
+            self.position += 1;
+        }
+    }
+
+    /// Span of the characters parsed since the start, without the gaps around them.
+    fn span_since(&self, start: usize) -> Span {
+        let mut parsed = self.chars[start..self.position.min(self.chars.len())]
+            .iter()
+            .filter(|(char, _)| *char != ShellChar::Gap);
+
+        match (parsed.next(), parsed.next_back()) {
+            (Some((_, first)), Some((_, last))) => Span::new(first.start, last.end),
+            (Some((_, first)), None) => *first,
+            _ => {
+                let position = self
+                    .chars
+                    .get(self.position)
+                    .or(self.chars.last())
+                    .map(|(_, span)| span.start)
+                    .unwrap_or(0);
+
+                Span::new(position, position)
+            }
+        }
+    }
+
+    /// Consume the text, returning its span.
+    fn consume(&mut self, text: &str) -> Span {
+        let start = self.position;
+        self.position += text.chars().count();
+
+        self.span_since(start)
+    }
+
+    fn is_closing_paren(&self) -> bool {
+        self.depth > 0 && self.peek_char(0) == Some(')')
+    }
+
+    fn is_end_of_command(&self) -> bool {
+        match self.peek(0) {
+            None => true,
+            Some(ShellChar::Char('|' | ';')) => true,
+            Some(ShellChar::Char('&')) => self.peek_char(1) != Some('>'),
+            _ => self.is_closing_paren(),
+        }
+    }
+
+    fn list(&mut self) -> Spanned<ShellList> {
+        let start = self.position;
+        let mut list = ShellList::default();
+
+        loop {
+            self.skip_gaps();
+
+            if self.peek(0).is_none() || self.is_closing_paren() {
+                break;
+            }
+
+            let operator = [
+                ("&&", ListOperator::And),
+                ("||", ListOperator::Or),
+                (";", ListOperator::Sequence),
+                ("&", ListOperator::Background),
+            ]
+            .into_iter()
+            .find(|(text, _)| self.starts_with(0, text));
+
+            if let Some((text, operator)) = operator {
+                let span = self.consume(text);
+                list.operators.push((operator, span));
+                continue;
+            }
+
+            match self.pipeline() {
+                Some(pipeline) => list.pipelines.push(pipeline),
+                // Skip what can't start a command, like a `;;`
+                None => self.position += 1,
+            }
+        }
+
+        (list, self.span_since(start))
+    }
+
+    fn pipeline(&mut self) -> Option<Spanned<ShellPipeline>> {
+        let start = self.position;
+        let mut pipeline = ShellPipeline::default();
+
+        loop {
+            self.skip_gaps();
+
+            if self.starts_with(0, "|&") {
+                pipeline.pipes.push(self.consume("|&"));
+                continue;
+            }
+
+            if self.peek_char(0) == Some('|') && self.peek_char(1) != Some('|') {
+                pipeline.pipes.push(self.consume("|"));
+                continue;
+            }
+
+            if self.is_end_of_command() {
+                break;
+            }
+
+            match self.command() {
+                Some(command) => pipeline.commands.push(command),
+                None => break,
+            }
+        }
+
+        if pipeline.commands.is_empty() && pipeline.pipes.is_empty() {
+            return None;
+        }
+
+        Some((pipeline, self.span_since(start)))
+    }
+
+    fn command(&mut self) -> Option<Spanned<ShellCommand>> {
+        let start = self.position;
+
+        if self.peek_char(0) == Some('(') {
+            let open = self.consume("(");
+            let list = self.nested_list();
+            let close = self.closing_paren();
+            let mut redirections = vec![];
+
+            loop {
+                self.skip_gaps();
+
+                match self.redirection() {
+                    Some(redirection) => redirections.push(redirection),
+                    None => break,
+                }
+            }
+
+            return Some((
+                ShellCommand::Subshell(open, Box::new(list), close, redirections),
+                self.span_since(start),
+            ));
+        }
+
+        let mut command = SimpleCommand::default();
+
+        loop {
+            self.skip_gaps();
+
+            if self.is_end_of_command() {
+                break;
+            }
+
+            if let Some(redirection) = self.redirection() {
+                command.redirections.push(redirection);
+                continue;
+            }
+
+            let word = self.word();
+
+            if command.words.is_empty() && word.is_assignment() {
+                command.assignments.push(word);
+            } else {
+                command.words.push(word);
+            }
+        }
+
+        if command == SimpleCommand::default() {
+            return None;
+        }
+
+        Some((ShellCommand::Simple(command), self.span_since(start)))
+    }
+
+    fn nested_list(&mut self) -> Spanned<ShellList> {
+        self.depth += 1;
+        let list = self.list();
+        self.depth -= 1;
+
+        list
+    }
+
+    fn closing_paren(&mut self) -> Option<Span> {
+        self.skip_gaps();
+
+        match self.peek_char(0) {
+            Some(')') => Some(self.consume(")")),
+            _ => None,
+        }
+    }
+
+    fn redirection(&mut self) -> Option<Redirection> {
+        let digits = (0..)
+            .take_while(|offset| self.peek_char(*offset).is_some_and(|c| c.is_ascii_digit()))
+            .count();
+
+        let operator = REDIRECTION_OPERATORS
+            .iter()
+            .find(|operator| self.starts_with(digits, operator))?;
+
+        if digits > 0 && operator.starts_with('&') {
+            return None;
+        }
+
+        let start = self.position;
+        self.position += digits + operator.chars().count();
+
+        let text = self.chars[start..self.position]
+            .iter()
+            .filter_map(|(char, _)| match char {
+                ShellChar::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        let operator = (text, self.span_since(start));
+
+        self.skip_gaps();
+
+        let target = match self.is_end_of_command() || matches!(self.peek_char(0), Some('<' | '>'))
+        {
+            true => None,
+            false => Some(self.word()),
+        };
+
+        Some(Redirection { operator, target })
+    }
+
+    fn word(&mut self) -> ShellWord {
+        let start = self.position;
+        let mut parts: Vec<Spanned<WordPart>> = vec![];
+        let mut quote = None;
+
+        while let Some((char, span)) = self.chars.get(self.position).copied() {
+            match char {
+                ShellChar::Gap if quote.is_some() => {}
+                ShellChar::Gap => break,
+                ShellChar::Char(c) if quote.is_none() && self.is_word_end(c) => break,
+                ShellChar::Char(c @ ('"' | '\'')) if quote.is_none() || quote == Some(c) => {
+                    quote = match quote {
+                        Some(_) => None,
+                        None => Some(c),
+                    };
+                    parts.push((WordPart::Quote(c), span));
+                }
+                ShellChar::Char(c) => match parts.last_mut() {
+                    Some((WordPart::Text(text), text_span)) if text_span.end == span.start => {
+                        text.push(c);
+                        text_span.end = span.end;
+                    }
+                    _ => parts.push((WordPart::Text(c.to_string()), span)),
+                },
+                ShellChar::Escaped('$') if quote != Some('\'') => {
+                    self.position += 1;
+                    parts.push(self.expansion(span));
+                    continue;
+                }
+                ShellChar::Escaped(c) => parts.push((WordPart::Escape(c), span)),
+                ShellChar::Interpolation => parts.push((WordPart::Interpolation, span)),
+            }
+
+            self.position += 1;
+        }
+
+        ShellWord {
+            parts,
+            span: self.span_since(start),
+        }
+    }
+
+    fn is_word_end(&self, c: char) -> bool {
+        match c {
+            '|' | '&' | ';' | '<' | '>' => true,
+            ')' => self.depth > 0,
+            _ => false,
+        }
+    }
+
+    /// Variable or command substitution following an escaped `$`.
+    fn expansion(&mut self, dollar: Span) -> Spanned<WordPart> {
+        match self.peek_char(0) {
+            Some('(') => {
+                let open = Span::new(dollar.start, self.consume("(").end);
+                let list = self.nested_list();
+                let close = self.closing_paren();
+                let end = close.map_or(list.1.end.max(open.end), |close| close.end);
+
+                (
+                    WordPart::Substitution(open, Box::new(list), close),
+                    Span::new(dollar.start, end),
+                )
+            }
+            Some(c) if is_variable_char(c) && !c.is_ascii_digit() => {
+                let mut name = String::new();
+                let mut end = dollar.end;
+
+                while let Some((ShellChar::Char(c), span)) = self.chars.get(self.position) {
+                    if !is_variable_char(*c) || span.start != end {
+                        break;
+                    }
+
+                    name.push(*c);
+                    end = span.end;
+                    self.position += 1;
+                }
+
+                (WordPart::Variable(name), Span::new(dollar.start, end))
+            }
+            Some(c) if c.is_ascii_digit() || "@*#?!-".contains(c) => {
+                let span = self.consume(&c.to_string());
+
+                (
+                    WordPart::Variable(c.to_string()),
+                    Span::new(dollar.start, span.end),
+                )
+            }
+            _ => (WordPart::Escape('$'), dollar),
+        }
+    }
+}
+
+fn list_simple_commands<'a>(list: &'a ShellList, commands: &mut Vec<Spanned<&'a SimpleCommand>>) {
+    for (pipeline, _) in list.pipelines.iter() {
+        for (command, span) in pipeline.commands.iter() {
+            match command {
+                ShellCommand::Simple(command) => {
+                    commands.push((command, *span));
+
+                    let words = command
+                        .assignments
+                        .iter()
+                        .chain(command.words.iter())
+                        .chain(redirection_targets(&command.redirections));
+
+                    words_simple_commands(words, commands);
+                }
+                ShellCommand::Subshell(_, list, _, redirections) => {
+                    list_simple_commands(&list.0, commands);
+                    words_simple_commands(redirection_targets(redirections), commands);
+                }
+            }
+        }
+    }
+}
+
+fn words_simple_commands<'a>(
+    words: impl Iterator<Item = &'a ShellWord>,
+    commands: &mut Vec<Spanned<&'a SimpleCommand>>,
+) {
+    for word in words {
+        for (part, _) in word.parts.iter() {
+            if let WordPart::Substitution(_, list, _) = part {
+                list_simple_commands(&list.0, commands);
+            }
+        }
+    }
+}
+
+fn redirection_targets(redirections: &[Redirection]) -> impl Iterator<Item = &ShellWord> {
+    redirections
+        .iter()
+        .flat_map(|redirection| redirection.target.iter())
+}
+
+fn list_semantic_tokens(list: &ShellList, tokens: &mut Vec<Spanned<SemanticTokenType>>) {
+    for (_, span) in list.operators.iter() {
+        tokens.push((SemanticTokenType::OPERATOR, *span));
+    }
+
+    for (pipeline, _) in list.pipelines.iter() {
+        for span in pipeline.pipes.iter() {
+            tokens.push((SemanticTokenType::OPERATOR, *span));
+        }
+
+        for (command, _) in pipeline.commands.iter() {
+            match command {
+                ShellCommand::Simple(command) => {
+                    command_semantic_tokens(command, tokens);
+                }
+                ShellCommand::Subshell(open, list, close, redirections) => {
+                    tokens.push((SemanticTokenType::OPERATOR, *open));
+                    list_semantic_tokens(&list.0, tokens);
+                    tokens.extend(close.map(|close| (SemanticTokenType::OPERATOR, close)));
+                    redirections_semantic_tokens(redirections, tokens);
+                }
+            }
+        }
+    }
+}
+
+fn command_semantic_tokens(command: &SimpleCommand, tokens: &mut Vec<Spanned<SemanticTokenType>>) {
+    for word in command.assignments.iter() {
+        let mut parts = word.parts.iter();
+
+        // The name of the variable and the `=` are at the start of the first part
+        if let Some((WordPart::Text(text), span)) = parts.next() {
+            let name_len = text.find('=').unwrap_or(text.len());
+            let name_end = span.start + text[..name_len].chars().count();
+
+            tokens.push((SemanticTokenType::VARIABLE, Span::new(span.start, name_end)));
+            tokens.push((
+                SemanticTokenType::OPERATOR,
+                Span::new(name_end, name_end + 1),
+            ));
+
+            if name_end + 1 < span.end {
+                tokens.push((SemanticTokenType::STRING, Span::new(name_end + 1, span.end)));
+            }
+        }
+
+        parts.for_each(|part| part_semantic_tokens(part, SemanticTokenType::STRING, tokens));
+    }
+
+    let executable = command.executable();
+
+    for word in command.words.iter() {
+        let text_type = match word.literal() {
+            _ if executable.is_some_and(|executable| std::ptr::eq(executable, word)) => {
+                SemanticTokenType::FUNCTION
+            }
+            Some(text) if text.starts_with('-') => CONSTANT,
+            _ => SemanticTokenType::STRING,
+        };
+
+        word_semantic_tokens(word, text_type, tokens);
+    }
+
+    redirections_semantic_tokens(&command.redirections, tokens);
+}
+
+fn redirections_semantic_tokens(
+    redirections: &[Redirection],
+    tokens: &mut Vec<Spanned<SemanticTokenType>>,
+) {
+    for redirection in redirections.iter() {
+        tokens.push((SemanticTokenType::OPERATOR, redirection.operator.1));
+
+        if let Some(target) = &redirection.target {
+            word_semantic_tokens(target, SemanticTokenType::STRING, tokens);
+        }
+    }
+}
+
+fn word_semantic_tokens(
+    word: &ShellWord,
+    text_type: SemanticTokenType,
+    tokens: &mut Vec<Spanned<SemanticTokenType>>,
+) {
+    for part in word.parts.iter() {
+        part_semantic_tokens(part, text_type.clone(), tokens);
+    }
+}
+
+fn part_semantic_tokens(
+    (part, span): &Spanned<WordPart>,
+    text_type: SemanticTokenType,
+    tokens: &mut Vec<Spanned<SemanticTokenType>>,
+) {
+    match part {
+        WordPart::Text(_) => tokens.push((text_type, *span)),
+        WordPart::Quote(_) => tokens.push((SemanticTokenType::STRING, *span)),
+        WordPart::Escape(_) => tokens.push((ESCAPE_SEQUENCE, *span)),
+        WordPart::Variable(_) => tokens.push((SemanticTokenType::VARIABLE, *span)),
+        WordPart::Substitution(open, list, close) => {
+            tokens.push((SemanticTokenType::OPERATOR, *open));
+            list_semantic_tokens(&list.0, tokens);
+            tokens.extend(close.map(|close| (SemanticTokenType::OPERATOR, close)));
+        }
+        WordPart::Interpolation => {}
+    }
+}
+
+fn list_selection_spans((list, span): &Spanned<ShellList>, position: usize, spans: &mut Vec<Span>) {
+    if !contains(span, position) {
+        return;
+    }
+
+    spans.push(*span);
+
+    let pipeline = list
+        .pipelines
+        .iter()
+        .find(|(_, span)| contains(span, position));
+
+    let (pipeline, span) = match pipeline {
+        Some(pipeline) => pipeline,
+        None => return,
+    };
+
+    spans.push(*span);
+
+    let (command, span) = match pipeline
+        .commands
+        .iter()
+        .find(|(_, span)| contains(span, position))
+    {
+        Some(command) => command,
+        None => return,
+    };
+
+    spans.push(*span);
+
+    match command {
+        ShellCommand::Simple(command) => {
+            let word = command
+                .assignments
+                .iter()
+                .chain(command.words.iter())
+                .find(|word| contains(&word.span, position));
+
+            match word {
+                Some(word) => word_selection_spans(word, position, spans),
+                None => redirections_selection_spans(&command.redirections, position, spans),
+            }
+        }
+        ShellCommand::Subshell(_, list, _, redirections) => {
+            list_selection_spans(list, position, spans);
+            redirections_selection_spans(redirections, position, spans);
+        }
+    }
+}
+
+fn redirections_selection_spans(
+    redirections: &[Redirection],
+    position: usize,
+    spans: &mut Vec<Span>,
+) {
+    let redirection = redirections.iter().find(|redirection| {
+        let end = redirection
+            .target
+            .as_ref()
+            .map_or(redirection.operator.1.end, |target| target.span.end);
+
+        contains(&Span::new(redirection.operator.1.start, end), position)
+    });
+
+    let redirection = match redirection {
+        Some(redirection) => redirection,
+        None => return,
+    };
+
+    match &redirection.target {
+        Some(target) => {
+            spans.push(Span::new(redirection.operator.1.start, target.span.end));
+
+            if contains(&target.span, position) {
+                word_selection_spans(target, position, spans);
+            }
+        }
+        None => spans.push(redirection.operator.1),
+    }
+}
+
+fn word_selection_spans(word: &ShellWord, position: usize, spans: &mut Vec<Span>) {
+    spans.push(word.span);
+
+    let quotes = word
+        .parts
+        .iter()
+        .filter(|(part, _)| matches!(part, WordPart::Quote(_)))
+        .map(|(_, span)| *span)
+        .collect::<Vec<_>>();
+
+    // Text inside a pair of quotes
+    for pair in quotes.chunks(2) {
+        if let [open, close] = pair {
+            if open.end <= position && position <= close.start {
+                spans.push(Span::new(open.start, close.end));
+                spans.push(Span::new(open.end, close.start));
+            }
+        }
+    }
+
+    let part = word.parts.iter().find(|(_, span)| contains(span, position));
+
+    match part {
+        Some((WordPart::Substitution(_, list, _), span)) => {
+            spans.push(*span);
+            list_selection_spans(list, position, spans);
+        }
+        Some((_, span)) => spans.push(*span),
+        None => {}
+    }
+}
+
+/// Word or operator of a script, with what can be completed in it and after it.
+struct CompletionMarker<'a> {
+    span: Span,
+    word: Option<&'a ShellWord>,
+    inside: Option<ShellCompletion>,
+    after: ShellCompletion,
+}
+
+fn operator_marker<'a>(span: Span, after: ShellCompletion) -> CompletionMarker<'a> {
+    CompletionMarker {
+        span,
+        word: None,
+        inside: None,
+        after,
+    }
+}
+
+fn list_completion_markers<'a>(list: &'a ShellList, markers: &mut Vec<CompletionMarker<'a>>) {
+    for (_, span) in list.operators.iter() {
+        markers.push(operator_marker(*span, ShellCompletion::Executable));
+    }
+
+    for (pipeline, _) in list.pipelines.iter() {
+        for span in pipeline.pipes.iter() {
+            markers.push(operator_marker(*span, ShellCompletion::Executable));
+        }
+
+        for (command, _) in pipeline.commands.iter() {
+            match command {
+                ShellCommand::Simple(command) => command_completion_markers(command, markers),
+                ShellCommand::Subshell(open, list, close, redirections) => {
+                    markers.push(operator_marker(*open, ShellCompletion::Executable));
+                    list_completion_markers(&list.0, markers);
+
+                    if let Some(close) = close {
+                        markers.push(operator_marker(*close, ShellCompletion::Argument(None)));
+                    }
+
+                    redirections_completion_markers(
+                        redirections,
+                        ShellCompletion::Argument(None),
+                        markers,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn command_completion_markers<'a>(
+    command: &'a SimpleCommand,
+    markers: &mut Vec<CompletionMarker<'a>>,
+) {
+    let executable = command.executable();
+    let arguments = ShellCompletion::Argument(executable.and_then(|word| word.literal()));
+
+    for word in command.assignments.iter() {
+        word_completion_markers(word, None, ShellCompletion::Executable, markers);
+    }
+
+    for word in command.words.iter() {
+        let is_after_executable =
+            executable.is_some_and(|executable| executable.span.start < word.span.start);
+
+        match is_after_executable {
+            true => {
+                word_completion_markers(word, Some(arguments.clone()), arguments.clone(), markers)
+            }
+            false => word_completion_markers(
+                word,
+                Some(ShellCompletion::Executable),
+                match executable.is_some_and(|executable| std::ptr::eq(executable, word)) {
+                    true => arguments.clone(),
+                    false => ShellCompletion::Executable,
+                },
+                markers,
+            ),
+        }
+    }
+
+    let after_redirections = match executable {
+        Some(_) => arguments,
+        None => ShellCompletion::Executable,
+    };
+
+    redirections_completion_markers(&command.redirections, after_redirections, markers);
+}
+
+fn redirections_completion_markers<'a>(
+    redirections: &'a [Redirection],
+    after: ShellCompletion,
+    markers: &mut Vec<CompletionMarker<'a>>,
+) {
+    for redirection in redirections.iter() {
+        markers.push(operator_marker(
+            redirection.operator.1,
+            ShellCompletion::Argument(None),
+        ));
+
+        if let Some(target) = &redirection.target {
+            word_completion_markers(
+                target,
+                Some(ShellCompletion::Argument(None)),
+                after.clone(),
+                markers,
+            );
+        }
+    }
+}
+
+fn word_completion_markers<'a>(
+    word: &'a ShellWord,
+    inside: Option<ShellCompletion>,
+    after: ShellCompletion,
+    markers: &mut Vec<CompletionMarker<'a>>,
+) {
+    markers.push(CompletionMarker {
+        span: word.span,
+        word: Some(word),
+        inside,
+        after,
+    });
+
+    for (part, _) in word.parts.iter() {
+        if let WordPart::Substitution(open, list, _) = part {
+            markers.push(operator_marker(*open, ShellCompletion::Executable));
+            list_completion_markers(&list.0, markers);
+        }
+    }
+}
+
+/// Whether the position is in the word, and not in a command substituted in it.
+fn is_in_word(word: &ShellWord, position: usize) -> bool {
+    contains(&word.span, position)
+        && !word.parts.iter().any(|(part, _)| match part {
+            WordPart::Substitution(open, _, close) => {
+                open.end <= position && close.is_none_or(|close| position <= close.start)
+            }
+            _ => false,
+        })
+}
+
+fn word_completion(
+    word: &ShellWord,
+    inside: Option<ShellCompletion>,
+    position: usize,
+) -> Option<(ShellCompletion, Spanned<String>)> {
+    let mut prefix = String::new();
+    let mut start = word.span.start;
+    let mut previous_end = None;
+
+    for (part, span) in word.parts.iter() {
+        if span.start >= position {
+            break;
+        }
+
+        // Words are split on whitespace, so a gap inside the word is a quoted one
+        if previous_end.is_some_and(|end| span.start > end) {
+            prefix.push(' ');
+        }
+        previous_end = Some(span.end);
+
+        match part {
+            // The typed text starts after the last quote
+            WordPart::Quote(_) => {
+                prefix.clear();
+                start = span.end;
+                previous_end = None;
+            }
+            WordPart::Text(text) => prefix.extend(text.chars().take(position - span.start)),
+            WordPart::Escape('$') if span.end == position => {
+                return Some((
+                    ShellCompletion::Variable,
+                    (String::new(), Span::new(position, position)),
+                ));
+            }
+            WordPart::Escape(c) => prefix.push(*c),
+            WordPart::Variable(name) if position <= span.end => {
+                // The name starts after the escaped `$`
+                let name_start = span.end - name.chars().count();
+                let name = name.chars().take(position - name_start).collect();
+
+                return Some((
+                    ShellCompletion::Variable,
+                    (name, Span::new(name_start, position)),
+                ));
+            }
+            WordPart::Variable(_) | WordPart::Substitution(_, _, _) | WordPart::Interpolation => {
+                return None
+            }
+        }
+    }
+
+    Some((inside?, (prefix, Span::new(start, position))))
+}
@@ -1,8 +1,13 @@
 pub mod analysis;
 pub mod backend;
+pub mod compiler;
+pub mod detection;
+pub mod diagnostics;
 pub mod files;
 pub mod fs;
 pub mod grammar;
 pub mod paths;
+pub mod settings;
 pub mod stdlib;
+pub mod stdlib_index;
 pub mod utils;
@@ -1,9 +1,6 @@
-use std::{
-    env::temp_dir,
-    process::{Command, Stdio},
-};
+use std::env::temp_dir;
 
-use amber_lsp::backend::{AmberVersion, Backend};
+use amber_lsp::{backend::Backend, settings::VersionSetting, stdlib::STDLIB_CONTENTS_REQUEST};
 use clap::{builder::PossibleValue, Parser, ValueEnum};
 use tower_lsp::{LspService, Server};
 use tracing::subscriber;
@@ -17,13 +14,13 @@ enum CliAmberVersion {
     Alpha040,
 }
 
-impl From<CliAmberVersion> for AmberVersion {
+impl From<CliAmberVersion> for VersionSetting {
     fn from(val: CliAmberVersion) -> Self {
         match val {
-            CliAmberVersion::Auto => AmberVersion::Alpha034,
-            CliAmberVersion::Alpha034 => AmberVersion::Alpha034,
-            CliAmberVersion::Alpha035 => AmberVersion::Alpha035,
-            CliAmberVersion::Alpha040 => AmberVersion::Alpha040,
+            CliAmberVersion::Auto => VersionSetting::Auto,
+            CliAmberVersion::Alpha034 => VersionSetting::Alpha034,
+            CliAmberVersion::Alpha035 => VersionSetting::Alpha035,
+            CliAmberVersion::Alpha040 => VersionSetting::Alpha040,
         }
     }
 }
@@ -91,35 +88,10 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let amber_version = if args.amber_version == CliAmberVersion::Auto {
-        detect_amber_version()
-    } else {
-        args.amber_version.into()
-    };
+    let amber_version: VersionSetting = args.amber_version.into();
 
-    let (service, socket) = LspService::new(|client| Backend::new(client, amber_version, None));
+    let (service, socket) = LspService::build(|client| Backend::new(client, amber_version, None))
+        .custom_method(STDLIB_CONTENTS_REQUEST, Backend::stdlib_contents)
+        .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
-
-#[tracing::instrument(skip_all)]
-fn detect_amber_version() -> AmberVersion {
-    let output = Command::new("amber")
-        .arg("-V")
-        .stdout(Stdio::piped())
-        .output();
-
-    let version = match output {
-        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
-        Err(e) => {
-            tracing::error!("Failed to execute amber command: {}", e);
-            return AmberVersion::Alpha040; // Default to the latest version if detection fails
-        }
-    };
-
-    match version.split_whitespace().last() {
-        Some("0.3.4-alpha") => AmberVersion::Alpha034,
-        Some("0.3.5-alpha") => AmberVersion::Alpha035,
-        Some("0.4.0-alpha") => AmberVersion::Alpha040,
-        _ => AmberVersion::Alpha040,
-    }
-}
@@ -0,0 +1,352 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+use tracing::warn;
+
+use crate::{backend::AmberVersion, diagnostics::DiagnosticKind};
+
+/// Configuration section the settings are read from.
+pub const SETTINGS_SECTION: &str = "amber-lsp";
+
+/// Settings of the language server, read from the client configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Amber version used to analyze files.
+    pub version: VersionSetting,
+    /// Severity overrides, keyed by the diagnostic kind.
+    pub diagnostics: HashMap<DiagnosticKind, SeverityLevel>,
+    pub inlay_hints: InlayHintsSettings,
+    /// Maximum number of diagnostics published for a single file.
+    pub max_number_of_problems: usize,
+    /// Additional directories searched for imported files.
+    pub import_paths: Vec<PathBuf>,
+    /// Directory `std` imports are resolved from. The embedded standard library is used if not set.
+    pub stdlib_path: Option<PathBuf>,
+    /// Path to the `amber` compiler. It's looked up on `PATH` if not set.
+    pub amber_path: Option<PathBuf>,
+    /// Resolve `std` imports from the standard library installed with the compiler,
+    /// falling back to the embedded one.
+    pub use_installed_stdlib: bool,
+    pub compiler_diagnostics: CompilerDiagnosticsSettings,
+    /// Commands treated as available, in addition to the ones found on `PATH`.
+    pub known_commands: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: VersionSetting::Auto,
+            diagnostics: HashMap::new(),
+            inlay_hints: InlayHintsSettings::default(),
+            max_number_of_problems: 100,
+            import_paths: vec![],
+            stdlib_path: None,
+            amber_path: None,
+            use_installed_stdlib: false,
+            compiler_diagnostics: CompilerDiagnosticsSettings::default(),
+            known_commands: vec![],
+        }
+    }
+}
+
+impl Settings {
+    /// Parse settings sent by the client.
+    ///
+    /// Falls back to the default of each setting with a malformed value, see [`Settings::parse`].
+    pub fn from_value(value: Value) -> Self {
+        let (settings, problems) = Settings::parse(value);
+
+        for problem in problems {
+            warn!("{}", problem);
+        }
+
+        settings
+    }
+
+    /// Parse settings sent by the client, returning them with a description of each
+    /// malformed setting.
+    ///
+    /// Each setting is read on its own, and so is each diagnostic kind of the `diagnostics`
+    /// overrides. Malformed ones keep their default, unknown settings are ignored.
+    pub fn parse(value: Value) -> (Self, Vec<String>) {
+        let mut settings = Settings::default();
+        let mut problems = vec![];
+
+        let object = match value {
+            Value::Null => return (settings, problems),
+            Value::Object(object) => object,
+            value => {
+                problems.push(format!("Invalid settings, expected an object: {}", value));
+                return (settings, problems);
+            }
+        };
+
+        for (key, value) in object {
+            let result = match key.as_str() {
+                "version" => parse_setting(value, &mut settings.version),
+                "diagnostics" => {
+                    settings.diagnostics = parse_diagnostics(value, &mut problems);
+                    Ok(())
+                }
+                "inlayHints" => parse_setting(value, &mut settings.inlay_hints),
+                "maxNumberOfProblems" => parse_setting(value, &mut settings.max_number_of_problems),
+                "importPaths" => parse_setting(value, &mut settings.import_paths),
+                "stdlibPath" => parse_setting(value, &mut settings.stdlib_path),
+                "amberPath" => parse_setting(value, &mut settings.amber_path),
+                "useInstalledStdlib" => parse_setting(value, &mut settings.use_installed_stdlib),
+                "compilerDiagnostics" => parse_setting(value, &mut settings.compiler_diagnostics),
+                "knownCommands" => parse_setting(value, &mut settings.known_commands),
+                _ => Ok(()),
+            };
+
+            if let Err(err) = result {
+                problems.push(format!("Invalid setting `{}`: {}", key, err));
+            }
+        }
+
+        (settings, problems)
+    }
+
+    /// Resolve relative import paths against the workspace folder the settings apply to.
+    ///
+    /// They are kept relative if there is no such folder.
+    pub fn resolve_import_paths(&mut self, workspace_folder: Option<&Path>) {
+        if let Some(workspace_folder) = workspace_folder {
+            for import_path in self.import_paths.iter_mut() {
+                if import_path.is_relative() {
+                    *import_path = workspace_folder.join(&*import_path);
+                }
+            }
+        }
+    }
+
+    /// Severity with which diagnostics of the given kind are published.
+    ///
+    /// Returns [`None`] if they are turned off.
+    pub fn severity(&self, kind: DiagnosticKind) -> Option<DiagnosticSeverity> {
+        match self.diagnostics.get(&kind) {
+            Some(level) => level.to_severity(),
+            None => Some(kind.default_severity()),
+        }
+    }
+}
+
+fn parse_setting<T: DeserializeOwned>(value: Value, setting: &mut T) -> serde_json::Result<()> {
+    *setting = serde_json::from_value(value)?;
+
+    Ok(())
+}
+
+/// Severity overrides, skipping unknown diagnostic kinds and severity levels.
+fn parse_diagnostics(
+    value: Value,
+    problems: &mut Vec<String>,
+) -> HashMap<DiagnosticKind, SeverityLevel> {
+    let object = match value {
+        Value::Object(object) => object,
+        value => {
+            problems.push(format!(
+                "Invalid setting `diagnostics`, expected an object: {}",
+                value
+            ));
+            return HashMap::new();
+        }
+    };
+
+    object
+        .into_iter()
+        .filter_map(|(kind, level)| {
+            let parsed = serde_json::from_value(Value::String(kind.clone()))
+                .and_then(|kind| Ok((kind, serde_json::from_value(level)?)));
+
+            match parsed {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    problems.push(format!("Invalid setting `diagnostics.{}`: {}", kind, err));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Settings of the whole workspace, together with settings of its workspace folders
+/// and documents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkspaceSettings {
+    pub global: Settings,
+    /// Settings scoped to a workspace folder or a single document.
+    ///
+    /// They are [`None`] until pulled from the client, the global settings apply in that case.
+    pub scopes: Vec<(Url, Option<Settings>)>,
+    /// Workspace folders open in the client.
+    pub folders: Vec<Url>,
+}
+
+impl WorkspaceSettings {
+    /// Settings that apply to the document, taken from the most specific scope containing it.
+    pub fn get(&self, uri: &Url) -> &Settings {
+        self.scopes
+            .iter()
+            .filter_map(|(scope, settings)| match settings {
+                Some(settings) if is_in_scope(uri, scope) => Some((scope, settings)),
+                _ => None,
+            })
+            .max_by_key(|(scope, _)| scope.as_str().len())
+            .map(|(_, settings)| settings)
+            .unwrap_or(&self.global)
+    }
+
+    /// Whether settings of a scope containing the document were already pulled from the
+    /// client.
+    pub fn has_scope_settings(&self, uri: &Url) -> bool {
+        self.scopes
+            .iter()
+            .any(|(scope, settings)| settings.is_some() && is_in_scope(uri, scope))
+    }
+
+    /// Workspace folder containing the scope, or the only workspace folder for the global
+    /// settings.
+    pub fn folder_of(&self, scope: Option<&Url>) -> Option<&Url> {
+        match scope {
+            Some(scope) => self
+                .folders
+                .iter()
+                .filter(|folder| is_in_scope(scope, folder))
+                .max_by_key(|folder| folder.as_str().len()),
+            None if self.folders.len() == 1 => self.folders.first(),
+            None => None,
+        }
+    }
+
+    pub fn add_folder(&mut self, folder: Url) {
+        if !self.folders.contains(&folder) {
+            self.folders.push(folder.clone());
+        }
+
+        self.set_scope(folder, None);
+    }
+
+    pub fn remove_folder(&mut self, folder: &Url) {
+        self.folders.retain(|uri| uri != folder);
+        self.remove_scope(folder);
+    }
+
+    pub fn set_scope(&mut self, scope: Url, settings: Option<Settings>) {
+        match self.scopes.iter_mut().find(|(uri, _)| *uri == scope) {
+            Some((_, scope_settings)) => *scope_settings = settings,
+            None => self.scopes.push((scope, settings)),
+        }
+    }
+
+    pub fn remove_scope(&mut self, scope: &Url) {
+        self.scopes.retain(|(uri, _)| uri != scope);
+    }
+}
+
+fn is_in_scope(uri: &Url, scope: &Url) -> bool {
+    uri == scope
+        || uri
+            .as_str()
+            .starts_with(&format!("{}/", scope.as_str().trim_end_matches('/')))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub enum VersionSetting {
+    /// Detect the version of each document.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "0.3.4-alpha")]
+    Alpha034,
+    #[serde(rename = "0.3.5-alpha")]
+    Alpha035,
+    #[serde(rename = "0.4.0-alpha")]
+    Alpha040,
+}
+
+impl VersionSetting {
+    /// Returns [`None`] if the version should be detected.
+    pub fn amber_version(&self) -> Option<AmberVersion> {
+        match self {
+            VersionSetting::Auto => None,
+            VersionSetting::Alpha034 => Some(AmberVersion::Alpha034),
+            VersionSetting::Alpha035 => Some(AmberVersion::Alpha035),
+            VersionSetting::Alpha040 => Some(AmberVersion::Alpha040),
+        }
+    }
+}
+
+impl From<AmberVersion> for VersionSetting {
+    fn from(amber_version: AmberVersion) -> Self {
+        match amber_version {
+            AmberVersion::Alpha034 => VersionSetting::Alpha034,
+            AmberVersion::Alpha035 => VersionSetting::Alpha035,
+            AmberVersion::Alpha040 => VersionSetting::Alpha040,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeverityLevel {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    Off,
+}
+
+impl SeverityLevel {
+    pub fn to_severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            SeverityLevel::Error => Some(DiagnosticSeverity::ERROR),
+            SeverityLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            SeverityLevel::Information => Some(DiagnosticSeverity::INFORMATION),
+            SeverityLevel::Hint => Some(DiagnosticSeverity::HINT),
+            SeverityLevel::Off => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlayHintsSettings {
+    /// Show any inlay hints at all.
+    pub enabled: bool,
+    /// Show inferred types of variables without a type annotation, and the types
+    /// variables are narrowed to by `is` checks.
+    pub variable_types: bool,
+}
+
+impl Default for InlayHintsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            variable_types: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompilerDiagnosticsSettings {
+    /// Check documents with the `amber` compiler when they are saved.
+    pub enabled: bool,
+    /// Time in milliseconds after which the compiler is stopped.
+    pub timeout: u64,
+}
+
+impl Default for CompilerDiagnosticsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: 5000,
+        }
+    }
+}
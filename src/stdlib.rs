@@ -1,6 +1,5 @@
-use std::{env::temp_dir, future::Future, path::PathBuf, pin::Pin};
+use std::path::PathBuf;
 
-use clap::builder::OsStr;
 use include_dir::{include_dir, Dir, DirEntry};
 use tower_lsp::lsp_types::Url;
 use tracing::warn;
@@ -9,107 +8,192 @@ use crate::backend::{AmberVersion, Backend};
 
 pub const STDLIB: Dir = include_dir!("$CARGO_MANIFEST_DIR/resources/");
 
+/// URI scheme of the embedded stdlib documents, e.g. `amber-std:/alpha040/std/text.ab`.
+///
+/// They don't exist on disk, clients get their contents with the [`STDLIB_CONTENTS_REQUEST`].
+pub const STDLIB_SCHEME: &str = "amber-std";
+
+/// Custom request returning the contents of an embedded stdlib document.
+pub const STDLIB_CONTENTS_REQUEST: &str = "amber/stdlibContents";
+
+/// Directory of the version's stdlib in [`STDLIB`].
+pub fn version_dir(amber_version: &AmberVersion) -> &'static str {
+    match amber_version {
+        AmberVersion::Alpha034 => "alpha034",
+        AmberVersion::Alpha035 => "alpha035",
+        AmberVersion::Alpha040 => "alpha040",
+    }
+}
+
+/// URI of a file of the embedded stdlib, `path` is relative to the stdlib of the version.
+pub fn stdlib_uri(amber_version: &AmberVersion, path: &str) -> Url {
+    Url::parse(&format!(
+        "{}:/{}/{}",
+        STDLIB_SCHEME,
+        version_dir(amber_version),
+        path
+    ))
+    .unwrap()
+}
+
+/// Path of the document in [`STDLIB`], if it's part of the embedded stdlib.
+pub fn embedded_path(url: &Url) -> Option<&str> {
+    if url.scheme() != STDLIB_SCHEME {
+        return None;
+    }
+
+    url.path().strip_prefix('/')
+}
+
 pub fn is_builtin_file(url: &Url) -> bool {
-    let cache_dir = temp_dir().join("amber-lsp");
+    embedded_path(url).is_some_and(|path| path.ends_with("builtin.ab"))
+}
 
-    let file_path = match url.to_file_path() {
-        Ok(path) => path,
-        Err(_) => {
-            warn!("Invalid file path for URL: {}", url);
-            return false;
-        }
-    };
+/// Amber version of the embedded stdlib the file belongs to.
+///
+/// Returns [`None`] if the file is not part of the embedded stdlib.
+pub fn stdlib_version(url: &Url) -> Option<AmberVersion> {
+    match embedded_path(url)?.split('/').next()? {
+        "alpha034" => Some(AmberVersion::Alpha034),
+        "alpha035" => Some(AmberVersion::Alpha035),
+        "alpha040" => Some(AmberVersion::Alpha040),
+        _ => None,
+    }
+}
+
+/// Contents of a document of the embedded stdlib.
+pub fn stdlib_contents(url: &Url) -> Option<String> {
+    let file = STDLIB.get_file(embedded_path(url)?)?;
 
-    file_path.starts_with(cache_dir) && file_path.ends_with("builtin.ab")
+    file.contents_utf8().map(|contents| contents.to_string())
 }
 
-#[tracing::instrument(skip(backend))]
-async fn save_resources(backend: &Backend) -> PathBuf {
-    let cache_dir = temp_dir().join("amber-lsp");
-
-    let path = match backend.amber_version {
-        AmberVersion::Alpha034 => "alpha034/".to_string(),
-        AmberVersion::Alpha035 => "alpha035/".to_string(),
-        AmberVersion::Alpha040 => "alpha040/".to_string(),
-    };
-
-    let _ = backend
-        .files
-        .fs
-        .create_dir_all(&cache_dir.join(path.clone()))
-        .await;
-
-    if let Some(dir) = STDLIB.get_dir(path.clone()) {
-        for entry in dir.entries() {
-            save_entry(backend, &cache_dir, entry).await;
+/// Directories of external standard libraries used by the document, in order of priority.
+///
+/// These are the configured `stdlibPath` and, if enabled, the standard library installed
+/// with the compiler.
+pub async fn external_stdlib_dirs(backend: &Backend, uri: &Url) -> Vec<PathBuf> {
+    let settings = backend.settings_for(uri);
+
+    let mut dirs = vec![];
+
+    if let Some(stdlib_path) = settings.stdlib_path {
+        dirs.push(stdlib_path);
+    }
+
+    if settings.use_installed_stdlib {
+        if let Some(installed_dir) = installed_stdlib_dir(backend, uri).await {
+            dirs.push(installed_dir);
         }
     }
 
-    cache_dir.join(path.clone())
+    dirs
 }
 
-fn save_entry<'a>(
-    backend: &'a Backend,
-    current_path: &'a PathBuf,
-    entry: &'a DirEntry<'a>,
-) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
-    Box::pin(async move {
-        match entry {
-            DirEntry::Dir(dir) => {
-                let path = current_path.join(dir.path());
-
-                let _ = backend.files.fs.create_dir_all(&path).await;
-                for entry in dir.entries() {
-                    save_entry(backend, current_path, entry).await;
-                }
-            }
-            DirEntry::File(file) => {
-                let path = current_path.join(file.path());
+/// Find the standard library installed next to the compiler used by the document.
+///
+/// It's searched for in the directory of the binary and in `lib/amber` or `share/amber`
+/// of its installation prefix. The installed standard library is not used if the compiler
+/// reports a different version than the one the document is analyzed with.
+async fn installed_stdlib_dir(backend: &Backend, uri: &Url) -> Option<PathBuf> {
+    let compiler = backend.compiler_path(uri).await?;
 
-                if path.exists() {
-                    return;
-                }
+    let amber_version = backend.amber_version_for(uri);
+    if let Some(compiler_version) = backend.compiler_version(&compiler).await {
+        if compiler_version != amber_version {
+            warn!(
+                "Skipping stdlib of {} {}, the document uses {}",
+                compiler.display(),
+                compiler_version,
+                amber_version
+            );
+            return None;
+        }
+    }
 
-                let contents = file.contents_utf8().unwrap().to_string();
+    // The binary on `PATH` is usually a link to the installation directory
+    let compiler = tokio::fs::canonicalize(&compiler).await.unwrap_or(compiler);
+    let bin_dir = compiler.parent()?;
 
-                backend.files.fs.write(&path, &contents).await.unwrap();
-            }
+    let mut candidates = vec![bin_dir.to_path_buf()];
+    if let Some(prefix) = bin_dir.parent() {
+        candidates.push(prefix.join("lib").join("amber"));
+        candidates.push(prefix.join("share").join("amber"));
+    }
+
+    for dir in candidates {
+        if backend.files.fs.exists(&dir.join("std")).await {
+            return Some(dir);
         }
-    })
+    }
+
+    None
+}
+
+/// External standard library directory the file belongs to.
+///
+/// Returns [`None`] if the file is not part of an external stdlib used by the document.
+pub async fn external_stdlib_dir_of(backend: &Backend, uri: &Url, file: &Url) -> Option<PathBuf> {
+    let file_path = file.to_file_path().ok()?;
+
+    external_stdlib_dirs(backend, uri)
+        .await
+        .into_iter()
+        .find(|dir| file_path.starts_with(dir))
 }
 
+/// First existing external standard library directory of the document.
+async fn external_stdlib_dir(backend: &Backend, uri: &Url) -> Option<PathBuf> {
+    for dir in external_stdlib_dirs(backend, uri).await {
+        if backend.files.fs.exists(&dir).await {
+            return Some(dir);
+        }
+    }
+
+    None
+}
+
+/// Resolve a stdlib import of the document.
+///
+/// External standard libraries are searched first, the embedded one is the fallback.
 #[tracing::instrument(skip(backend))]
-pub async fn resolve(backend: &Backend, path: String) -> Option<Url> {
+pub async fn resolve(backend: &Backend, uri: &Url, path: String) -> Option<Url> {
     let file_path = path + ".ab";
 
-    let memory_path = match backend.amber_version {
-        AmberVersion::Alpha034 => PathBuf::from("alpha034"),
-        AmberVersion::Alpha035 => PathBuf::from("alpha035"),
-        AmberVersion::Alpha040 => PathBuf::from("alpha040"),
-    }
-    .join(file_path.clone());
+    for dir in external_stdlib_dirs(backend, uri).await {
+        let external_path = dir.join(&file_path);
+
+        if backend.files.fs.exists(&external_path).await {
+            return Url::from_file_path(external_path).ok();
+        }
 
-    if !STDLIB.contains(memory_path.clone()) {
         warn!(
-            "File not found in stdlib: {}",
-            memory_path.clone().to_str().unwrap()
+            "File not found in external stdlib: {}",
+            external_path.to_str().unwrap()
         );
-        return None;
     }
 
-    let base_path = save_resources(backend).await;
+    let amber_version = backend.amber_version_for(uri);
 
-    let file_path = base_path.join(file_path);
+    let memory_path = PathBuf::from(version_dir(&amber_version)).join(&file_path);
 
-    warn!("File found in resources: {}", file_path.to_str().unwrap());
+    if !STDLIB.contains(&memory_path) {
+        warn!(
+            "File not found in stdlib: {}",
+            memory_path.to_str().unwrap()
+        );
+        return None;
+    }
 
-    Url::from_file_path(file_path).ok()
+    Some(stdlib_uri(&amber_version, &file_path))
 }
 
-pub async fn find_in_stdlib(backend: &Backend, path: &str) -> Vec<String> {
+pub async fn find_in_stdlib(backend: &Backend, uri: &Url, path: &str) -> Vec<String> {
     let parts = path.split('/').collect::<Vec<&str>>();
 
-    match backend.amber_version {
+    let amber_version = backend.amber_version_for(uri);
+
+    match amber_version {
         AmberVersion::Alpha034 => {
             vec!["std".to_string()]
         }
@@ -122,32 +206,61 @@ pub async fn find_in_stdlib(backend: &Backend, path: &str) -> Vec<String> {
                 return vec![];
             }
 
-            let stdlib_dir = save_resources(backend).await;
-
-            let path_in_std = stdlib_dir.clone().join(parts.join("/"));
-
-            backend
-                .files
-                .fs
-                .read_dir(&path_in_std)
-                .await
-                .iter()
-                .filter(|path| path.is_dir() || (path.extension() == Some(&OsStr::from("ab"))))
-                .map(|path| {
-                    let base_path = path
-                        .strip_prefix(stdlib_dir.clone().join("std"))
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string();
-
-                    if path.is_file() {
-                        return base_path.strip_suffix(".ab").unwrap().to_string();
-                    }
-
-                    base_path
-                })
-                .collect()
+            match external_stdlib_dir(backend, uri).await {
+                Some(stdlib_dir) => {
+                    let path_in_std = stdlib_dir.join(parts.join("/"));
+
+                    backend
+                        .files
+                        .fs
+                        .read_dir(&path_in_std)
+                        .await
+                        .iter()
+                        .filter(|path| path.is_dir() || (path.extension() == Some("ab".as_ref())))
+                        .map(|path| {
+                            let base_path = path
+                                .strip_prefix(stdlib_dir.join("std"))
+                                .unwrap()
+                                .to_str()
+                                .unwrap()
+                                .to_string();
+
+                            if path.is_file() {
+                                return base_path.strip_suffix(".ab").unwrap().to_string();
+                            }
+
+                            base_path
+                        })
+                        .collect()
+                }
+                None => {
+                    let std_dir = PathBuf::from(version_dir(&amber_version)).join("std");
+
+                    let dir = match STDLIB.get_dir(std_dir.join(parts[1..].join("/"))) {
+                        Some(dir) => dir,
+                        None => return vec![],
+                    };
+
+                    dir.entries()
+                        .iter()
+                        .filter_map(|entry| {
+                            let base_path = entry
+                                .path()
+                                .strip_prefix(&std_dir)
+                                .ok()?
+                                .to_str()?
+                                .to_string();
+
+                            match entry {
+                                DirEntry::Dir(_) => Some(base_path),
+                                DirEntry::File(_) => {
+                                    base_path.strip_suffix(".ab").map(|path| path.to_string())
+                                }
+                            }
+                        })
+                        .collect()
+                }
+            }
         }
     }
 }
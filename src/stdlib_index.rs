@@ -0,0 +1,371 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+
+use include_dir::{Dir, DirEntry};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tower_lsp::{lsp_types::Url, LspService};
+
+use crate::{
+    analysis::{
+        insert_symbol_definition,
+        types::{DataType, GenericsMap},
+        FunctionArgument, FunctionSymbol, SymbolInfo, SymbolTable, SymbolType,
+    },
+    backend::{AmberVersion, Backend},
+    files::{FileVersion, DEFAULT_VERSION},
+    fs::MemoryFS,
+    grammar::{CompilerFlag, Span},
+    paths::FileId,
+    settings::VersionSetting,
+    stdlib::{embedded_path, stdlib_contents, stdlib_uri, version_dir, STDLIB},
+};
+
+/// Path of the checked in index, relative to the crate root.
+pub const STDLIB_INDEX_PATH: &str = "src/stdlib_index.json";
+
+/// Public functions of the embedded stdlib.
+///
+/// Stdlib imports are resolved from the index, so the stdlib doesn't have to be parsed
+/// and analyzed every time the server starts. The index is generated with
+/// `cargo xtask stdlib-index` and checked in next to this file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StdlibIndex {
+    pub modules: Vec<IndexedModule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedModule {
+    /// Path of the module in [`STDLIB`], e.g. `alpha040/std/text.ab`.
+    pub path: String,
+    pub functions: Vec<IndexedFunction>,
+    /// Generic types used in the signatures of the functions.
+    pub generics: Vec<IndexedGeneric>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFunction {
+    pub name: String,
+    /// Span of the function name.
+    pub span: (usize, usize),
+    pub arguments: Vec<(FunctionArgument, (usize, usize))>,
+    pub data_type: DataType,
+    pub compiler_flags: Vec<CompilerFlag>,
+    pub docs: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedGeneric {
+    /// Id of the generic in the module, a new one is assigned when it's loaded.
+    pub id: usize,
+    pub constraint: DataType,
+    pub is_inferred: bool,
+}
+
+/// Analyze the embedded stdlib of every supported Amber version.
+///
+/// Imports between stdlib modules are analyzed too, instead of being loaded from the
+/// current index.
+pub async fn build() -> StdlibIndex {
+    let (service, _) = LspService::new(|client| {
+        let mut backend = Backend::new(
+            client,
+            VersionSetting::Auto,
+            Some(Arc::new(MemoryFS::new())),
+        );
+        backend.use_stdlib_index = false;
+
+        backend
+    });
+    let backend = service.inner();
+
+    let mut modules = vec![];
+
+    for amber_version in [
+        AmberVersion::Alpha034,
+        AmberVersion::Alpha035,
+        AmberVersion::Alpha040,
+    ] {
+        let version_dir = version_dir(&amber_version);
+
+        let dir = match STDLIB.get_dir(version_dir) {
+            Some(dir) => dir,
+            None => continue,
+        };
+
+        let mut paths = vec![];
+        module_paths(dir, &mut paths);
+        paths.sort();
+
+        for path in paths {
+            let module_path = path.strip_prefix(&format!("{}/", version_dir)).unwrap();
+            let uri = stdlib_uri(&amber_version, module_path);
+
+            let file = match backend.open_document(&uri).await {
+                Ok(file) => file,
+                Err(_) => panic!("Failed to analyze stdlib module {}", path),
+            };
+
+            modules.push(index_module(backend, path, file));
+        }
+    }
+
+    StdlibIndex { modules }
+}
+
+fn module_paths(dir: &Dir, paths: &mut Vec<String>) {
+    for entry in dir.entries() {
+        match entry {
+            DirEntry::Dir(dir) => module_paths(dir, paths),
+            DirEntry::File(file) if file.path().extension() == Some("ab".as_ref()) => {
+                paths.push(file.path().to_string_lossy().replace('\\', "/"));
+            }
+            DirEntry::File(_) => {}
+        }
+    }
+}
+
+fn index_module(backend: &Backend, path: String, file: (FileId, FileVersion)) -> IndexedModule {
+    let symbol_table = backend
+        .files
+        .symbol_table
+        .get(&file)
+        .map(|symbol_table| symbol_table.clone())
+        .unwrap_or_default();
+
+    let mut definitions = symbol_table
+        .public_definitions
+        .values()
+        .filter(|location| location.file == file)
+        .collect::<Vec<_>>();
+    definitions.sort_by_key(|location| location.start);
+
+    let mut functions = vec![];
+    let mut generics = vec![];
+
+    for location in definitions {
+        let symbol_info = match symbol_table.symbols.get(&location.start) {
+            Some(symbol_info) => symbol_info,
+            None => continue,
+        };
+
+        let FunctionSymbol {
+            arguments,
+            compiler_flags,
+            docs,
+            ..
+        } = match &symbol_info.symbol_type {
+            SymbolType::Function(function) => function,
+            _ => continue,
+        };
+
+        for (argument, _) in arguments {
+            collect_generics(
+                &backend.files.generic_types,
+                &argument.data_type,
+                &mut generics,
+            );
+        }
+        collect_generics(
+            &backend.files.generic_types,
+            &symbol_info.data_type,
+            &mut generics,
+        );
+
+        functions.push(IndexedFunction {
+            name: symbol_info.name.clone(),
+            span: (symbol_info.span.start, symbol_info.span.end),
+            arguments: arguments
+                .iter()
+                .map(|(argument, span)| (argument.clone(), (span.start, span.end)))
+                .collect(),
+            data_type: symbol_info.data_type.clone(),
+            compiler_flags: compiler_flags.clone(),
+            docs: docs.clone(),
+        });
+    }
+
+    // Number the generics of each module from zero, so the index doesn't depend on
+    // the order the modules are analyzed in
+    let ids = generics
+        .iter()
+        .enumerate()
+        .map(|(index, generic)| (generic.id, index))
+        .collect::<HashMap<_, _>>();
+
+    IndexedModule {
+        path,
+        functions: functions
+            .into_iter()
+            .map(|function| IndexedFunction {
+                arguments: function
+                    .arguments
+                    .into_iter()
+                    .map(|(argument, span)| {
+                        (
+                            FunctionArgument {
+                                data_type: remap_generics(&argument.data_type, &ids),
+                                ..argument
+                            },
+                            span,
+                        )
+                    })
+                    .collect(),
+                data_type: remap_generics(&function.data_type, &ids),
+                ..function
+            })
+            .collect(),
+        generics: generics
+            .into_iter()
+            .map(|generic| IndexedGeneric {
+                id: ids[&generic.id],
+                constraint: remap_generics(&generic.constraint, &ids),
+                is_inferred: generic.is_inferred,
+            })
+            .collect(),
+    }
+}
+
+fn collect_generics(generics_map: &GenericsMap, ty: &DataType, generics: &mut Vec<IndexedGeneric>) {
+    match ty {
+        DataType::Generic(id) => {
+            if generics.iter().any(|generic| generic.id == *id) {
+                return;
+            }
+
+            let constraint = generics_map.get(*id);
+
+            generics.push(IndexedGeneric {
+                id: *id,
+                constraint: constraint.clone(),
+                is_inferred: generics_map.is_inferred(*id),
+            });
+
+            collect_generics(generics_map, &constraint, generics);
+        }
+        DataType::Array(ty) | DataType::Failable(ty) => {
+            collect_generics(generics_map, ty, generics)
+        }
+        DataType::Union(types) => types
+            .iter()
+            .for_each(|ty| collect_generics(generics_map, ty, generics)),
+        _ => {}
+    }
+}
+
+/// Replace generic ids with the ids they're mapped to.
+fn remap_generics(ty: &DataType, ids: &HashMap<usize, usize>) -> DataType {
+    match ty {
+        DataType::Generic(id) => DataType::Generic(*ids.get(id).unwrap_or(id)),
+        DataType::Array(ty) => DataType::Array(Box::new(remap_generics(ty, ids))),
+        DataType::Failable(ty) => DataType::Failable(Box::new(remap_generics(ty, ids))),
+        DataType::Union(types) => {
+            DataType::Union(types.iter().map(|ty| remap_generics(ty, ids)).collect())
+        }
+        ty => ty.clone(),
+    }
+}
+
+static STDLIB_INDEX: LazyLock<StdlibIndex> = LazyLock::new(|| {
+    serde_json::from_str(include_str!("stdlib_index.json")).expect("Invalid stdlib index")
+});
+
+/// Load a module of the embedded stdlib from the index, without parsing it.
+///
+/// Returns [`None`] if the document is not part of the embedded stdlib, it has to be opened
+/// as any other document then.
+pub fn load(backend: &Backend, uri: &Url) -> Option<(FileId, FileVersion)> {
+    if !backend.use_stdlib_index {
+        return None;
+    }
+
+    let path = embedded_path(uri)?;
+
+    if let Some(file_id) = backend.files.get(uri) {
+        return Some((file_id, backend.files.get_latest_version(file_id)));
+    }
+
+    let module = STDLIB_INDEX
+        .modules
+        .iter()
+        .find(|module| module.path == path)?;
+    let text = stdlib_contents(uri)?;
+
+    let file = (
+        backend.files.insert(uri.clone(), DEFAULT_VERSION),
+        DEFAULT_VERSION,
+    );
+
+    backend
+        .files
+        .document_map
+        .insert(file, Rope::from_str(&text));
+
+    let generics_map = &backend.files.generic_types;
+
+    let ids = module
+        .generics
+        .iter()
+        .map(|generic| (generic.id, generics_map.new_generic_id()))
+        .collect::<HashMap<_, _>>();
+
+    for generic in module.generics.iter() {
+        let id = ids[&generic.id];
+
+        generics_map.constrain_generic_type(id, remap_generics(&generic.constraint, &ids));
+
+        if generic.is_inferred {
+            generics_map.mark_as_inferred(id);
+        }
+    }
+
+    generics_map.insert(file.0, file.1, ids.values().copied().collect());
+
+    let mut symbol_table = SymbolTable::default();
+
+    for function in module.functions.iter() {
+        insert_symbol_definition(
+            &mut symbol_table,
+            &SymbolInfo {
+                name: function.name.clone(),
+                symbol_type: SymbolType::Function(FunctionSymbol {
+                    arguments: function
+                        .arguments
+                        .iter()
+                        .map(|(argument, (start, end))| {
+                            (
+                                FunctionArgument {
+                                    data_type: remap_generics(&argument.data_type, &ids),
+                                    ..argument.clone()
+                                },
+                                Span::new(*start, *end),
+                            )
+                        })
+                        .collect(),
+                    is_public: true,
+                    compiler_flags: function.compiler_flags.clone(),
+                    docs: function.docs.clone(),
+                }),
+                data_type: remap_generics(&function.data_type, &ids),
+                is_definition: true,
+                undefined: false,
+                span: Span::new(function.span.0, function.span.1),
+                contexts: vec![],
+            },
+            file,
+            function.span.1..=usize::MAX,
+            true,
+        );
+    }
+
+    backend.files.symbol_table.insert(file, symbol_table);
+    backend
+        .files
+        .analyze_lock
+        .insert(file, Arc::new(RwLock::new(true)));
+
+    Some(file)
+}
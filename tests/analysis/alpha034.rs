@@ -3,12 +3,15 @@ use std::{path::Path, sync::Arc};
 use amber_lsp::{
     analysis::{FunctionSymbol, SymbolType},
     backend::{AmberVersion, Backend},
+    diagnostics::DiagnosticKind,
     fs::MemoryFS,
 };
 use insta::assert_debug_snapshot;
 use tokio::test;
 use tower_lsp::{lsp_types::Url, LspService};
 
+use super::{diagnostics, has_range_index};
+
 #[test]
 async fn test_function_definition() {
     let (service, _) = LspService::new(|client| {
@@ -59,7 +62,7 @@ async fn test_function_definition() {
     let b_defs = symbol_table.definitions.get("b").unwrap();
 
     assert_debug_snapshot!(foo_defs.get(&usize::MAX)); // in socpe
-    assert_debug_snapshot!(foo_defs.get(&60)); // in body - out of scope
+    assert_debug_snapshot!(foo_defs.get(&60)); // in body - recursive call
 
     assert_debug_snapshot!(a_defs.get(&63)); // out of scope
     assert_debug_snapshot!(a_defs.get(&17)); // in scope
@@ -544,3 +547,87 @@ async fn test_generics_reference() {
 
     assert_debug_snapshot!(symbol_table.symbols);
 }
+
+#[test]
+async fn test_range_index_rules() {
+    assert!(!has_range_index(&AmberVersion::Alpha034));
+}
+
+#[test]
+async fn test_failable_function_calls() {
+    let text = r#"
+fun risky(): Num {
+    if false {
+        fail 1
+    }
+    return 1
+}
+
+fun safe(): Num {
+    return 1
+}
+
+fun unhandled() {
+    risky()
+}
+
+fun handled() {
+    risky() failed {
+        echo "failed"
+    }
+    unsafe risky()
+    unsafe {
+        risky()
+    }
+}
+
+fun redundant() {
+    unsafe safe()
+}
+
+fun inferred() {
+    risky()?
+}
+
+main {
+    inferred()?
+}
+
+unhandled()
+inferred()
+risky()?
+"#;
+
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha034,
+            text,
+            &[DiagnosticKind::Analysis, DiagnosticKind::UnnecessaryTrust],
+        )
+        .await,
+        vec![
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked as unsafe"
+                    .to_string(),
+                "risky".to_string(),
+            ),
+            (
+                DiagnosticKind::UnnecessaryTrust,
+                "Function call can't fail, `unsafe` has no effect".to_string(),
+                "unsafe".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked as unsafe"
+                    .to_string(),
+                "inferred".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Propagate can only be used inside of main block or function".to_string(),
+                "?".to_string(),
+            ),
+        ]
+    );
+}
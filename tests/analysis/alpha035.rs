@@ -0,0 +1,94 @@
+use amber_lsp::{backend::AmberVersion, diagnostics::DiagnosticKind};
+use tokio::test;
+
+use super::{diagnostics, has_range_index};
+
+const FAILURES: &[DiagnosticKind] = &[
+    DiagnosticKind::Analysis,
+    DiagnosticKind::UnnecessaryTrust,
+    DiagnosticKind::MisplacedPropagation,
+];
+
+#[test]
+async fn test_failable_function_calls() {
+    let text = r#"
+fun risky(): Num? {
+    if false {
+        fail 1
+    }
+    return 1
+}
+
+fun safe(): Num {
+    return 1
+}
+
+fun unhandled() {
+    risky()
+}
+
+fun handled() {
+    risky() failed {
+        echo "failed"
+    }
+    unsafe risky()
+    unsafe {
+        risky()
+    }
+}
+
+fun redundant() {
+    unsafe safe()
+}
+
+fun not_failable(): Num {
+    let x = risky()?
+    return x
+}
+
+fun inferred() {
+    risky()?
+}
+
+unhandled()
+inferred()
+"#;
+
+    assert_eq!(
+        diagnostics(AmberVersion::Alpha035, text, FAILURES).await,
+        vec![
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked as unsafe"
+                    .to_string(),
+                "risky".to_string(),
+            ),
+            (
+                DiagnosticKind::UnnecessaryTrust,
+                "Function call can't fail, `unsafe` has no effect".to_string(),
+                "unsafe".to_string(),
+            ),
+            (
+                DiagnosticKind::MisplacedPropagation,
+                "Failure can't be propagated from a function returning Num, declare its return type as failable".to_string(),
+                "?".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Function is propagating an error, but return type is not failable".to_string(),
+                "Num".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked as unsafe"
+                    .to_string(),
+                "inferred".to_string(),
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_range_index_rules() {
+    assert!(!has_range_index(&AmberVersion::Alpha035));
+}
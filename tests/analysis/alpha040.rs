@@ -0,0 +1,982 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    diagnostics::DiagnosticKind,
+    fs::MemoryFS,
+    grammar::Span,
+};
+use tokio::test;
+use tower_lsp::{lsp_types::Url, LspService};
+
+use super::{diagnostics, file_diagnostics, has_range_index};
+
+const FLOW: &[DiagnosticKind] = &[
+    DiagnosticKind::UnreachableCode,
+    DiagnosticKind::MissingReturn,
+];
+
+#[test]
+async fn test_missing_return() {
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            r#"
+fun sign(x: Num): Num {
+    if {
+        x > 0 {
+            return 1
+        }
+        x < 0 {
+            return -1
+        }
+    }
+}
+
+fun sign_or_zero(x: Num): Num {
+    if {
+        x > 0: return 1
+        x < 0: return -1
+        else: return 0
+    }
+}
+
+fun abs(x: Num): Num {
+    if x < 0 {
+        return -x
+    } else {
+        return x
+    }
+}
+
+fun first(items: [Num]): Num {
+    for item in items {
+        return item
+    }
+}
+
+fun forever(): Num {
+    loop {
+        echo 1
+    }
+}
+
+fun parse(text: Text): Num? {
+    if text == "" {
+        fail 1
+    }
+    return 1
+}
+
+fun log(text: Text): Null {
+    echo text
+}
+"#,
+            FLOW
+        )
+        .await,
+        vec![
+            (
+                DiagnosticKind::MissingReturn,
+                "Not all code paths return a value".to_string(),
+                "sign".to_string()
+            ),
+            (
+                DiagnosticKind::MissingReturn,
+                "Not all code paths return a value".to_string(),
+                "first".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_unreachable_code() {
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            r#"
+fun foo(x: Num): Num {
+    return x
+    echo "after return"
+    if x > 0 {
+        echo "nested"
+    }
+}
+
+fun bar(): Null? {
+    fail 1
+    echo "after fail"
+}
+
+main {
+    loop {
+        break
+        echo "after break"
+    }
+
+    for i in [1, 2] {
+        continue
+        echo "after continue"
+    }
+
+    $ls$ failed {
+        exit 1
+        echo "after exit"
+    }
+
+    echo "reachable"
+    exit 0
+    echo "after exit"
+}
+"#,
+            FLOW
+        )
+        .await
+        .into_iter()
+        .map(|(kind, message, text)| {
+            assert_eq!(kind, DiagnosticKind::UnreachableCode);
+            assert_eq!(message, "Unreachable code");
+            text
+        })
+        .collect::<Vec<_>>(),
+        vec![
+            "echo \"after return\"",
+            "if x > 0 {\n        echo \"nested\"\n    }",
+            "echo \"after fail\"",
+            "echo \"after break\"",
+            "echo \"after continue\"",
+            "echo \"after exit\"",
+            "echo \"after exit\"",
+        ]
+    );
+}
+
+#[test]
+async fn test_loop_without_break_never_ends() {
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            r#"
+main {
+    loop {
+        if true {
+            continue
+        }
+    }
+    echo "never"
+}
+"#,
+            FLOW
+        )
+        .await,
+        vec![(
+            DiagnosticKind::UnreachableCode,
+            "Unreachable code".to_string(),
+            "echo \"never\"".to_string()
+        )]
+    );
+}
+
+const FLAG_LINTS: &[DiagnosticKind] = &[
+    DiagnosticKind::NestedIfElse,
+    DiagnosticKind::GenericReturn,
+    DiagnosticKind::AbsurdCast,
+];
+
+#[test]
+async fn test_compiler_flag_lints() {
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            r#"
+fun nested(x: Num): Null {
+    if x > 0 {
+        echo "positive"
+    } else {
+        if x < 0 {
+            echo "negative"
+        }
+    }
+}
+
+fun identity(value) {
+    return value
+}
+
+fun casts(): Null {
+    let number = "12" as Num
+    let flag = 1 as Bool
+    let text = [1, 2] as Text
+    let numbers = ["a"] as [Num]
+}
+"#,
+            FLAG_LINTS
+        )
+        .await,
+        vec![
+            (
+                DiagnosticKind::NestedIfElse,
+                "Use an if chain instead of nested if else statements, or `#[allow_nested_if_else]` to allow it".to_string(),
+                "if x < 0 {\n            echo \"negative\"\n        }".to_string()
+            ),
+            (
+                DiagnosticKind::GenericReturn,
+                "Function returns a generic type, declare its return type or use `#[allow_generic_return]` to allow it".to_string(),
+                "identity".to_string()
+            ),
+            (
+                DiagnosticKind::AbsurdCast,
+                "Casting a value of type [Num] to Text is not recommended, use `#[allow_absurd_cast]` to allow it".to_string(),
+                "[1, 2] as Text".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_compiler_flags_silence_lints() {
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            r#"
+#[allow_nested_if_else]
+fun nested(x: Num): Null {
+    if x > 0 {
+        echo "positive"
+    } else {
+        if x < 0 {
+            echo "negative"
+        }
+    }
+}
+
+#[allow_generic_return]
+fun identity(value) {
+    return value
+}
+
+#[allow_absurd_cast]
+fun casts(): Null {
+    let text = [1, 2] as Text
+}
+"#,
+            FLAG_LINTS
+        )
+        .await,
+        vec![]
+    );
+}
+
+const FAILURES: &[DiagnosticKind] = &[
+    DiagnosticKind::Analysis,
+    DiagnosticKind::UnnecessaryTrust,
+    DiagnosticKind::MisplacedPropagation,
+];
+
+#[test]
+async fn test_failable_function_calls() {
+    let text = r#"
+fun risky(): Num? {
+    if false {
+        fail 1
+    }
+    return 1
+}
+
+fun safe(): Num {
+    return 1
+}
+
+fun unhandled() {
+    risky()
+}
+
+fun handled() {
+    risky() failed {
+        echo "failed"
+    }
+    trust risky()
+    trust {
+        risky()
+    }
+}
+
+fun redundant() {
+    trust safe()
+    unsafe safe()
+}
+
+fun propagating(): Num? {
+    let x = risky()?
+    return x
+}
+
+fun not_failable(): Num {
+    let x = risky()?
+    return x
+}
+
+fun inferred() {
+    risky()?
+}
+
+unhandled()
+inferred()
+"#;
+
+    assert_eq!(
+        diagnostics(AmberVersion::Alpha040, text, FAILURES).await,
+        vec![
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked with `trust` modifier".to_string(),
+                "risky".to_string(),
+            ),
+            (
+                DiagnosticKind::UnnecessaryTrust,
+                "Function call can't fail, `trust` has no effect".to_string(),
+                "trust".to_string(),
+            ),
+            (
+                DiagnosticKind::UnnecessaryTrust,
+                "Function call can't fail, `unsafe` has no effect".to_string(),
+                "unsafe".to_string(),
+            ),
+            (
+                DiagnosticKind::MisplacedPropagation,
+                "Failure can't be propagated from a function returning Num, declare its return type as failable".to_string(),
+                "?".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Function is propagating an error, but return type is not failable".to_string(),
+                "Num".to_string(),
+            ),
+            (
+                DiagnosticKind::Analysis,
+                "Failable function must be handled with a failure handler or marked with `trust` modifier".to_string(),
+                "inferred".to_string(),
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_is_narrowing() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+
+    let text = r#"let value = true then 1 else "one"
+
+if value is Text {
+    let text = value
+    echo text
+}
+
+if {
+    value is Num: echo value + 1
+    else: echo value
+}
+
+let label = value is Text then value else "number"
+
+if value is Num {
+    value = "two"
+    echo value
+}
+"#;
+
+    backend.files.fs.write(file, text).await.unwrap();
+
+    let uri = Url::from_file_path(file).unwrap();
+    let file_id = backend.open_document(&uri).await.unwrap();
+
+    assert!(!backend
+        .files
+        .diagnostics
+        .get(&file_id)
+        .map(|diagnostics| diagnostics.clone())
+        .unwrap_or_default()
+        .iter()
+        .any(|diagnostic| diagnostic.kind == DiagnosticKind::Analysis));
+
+    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap().clone();
+
+    let types = symbol_table
+        .symbols
+        .iter()
+        .filter(|(_, symbol_info)| symbol_info.name != "value" || !symbol_info.is_definition)
+        .map(|(_, symbol_info)| {
+            (
+                symbol_info.name.clone(),
+                symbol_info
+                    .data_type
+                    .to_string(&backend.files.generic_types),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let types = types
+        .iter()
+        .map(|(name, ty)| (name.as_str(), ty.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        types,
+        vec![
+            ("value", "Num | Text"),
+            ("text", "Text"),
+            ("value", "Text"),
+            ("text", "Text"),
+            ("value", "Num | Text"),
+            ("value", "Num"),
+            ("value", "Num | Text"),
+            ("label", "Text"),
+            ("value", "Num | Text"),
+            ("value", "Text"),
+            ("value", "Num | Text"),
+            ("value", "Num | Text"),
+            ("value", "Num | Text"),
+        ]
+    );
+}
+
+#[test]
+async fn test_recursive_functions() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+
+    let text = r#"fun factorial(n: Num) {
+    if n <= 1 {
+        return 1
+    }
+    return n * factorial(n - 1)
+}
+
+fun is_even(n: Num) {
+    if n == 0 {
+        return true
+    }
+    return is_odd(n - 1)
+}
+
+fun is_odd(n: Num) {
+    if n == 0 {
+        return false
+    }
+    return is_even(n - 1)
+}
+
+echo factorial(5)
+echo first()
+
+fun first(): Num {
+    return 1
+}
+"#;
+
+    backend.files.fs.write(file, text).await.unwrap();
+
+    let uri = Url::from_file_path(file).unwrap();
+    let file_id = backend.open_document(&uri).await.unwrap();
+
+    let diagnostics = backend
+        .files
+        .diagnostics
+        .get(&file_id)
+        .map(|diagnostics| diagnostics.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect::<Vec<_>>();
+
+    // Code outside of function bodies can't call functions defined after it
+    assert_eq!(diagnostics, vec!["first is not defined".to_string()]);
+
+    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap().clone();
+
+    let types = ["factorial", "is_even", "is_odd"]
+        .iter()
+        .map(|name| {
+            let start = text.find(&format!("fun {}", name)).unwrap() + 4;
+            let symbol_info = symbol_table.symbols.get(&start).unwrap();
+
+            (
+                symbol_info.name.clone(),
+                symbol_info
+                    .data_type
+                    .to_string(&backend.files.generic_types),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        types,
+        vec![
+            ("factorial".to_string(), "Num".to_string()),
+            ("is_even".to_string(), "Bool".to_string()),
+            ("is_odd".to_string(), "Bool".to_string()),
+        ]
+    );
+}
+
+#[test]
+async fn test_unassigned_variables() {
+    let text = r#"
+fun branches(cond: Bool): Num {
+    let x = Num
+    if cond {
+        x = 1
+    }
+    echo x
+
+    let y = Num
+    if cond {
+        y = 1
+    } else {
+        y = 2
+    }
+    echo y
+
+    let z = Num
+    if {
+        cond: z = 1
+        else: echo z
+    }
+    return z
+}
+
+fun loops(items: [Num]) {
+    let last = Num
+    for item in items {
+        last = item
+    }
+    echo last
+
+    let sum = Num
+    loop {
+        sum = 0
+        break
+    }
+    echo sum
+}
+
+fun handlers() {
+    let out = Text
+    out = $echo 1$ failed {
+        echo out
+    }
+    echo out
+}
+
+fun shadowing() {
+    let x = Num
+    if true {
+        let x = 1
+        echo x
+    }
+    echo x
+}
+
+let empty = [Num]
+echo empty
+
+let top = Text
+echo top
+top = "top"
+echo top
+"#;
+
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            text,
+            &[DiagnosticKind::UnassignedVariable]
+        )
+        .await,
+        ["x", "z", "z", "last", "x", "top"]
+            .iter()
+            .map(|name| (
+                DiagnosticKind::UnassignedVariable,
+                format!("Variable `{}` might be used before it's assigned", name),
+                name.to_string(),
+            ))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+async fn test_conflicting_generic_arguments() {
+    let text = r#"
+fun show(value) {
+    let copy = value
+}
+
+fun calls() {
+    show(1)
+    show("one")
+    show(2)
+    show("two")
+}
+"#;
+
+    let chars = text.chars().collect::<Vec<_>>();
+    let slice = |span: &Span| -> String { chars[span.start..span.end].iter().collect() };
+
+    assert_eq!(
+        file_diagnostics(AmberVersion::Alpha040, text)
+            .await
+            .iter()
+            .map(|diagnostic| (
+                diagnostic.message.clone(),
+                slice(&diagnostic.span),
+                diagnostic
+                    .related
+                    .iter()
+                    .map(|(message, span)| (message.clone(), slice(span)))
+                    .collect::<Vec<_>>(),
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "Argument `value` of `show` is given type `Text`, conflicting with earlier calls"
+                    .to_string(),
+                "\"one\"".to_string(),
+                vec![(
+                    "`value` is given type `Num` here".to_string(),
+                    "1".to_string()
+                )],
+            ),
+            (
+                "Argument `value` of `show` is given type `Text`, conflicting with earlier calls"
+                    .to_string(),
+                "\"two\"".to_string(),
+                vec![
+                    (
+                        "`value` is given type `Num` here".to_string(),
+                        "1".to_string()
+                    ),
+                    (
+                        "`value` is given type `Num` here".to_string(),
+                        "2".to_string()
+                    ),
+                ],
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_operator_types() {
+    let text = r#"
+let difference = 1 - "one"
+let numbers = [1] + [2]
+let mixed = [1] + ["one"]
+let is_less = 1 < 2
+let is_before = "a" < "b"
+let is_same = 1 == "1"
+let are_same = [1] == [1]
+let range = 1.."ten"
+let number = 5
+let digit = number[0]
+let first = numbers[0]
+let texts = ["one"]
+texts += [1]
+texts += ["two"]
+"#;
+
+    let error = |message: &str, text: &str| {
+        (
+            DiagnosticKind::Analysis,
+            message.to_string(),
+            text.to_string(),
+        )
+    };
+
+    assert_eq!(
+        diagnostics(AmberVersion::Alpha040, text, &[DiagnosticKind::Analysis]).await,
+        vec![
+            error("Cannot apply `-` to Num and Text", "1 - \"one\""),
+            error("Cannot apply `+` to [Num] and [Text]", "[1] + [\"one\"]"),
+            error("Cannot apply `==` to Num and Text", "1 == \"1\""),
+            error("Cannot apply `..` to Num and Text", "1..\"ten\""),
+            error("Cannot index Num with Num", "number[0"),
+            error("Cannot apply `+` to [Text] and [Num]", "texts += [1]"),
+        ]
+    );
+}
+
+#[test]
+async fn test_module_structure() {
+    let text = r#"
+fun add(a: Num, a: Num): Num {
+    return a
+}
+
+fun add(a: Num): Num {
+    return a
+}
+
+main {
+    echo add(1, 2)
+}
+
+main {
+    echo "again"
+}
+"#;
+
+    let chars = text.chars().collect::<Vec<_>>();
+    let text_at = |span: Span| chars[span.start..span.end].iter().collect::<String>();
+
+    assert_eq!(
+        file_diagnostics(AmberVersion::Alpha040, text)
+            .await
+            .into_iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::Analysis)
+            .map(|diagnostic| (
+                diagnostic.message,
+                text_at(diagnostic.span),
+                diagnostic
+                    .related
+                    .into_iter()
+                    .map(|(message, span)| (message, text_at(span)))
+                    .collect::<Vec<_>>(),
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "Argument `a` is already defined".to_string(),
+                "a: Num".to_string(),
+                vec![],
+            ),
+            (
+                "Function `add` is already defined".to_string(),
+                "add".to_string(),
+                vec![("`add` is first defined here".to_string(), "add".to_string())],
+            ),
+            (
+                "Only one `main` block is allowed".to_string(),
+                "main".to_string(),
+                vec![("First `main` block is here".to_string(), "main".to_string())],
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_imported_file_checks() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let (src_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\src.ab"), Path::new("C:\\main.ab"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/src.ab"), Path::new("/main.ab"))
+        }
+    };
+
+    backend
+        .files
+        .fs
+        .write(
+            src_file,
+            r#"
+pub fun greet(name: Text): Text {
+    return "Hello {name}"
+}
+
+let greeting = "Hello"
+echo greeting
+
+main {
+    echo greet("world")
+}
+"#,
+        )
+        .await
+        .unwrap();
+
+    let text = r#"import { greet } from "src.ab"
+
+fun greet(name: Text): Text {
+    return name
+}
+"#;
+    backend.files.fs.write(main_file, text).await.unwrap();
+
+    let uri = Url::from_file_path(main_file).unwrap();
+    let file_id = backend.open_document(&uri).await.unwrap();
+
+    let chars = text.chars().collect::<Vec<_>>();
+    let diagnostics = backend
+        .files
+        .diagnostics
+        .get(&file_id)
+        .map(|diagnostics| diagnostics.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|diagnostic| {
+            [
+                DiagnosticKind::ImportSideEffects,
+                DiagnosticKind::ShadowedImport,
+            ]
+            .contains(&diagnostic.kind)
+        })
+        .map(|diagnostic| {
+            (
+                diagnostic.kind,
+                diagnostic.message,
+                chars[diagnostic.span.start..diagnostic.span.end]
+                    .iter()
+                    .collect::<String>(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        diagnostics,
+        vec![
+            (
+                DiagnosticKind::ImportSideEffects,
+                "Imported file has a `main` block, it doesn't run when the file is imported"
+                    .to_string(),
+                "\"src.ab\"".to_string(),
+            ),
+            (
+                DiagnosticKind::ImportSideEffects,
+                "Imported file runs statements at the top level when it's imported".to_string(),
+                "\"src.ab\"".to_string(),
+            ),
+            (
+                DiagnosticKind::ShadowedImport,
+                "`greet` shadows an imported symbol".to_string(),
+                "greet".to_string(),
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_shell_safety_lints() {
+    let text = r#"
+main(args) {
+    let target = args[1]
+    let cache = "cache"
+
+    silent $rm -rf {target}$ failed {
+        echo "Failed to remove {target}"
+    }
+    $rm -rf {cache}/old$ failed {
+        echo "Failed to remove the cache"
+    }
+    trust $rm {cache}$
+    $echo {target}$ failed {
+        echo "Failed"
+    }
+    cd target
+}
+"#;
+
+    let lint = |kind: DiagnosticKind, message: &str, text: &str| {
+        (kind, message.to_string(), text.to_string())
+    };
+
+    assert_eq!(
+        diagnostics(
+            AmberVersion::Alpha040,
+            text,
+            &[
+                DiagnosticKind::TaintedCommand,
+                DiagnosticKind::EmptyDestructiveArgument,
+                DiagnosticKind::SilencedDestructiveCommand,
+            ]
+        )
+        .await,
+        vec![
+            lint(
+                DiagnosticKind::TaintedCommand,
+                "Command is given a value taken from the script arguments",
+                "{target}"
+            ),
+            lint(
+                DiagnosticKind::EmptyDestructiveArgument,
+                "Value given to `rm` may be empty, check it before running the command",
+                "{target}"
+            ),
+            lint(
+                DiagnosticKind::SilencedDestructiveCommand,
+                "`rm` is run with `silent`, hiding what it removes",
+                "silent"
+            ),
+            lint(
+                DiagnosticKind::SilencedDestructiveCommand,
+                "`rm` is run with `trust`, ignoring its failure",
+                "trust"
+            ),
+            lint(
+                DiagnosticKind::TaintedCommand,
+                "Command is given a value taken from the script arguments",
+                "{target}"
+            ),
+            lint(
+                DiagnosticKind::TaintedCommand,
+                "`cd` is given a path taken from the script arguments",
+                "target"
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_range_index_rules() {
+    assert!(has_range_index(&AmberVersion::Alpha040));
+}
@@ -1 +1,88 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    analysis::{
+        operators::{operator_rules, Operator},
+        types::DataType,
+    },
+    backend::{AmberVersion, Backend},
+    diagnostics::{DiagnosticKind, FileDiagnostic},
+    fs::MemoryFS,
+};
+use tower_lsp::{lsp_types::Url, LspService};
+
 pub mod alpha034;
+pub mod alpha035;
+pub mod alpha040;
+
+/// Analyze the document, returning all of its diagnostics.
+pub async fn file_diagnostics(version: AmberVersion, text: &str) -> Vec<FileDiagnostic> {
+    let (service, _) =
+        LspService::new(|client| Backend::new(client, version, Some(Arc::new(MemoryFS::new()))));
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    backend.files.fs.write(file, text).await.unwrap();
+
+    let uri = Url::from_file_path(file).unwrap();
+    let file_id = backend.open_document(&uri).await.unwrap();
+
+    let diagnostics = backend
+        .files
+        .diagnostics
+        .get(&file_id)
+        .map(|diagnostics| diagnostics.clone())
+        .unwrap_or_default();
+
+    assert!(
+        !diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::Syntax),
+        "{:?}",
+        diagnostics
+    );
+
+    diagnostics
+}
+
+/// Analyze the document, returning its diagnostics of the given kinds with the text
+/// they point at.
+pub async fn diagnostics(
+    version: AmberVersion,
+    text: &str,
+    kinds: &[DiagnosticKind],
+) -> Vec<(DiagnosticKind, String, String)> {
+    let chars = text.chars().collect::<Vec<_>>();
+
+    file_diagnostics(version, text)
+        .await
+        .into_iter()
+        .filter(|diagnostic| kinds.contains(&diagnostic.kind))
+        .map(|diagnostic| {
+            (
+                diagnostic.kind,
+                diagnostic.message,
+                chars[diagnostic.span.start..diagnostic.span.end]
+                    .iter()
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Whether the version allows indexing an array with a range.
+pub fn has_range_index(version: &AmberVersion) -> bool {
+    operator_rules(version, Operator::Index)
+        .iter()
+        .any(|rule| rule.right == DataType::Array(Box::new(DataType::Number)))
+}
@@ -0,0 +1,155 @@
+use amber_lsp::backend::AmberVersion;
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        CodeActionContext, CodeActionOrCommand, CodeActionParams, Position, Range,
+        TextDocumentIdentifier, TextEdit, Url,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, main_file, open_params};
+
+/// Apply the edits to the text, which has only ASCII characters.
+fn apply_edits(text: &str, mut edits: Vec<TextEdit>) -> String {
+    let offset = |position: Position| {
+        text.split_inclusive('\n')
+            .take(position.line as usize)
+            .map(str::len)
+            .sum::<usize>()
+            + position.character as usize
+    };
+
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+
+    let mut text = text.to_string();
+
+    for edit in edits {
+        text.replace_range(
+            offset(edit.range.start)..offset(edit.range.end),
+            &edit.new_text,
+        );
+    }
+
+    text
+}
+
+/// Titles of the quick fixes offered in the document, with the document fixed by each.
+async fn quick_fixes(amber_version: AmberVersion, text: &str) -> Vec<(String, String)> {
+    let service = backend_service(amber_version);
+
+    let backend = service.inner();
+
+    let uri = Url::from_file_path(main_file()).unwrap();
+
+    backend.did_open(open_params(&uri, text)).await;
+
+    let actions = backend
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::new(Position::new(0, 0), Position::new(u32::MAX, 0)),
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap_or_default();
+
+    actions
+        .into_iter()
+        .map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) => {
+                let edits = action
+                    .edit
+                    .and_then(|edit| edit.changes)
+                    .and_then(|mut changes| changes.remove(&uri))
+                    .unwrap_or_default();
+
+                (action.title, apply_edits(text, edits))
+            }
+            CodeActionOrCommand::Command(command) => (command.title, text.to_string()),
+        })
+        .collect()
+}
+
+#[test]
+async fn test_stdlib_alternative_fixes() {
+    let text = r#"import { split } from "std/text"
+
+main {
+    let dir = "out"
+    trust $mkdir -p "{dir}"$
+    let content = $cat {dir}/file.txt$ failed {
+        echo "Failed to read"
+    }
+    let lines = $cat {dir}$ failed {
+        echo "Failed to read"
+    }
+}
+"#;
+
+    assert_eq!(
+        quick_fixes(AmberVersion::Alpha040, text).await,
+        vec![
+            (
+                "Replace with `dir_create`".to_string(),
+                r#"import { split } from "std/text"
+import { dir_create } from "std/fs"
+
+main {
+    let dir = "out"
+    dir_create(dir)
+    let content = $cat {dir}/file.txt$ failed {
+        echo "Failed to read"
+    }
+    let lines = $cat {dir}$ failed {
+        echo "Failed to read"
+    }
+}
+"#
+                .to_string()
+            ),
+            (
+                "Replace with `file_read`".to_string(),
+                r#"import { split } from "std/text"
+import { file_read } from "std/fs"
+
+main {
+    let dir = "out"
+    trust $mkdir -p "{dir}"$
+    let content = $cat {dir}/file.txt$ failed {
+        echo "Failed to read"
+    }
+    let lines = file_read(dir) failed {
+        echo "Failed to read"
+    }
+}
+"#
+                .to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+async fn test_stdlib_alternative_fixes_per_version() {
+    let text = r#"
+let name = "amber"
+echo unsafe $echo {name} | tr '[:lower:]' '[:upper:]'$
+"#;
+
+    assert_eq!(
+        quick_fixes(AmberVersion::Alpha034, text).await,
+        vec![(
+            "Replace with `upper`".to_string(),
+            r#"import { upper } from "std"
+
+
+let name = "amber"
+echo upper(name)
+"#
+            .to_string()
+        )]
+    );
+}
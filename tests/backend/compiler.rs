@@ -0,0 +1,592 @@
+use std::path::PathBuf;
+
+use amber_lsp::compiler::{parse_output, CompilerMessage, MessageLevel};
+use tokio::test;
+
+#[test]
+async fn test_parse_compiler_output() {
+    let output = "\x1b[1;41m ERROR \x1b[0m Variable 'y' does not exist
+at /project/main.ab:2:6
+
+1| let x = 1
+2| echo y
+        ^
+Did you mean 'x'?
+
+ WARN  Unused variable 'x'
+at /project/main.ab:1:5
+
+ ERROR  Failed to write the output
+";
+
+    assert_eq!(
+        parse_output(output),
+        vec![
+            CompilerMessage {
+                level: MessageLevel::Error,
+                message: "Variable 'y' does not exist".to_string(),
+                location: Some((PathBuf::from("/project/main.ab"), 2, 6)),
+            },
+            CompilerMessage {
+                level: MessageLevel::Warning,
+                message: "Unused variable 'x'".to_string(),
+                location: Some((PathBuf::from("/project/main.ab"), 1, 5)),
+            },
+            CompilerMessage {
+                level: MessageLevel::Error,
+                message: "Failed to write the output".to_string(),
+                location: None,
+            },
+        ]
+    );
+}
+
+#[cfg(unix)]
+mod fake_compiler {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Duration};
+
+    use amber_lsp::{
+        backend::{AmberVersion, Backend},
+        compiler::{
+            CompiledBash, ScriptExit, RUN_COMMAND, RUN_WITH_ARGUMENTS_COMMAND,
+            SHOW_COMPILED_BASH_COMMAND,
+        },
+        settings::{CompilerDiagnosticsSettings, Settings},
+    };
+    use tokio::test;
+    use tower_lsp::{
+        lsp_types::{
+            DidSaveTextDocumentParams, ExecuteCommandParams, NumberOrString, Position,
+            TextDocumentIdentifier, Url,
+        },
+        LanguageServer,
+    };
+
+    use crate::backend::{backend_service, open_params};
+
+    /// Write an executable script standing in for the `amber` compiler.
+    fn fake_compiler(name: &str, script: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("amber-lsp-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("amber");
+        std::fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        path
+    }
+
+    async fn open(backend: &Backend, uri: &Url, text: &str) {
+        backend.did_open(open_params(uri, text)).await;
+    }
+
+    async fn show_compiled_bash(backend: &Backend, uri: &Url) -> Option<CompiledBash> {
+        backend
+            .execute_command(ExecuteCommandParams {
+                command: SHOW_COMPILED_BASH_COMMAND.to_string(),
+                arguments: vec![serde_json::to_value(uri).unwrap()],
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .map(|value| serde_json::from_value(value).unwrap())
+    }
+
+    async fn open_and_save(backend: &Backend, uri: &Url, text: &str) {
+        open(backend, uri, text).await;
+
+        backend
+            .did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                text: None,
+            })
+            .await;
+    }
+
+    #[test]
+    async fn test_compiler_diagnostics_on_save() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        // Reports the undefined function, already found by the language server, a warning
+        // at the same place, a problem only the compiler knows about and a problem of
+        // another file
+        let compiler = fake_compiler(
+            "diagnostics",
+            r#"[ "$1" = "check" ] || exit 2
+echo " ERROR  Function 'foo' does not exist" >&2
+echo "at $2:2:1" >&2
+echo " WARN  Function 'foo' is deprecated" >&2
+echo "at $2:2:1" >&2
+echo " ERROR  Cannot translate this expression to Bash" >&2
+echo "at $2:1:5" >&2
+echo " WARN  Deprecated function" >&2
+echo "at /other/file.ab:1:1" >&2
+exit 1
+"#,
+        );
+
+        let uri = Url::from_file_path("/project/main.ab").unwrap();
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                compiler_diagnostics: CompilerDiagnosticsSettings {
+                    enabled: true,
+                    timeout: 5000,
+                },
+                ..Settings::default()
+            })
+            .await;
+
+        open_and_save(backend, &uri, "let x = 1\nfoo(x)\n").await;
+
+        let file_id = backend.files.get(&uri).unwrap();
+        let version = backend.files.get_latest_version(file_id);
+
+        let diagnostics = backend.document_diagnostics(file_id, version).unwrap();
+
+        let foo_diagnostics = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.range.start == Position::new(1, 0))
+            .map(|diagnostic| diagnostic.code.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            foo_diagnostics,
+            vec![
+                Some(NumberOrString::String("analysis".to_string())),
+                Some(NumberOrString::String("compilerWarning".to_string())),
+            ]
+        );
+
+        let compiler_diagnostics = diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("compilerError".to_string()))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(compiler_diagnostics.len(), 1);
+        assert_eq!(
+            compiler_diagnostics[0].message,
+            "Cannot translate this expression to Bash"
+        );
+        assert_eq!(compiler_diagnostics[0].range.start, Position::new(0, 4));
+        assert_eq!(compiler_diagnostics[0].range.end, Position::new(0, 5));
+
+        let compiler_warnings = diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("compilerWarning".to_string()))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(compiler_warnings.len(), 1);
+        assert_eq!(compiler_warnings[0].message, "Function 'foo' is deprecated");
+
+        // Turning the compiler off drops its diagnostics
+        backend.update_settings(Settings::default()).await;
+
+        let diagnostics = backend.document_diagnostics(file_id, version).unwrap();
+        assert!(!diagnostics.iter().any(|diagnostic| {
+            diagnostic.code == Some(NumberOrString::String("compilerError".to_string()))
+        }));
+    }
+
+    #[test]
+    async fn test_compiler_timeout() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        let compiler = fake_compiler(
+            "timeout",
+            r#"sleep 10
+echo " ERROR  Too late" >&2
+exit 1
+"#,
+        );
+
+        let uri = Url::from_file_path("/project/main.ab").unwrap();
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                compiler_diagnostics: CompilerDiagnosticsSettings {
+                    enabled: true,
+                    timeout: 100,
+                },
+                ..Settings::default()
+            })
+            .await;
+
+        let started = std::time::Instant::now();
+
+        open_and_save(backend, &uri, "let x = 1\n").await;
+
+        assert!(started.elapsed().as_secs() < 5);
+
+        let file_id = backend.files.get(&uri).unwrap();
+        let version = backend.files.get_latest_version(file_id);
+
+        assert!(!backend
+            .files
+            .compiler_diagnostics
+            .contains_key(&(file_id, version)));
+    }
+
+    #[test]
+    async fn test_show_compiled_bash() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        // Comments out every line of the compiled document
+        let compiler = fake_compiler(
+            "build",
+            r#"[ "$1" = "build" ] || exit 2
+sed 's/^/# /' "$2" > "$3"
+"#,
+        );
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        // The document only exists in the editor
+        let uri = Url::from_file_path("/project/main.ab").unwrap();
+        open(backend, &uri, "let x = 1\necho x\n").await;
+
+        let compiled_bash = show_compiled_bash(backend, &uri).await.unwrap();
+
+        assert_eq!(compiled_bash.uri.as_str(), "amber-bash:/project/main.sh");
+        assert_eq!(compiled_bash.text, "# let x = 1\n# echo x\n");
+    }
+
+    #[test]
+    async fn test_show_compiled_bash_leaves_no_files() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        let compiler = fake_compiler(
+            "build-copy",
+            r#"[ "$1" = "build" ] || exit 2
+cp "$2" "$3"
+"#,
+        );
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let project =
+            std::env::temp_dir().join(format!("amber-lsp-project-{}", std::process::id()));
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("main.ab"), "echo 1\n").unwrap();
+
+        let uri = Url::from_file_path(project.join("main.ab")).unwrap();
+        open(backend, &uri, "echo 2\n").await;
+
+        let compiled_bash = show_compiled_bash(backend, &uri).await.unwrap();
+        assert_eq!(compiled_bash.text, "echo 2\n");
+
+        let files = std::fs::read_dir(&project)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["main.ab"]);
+    }
+
+    #[test]
+    async fn test_show_compiled_bash_with_relative_imports() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        // Fails unless the imported file is found next to the compiled one
+        let compiler = fake_compiler(
+            "build-import",
+            r#"[ "$1" = "build" ] || exit 2
+cat "$(dirname "$2")/lib.ab" "$2" > "$3"
+"#,
+        );
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let project =
+            std::env::temp_dir().join(format!("amber-lsp-import-project-{}", std::process::id()));
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("lib.ab"), "pub fun foo() {}\n").unwrap();
+
+        let uri = Url::from_file_path(project.join("main.ab")).unwrap();
+        open(backend, &uri, "import { foo } from \"./lib.ab\"\nfoo()\n").await;
+
+        let compiled_bash = show_compiled_bash(backend, &uri).await.unwrap();
+        assert_eq!(
+            compiled_bash.text,
+            "pub fun foo() {}\nimport { foo } from \"./lib.ab\"\nfoo()\n"
+        );
+    }
+
+    #[test]
+    async fn test_show_compiled_bash_errors() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        let compiler = fake_compiler(
+            "build-errors",
+            r#"echo " ERROR  Cannot translate this expression to Bash" >&2
+echo "at $2:1:5" >&2
+exit 1
+"#,
+        );
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let uri = Url::from_file_path("/project/errors.ab").unwrap();
+        open(backend, &uri, "let x = 1\n").await;
+
+        assert_eq!(show_compiled_bash(backend, &uri).await, None);
+
+        let file_id = backend.files.get(&uri).unwrap();
+        let version = backend.files.get_latest_version(file_id);
+
+        let diagnostics = backend.document_diagnostics(file_id, version).unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("compilerError".to_string()))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Cannot translate this expression to Bash"
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 4));
+    }
+
+    async fn run(
+        backend: &Backend,
+        command: &str,
+        arguments: Vec<serde_json::Value>,
+    ) -> tower_lsp::jsonrpc::Result<ScriptExit> {
+        backend
+            .execute_command(ExecuteCommandParams {
+                command: command.to_string(),
+                arguments,
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .map(|value| serde_json::from_value(value.unwrap()).unwrap())
+    }
+
+    #[test]
+    async fn test_run_script() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        // Records the arguments it was run with in its working directory
+        let compiler = fake_compiler(
+            "run",
+            r#"echo "$@" > arguments
+echo "Hello"
+echo "Oops" >&2
+exit 3
+"#,
+        );
+        let dir = compiler.parent().unwrap().to_path_buf();
+        let file_path = dir.join("main.ab");
+        std::fs::write(&file_path, "main(args) {\n    echo args\n}\n").unwrap();
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let uri = Url::from_file_path(&file_path).unwrap();
+
+        let exit = run(
+            backend,
+            RUN_COMMAND,
+            vec![serde_json::to_value(&uri).unwrap()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            exit,
+            ScriptExit {
+                exit_code: Some(3),
+                success: false
+            }
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("arguments")).unwrap(),
+            format!("run {}\n", file_path.display())
+        );
+
+        run(
+            backend,
+            RUN_WITH_ARGUMENTS_COMMAND,
+            vec![
+                serde_json::to_value(&uri).unwrap(),
+                serde_json::json!(["first", "second argument"]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("arguments")).unwrap(),
+            format!("run {} first second argument\n", file_path.display())
+        );
+
+        assert!(run(
+            backend,
+            RUN_WITH_ARGUMENTS_COMMAND,
+            vec![serde_json::to_value(&uri).unwrap()]
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    async fn test_run_script_with_arguments_in_older_versions() {
+        let service = backend_service(AmberVersion::Alpha035);
+
+        let backend = service.inner();
+
+        let compiler = fake_compiler("run-alpha035", "exit 0\n");
+        let file_path = compiler.parent().unwrap().join("main.ab");
+        std::fs::write(&file_path, "echo 1\n").unwrap();
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let uri = Url::from_file_path(&file_path).unwrap();
+
+        let exit = run(
+            backend,
+            RUN_COMMAND,
+            vec![serde_json::to_value(&uri).unwrap()],
+        )
+        .await
+        .unwrap();
+        assert!(exit.success);
+
+        assert!(run(
+            backend,
+            RUN_WITH_ARGUMENTS_COMMAND,
+            vec![
+                serde_json::to_value(&uri).unwrap(),
+                serde_json::json!(["arg"])
+            ],
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    async fn test_run_script_with_binary_output() {
+        let service = backend_service(AmberVersion::Alpha040);
+
+        let backend = service.inner();
+
+        // Writes more than fits in the pipe after a line that is not valid UTF-8
+        let compiler = fake_compiler(
+            "run-binary",
+            r#"printf '\377\376\n'
+yes amber | head -n 100000
+exit 0
+"#,
+        );
+        let file_path = compiler.parent().unwrap().join("main.ab");
+        std::fs::write(&file_path, "echo 1\n").unwrap();
+
+        backend
+            .update_settings(Settings {
+                amber_path: Some(compiler),
+                ..Settings::default()
+            })
+            .await;
+
+        let uri = Url::from_file_path(&file_path).unwrap();
+
+        let exit = tokio::time::timeout(
+            Duration::from_secs(30),
+            run(
+                backend,
+                RUN_COMMAND,
+                vec![serde_json::to_value(&uri).unwrap()],
+            ),
+        )
+        .await
+        .expect("the script output is not drained")
+        .unwrap();
+
+        assert!(exit.success);
+    }
+
+    #[test]
+    async fn test_compiler_version_follows_binary_updates() {
+        let service = backend_service(AmberVersion::Alpha040);
+        let backend = service.inner();
+
+        let compiler = fake_compiler("version", "echo amber 0.3.4-alpha\n");
+        assert_eq!(
+            backend.compiler_version(&compiler).await,
+            Some(AmberVersion::Alpha034)
+        );
+
+        // The binary is only run again once the backend's file system reports
+        // a modification, updating it on disk alone goes unnoticed here
+        fake_compiler("version", "echo amber 0.4.0-alpha\n");
+        assert_eq!(
+            backend.compiler_version(&compiler).await,
+            Some(AmberVersion::Alpha034)
+        );
+
+        backend.files.fs.write(&compiler, "").await.unwrap();
+
+        assert_eq!(
+            backend.compiler_version(&compiler).await,
+            Some(AmberVersion::Alpha040)
+        );
+    }
+
+    #[test]
+    async fn test_compiler_version_timeout() {
+        let service = backend_service(AmberVersion::Alpha040);
+        let backend = service.inner();
+
+        let compiler = fake_compiler("version-hangs", "sleep 60\n");
+
+        let started = std::time::Instant::now();
+        assert_eq!(backend.compiler_version(&compiler).await, None);
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,111 @@
+use amber_lsp::backend::{AmberVersion, Backend};
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        CompletionItem, CompletionParams, CompletionResponse, CompletionTextEdit, Position, Range,
+        TextDocumentIdentifier, TextDocumentPositionParams, Url,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, main_file, open_params};
+
+async fn completions(backend: &Backend, uri: &Url, position: Position) -> Vec<CompletionItem> {
+    let response = backend
+        .completion(CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        })
+        .await
+        .unwrap();
+
+    match response {
+        Some(CompletionResponse::Array(items)) => items,
+        Some(response) => panic!("Unexpected response {:?}", response),
+        None => vec![],
+    }
+}
+
+fn labels(items: &[CompletionItem]) -> Vec<&str> {
+    items.iter().map(|item| item.label.as_str()).collect()
+}
+
+fn edit_range(item: &CompletionItem) -> Range {
+    match &item.text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => edit.range,
+        edit => panic!("Unexpected text edit {:?}", edit),
+    }
+}
+
+#[test]
+async fn test_command_completions() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let dir = main_file().parent().unwrap();
+
+    let binary = format!("frobnicate{}", std::env::consts::EXE_SUFFIX);
+    backend.set_search_path(Some(dir.join("bin").into()));
+    backend
+        .files
+        .fs
+        .write(&dir.join("bin").join(binary), "")
+        .await
+        .unwrap();
+
+    for file in ["src/lib.ab", "src/util/text.ab", "setup.sh"] {
+        backend.files.fs.write(&dir.join(file), "").await.unwrap();
+    }
+
+    let uri = Url::from_file_path(main_file()).unwrap();
+    let text = r#"let dir = "build"
+trust $frob$
+trust $rm -r$
+trust $cat s$
+trust $cat src/$
+trust $echo \$PAT$
+trust $ls {dir}$
+"#;
+
+    backend.did_open(open_params(&uri, text)).await;
+
+    let items = completions(backend, &uri, Position::new(1, 11)).await;
+    assert_eq!(labels(&items), vec!["frobnicate"]);
+    assert_eq!(
+        edit_range(&items[0]),
+        Range::new(Position::new(1, 7), Position::new(1, 11))
+    );
+
+    let items = completions(backend, &uri, Position::new(2, 12)).await;
+    assert_eq!(labels(&items), vec!["-r"]);
+    assert_eq!(
+        items[0].detail.as_deref(),
+        Some("Remove directories recursively")
+    );
+
+    let items = completions(backend, &uri, Position::new(3, 12)).await;
+    assert_eq!(labels(&items), vec!["setup.sh", "src/"]);
+
+    let items = completions(backend, &uri, Position::new(4, 15)).await;
+    assert_eq!(labels(&items), vec!["lib.ab", "util/"]);
+    assert_eq!(
+        edit_range(&items[0]),
+        Range::new(Position::new(4, 15), Position::new(4, 15))
+    );
+
+    let items = completions(backend, &uri, Position::new(5, 17)).await;
+    assert!(labels(&items).contains(&"PATH"));
+    assert_eq!(
+        edit_range(&items[0]),
+        Range::new(Position::new(5, 14), Position::new(5, 17))
+    );
+
+    let items = completions(backend, &uri, Position::new(6, 12)).await;
+    assert!(labels(&items).contains(&"dir"));
+}
@@ -0,0 +1,201 @@
+use amber_lsp::backend::AmberVersion;
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidSaveTextDocumentParams,
+        TextDocumentContentChangeEvent, TextDocumentIdentifier, Url,
+        VersionedTextDocumentIdentifier,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, open_params, paths};
+
+fn close_params(uri: &Url) -> DidCloseTextDocumentParams {
+    DidCloseTextDocumentParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+    }
+}
+
+#[test]
+async fn test_close_releases_imported_files() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (src_file, main_file) = paths();
+    let main_text = r#"
+    import { foo } from "src.ab"
+
+    foo()
+    "#;
+
+    vfs.write(src_file, r#"pub fun foo() {}"#).await.unwrap();
+    vfs.write(main_file, main_text).await.unwrap();
+
+    let src_uri = Url::from_file_path(src_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, main_text)).await;
+
+    assert!(backend.files.get(&main_uri).is_some());
+    assert!(backend.files.get(&src_uri).is_some());
+
+    backend.did_close(close_params(&main_uri)).await;
+
+    assert!(backend.files.get(&main_uri).is_none());
+    assert!(backend.files.get(&src_uri).is_none());
+    assert!(backend.files.document_map.is_empty());
+    assert!(backend.files.symbol_table.is_empty());
+    assert!(backend.files.ast_map.is_empty());
+}
+
+#[test]
+async fn test_close_reloads_file_used_by_open_document() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (src_file, main_file) = paths();
+    let main_text = r#"
+    import { foo } from "src.ab"
+
+    foo()
+    "#;
+
+    vfs.write(src_file, r#"pub fun foo() {}"#).await.unwrap();
+    vfs.write(main_file, main_text).await.unwrap();
+
+    let src_uri = Url::from_file_path(src_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, main_text)).await;
+    // Unsaved edit: `foo` is no longer defined in the editor buffer
+    backend
+        .did_open(open_params(&src_uri, r#"pub fun bar() {}"#))
+        .await;
+
+    backend.did_close(close_params(&src_uri)).await;
+
+    let src_file_id = backend.files.get(&src_uri).unwrap();
+    let main_file_id = backend.files.get(&main_uri).unwrap();
+
+    assert!(!backend.files.is_open(src_file_id));
+    assert!(backend.files.is_open(main_file_id));
+
+    let (_, src_version) = backend
+        .files
+        .get_document_latest_version(src_file_id)
+        .unwrap();
+    let src_symbol_table = backend
+        .files
+        .symbol_table
+        .get(&(src_file_id, src_version))
+        .unwrap();
+
+    assert!(src_symbol_table.definitions.contains_key("foo"));
+    assert!(!src_symbol_table.definitions.contains_key("bar"));
+
+    let main_version = backend.files.get_latest_version(main_file_id);
+    let main_diagnostics = backend
+        .files
+        .diagnostics
+        .get(&(main_file_id, main_version))
+        .map(|diagnostics| diagnostics.clone())
+        .unwrap_or_default();
+
+    assert!(main_diagnostics.is_empty());
+}
+
+#[test]
+async fn test_save_reanalyzes_transitive_dependents_once() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (lib_file, main_file) = paths();
+    let dir = main_file.parent().unwrap();
+    let (left_file, right_file) = (dir.join("left.ab"), dir.join("right.ab"));
+
+    // `main` reaches `src` through both `left` and `right`
+    let main_text =
+        "import * from \"left.ab\"\nimport { right } from \"right.ab\"\n\nbar()\nright()\n";
+
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+    vfs.write(&left_file, "pub import * from \"src.ab\"")
+        .await
+        .unwrap();
+    vfs.write(
+        &right_file,
+        "import * from \"src.ab\"\n\npub fun right() {\n    foo()\n}",
+    )
+    .await
+    .unwrap();
+    vfs.write(main_file, main_text).await.unwrap();
+
+    let lib_uri = Url::from_file_path(lib_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, main_text)).await;
+    backend
+        .did_open(open_params(&lib_uri, "pub fun foo() {}"))
+        .await;
+
+    let lib_file_id = backend.files.get(&lib_uri).unwrap();
+    let main_file_id = backend.files.get(&main_uri).unwrap();
+
+    let dependants = backend
+        .files
+        .get_files_dependant_on_transitively(lib_file_id)
+        .into_iter()
+        .map(|(file_id, _)| backend.files.lookup(&file_id))
+        .collect::<Vec<_>>();
+
+    assert_eq!(dependants.len(), 3);
+    assert_eq!(dependants.last(), Some(&main_uri));
+
+    let main_has_errors = || {
+        let version = backend.files.get_latest_version(main_file_id);
+
+        !backend
+            .files
+            .diagnostics
+            .get(&(main_file_id, version))
+            .unwrap()
+            .is_empty()
+    };
+
+    assert!(main_has_errors());
+
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: lib_uri.clone(),
+                version: 2,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "pub fun foo() {}\npub fun bar() {}".to_string(),
+            }],
+        })
+        .await;
+
+    // Dependents are analyzed again on save, not on every change
+    assert!(main_has_errors());
+
+    backend
+        .did_save(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: lib_uri },
+            text: None,
+        })
+        .await;
+
+    assert!(!main_has_errors());
+}
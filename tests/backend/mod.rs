@@ -0,0 +1,54 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{backend::Backend, fs::MemoryFS, settings::VersionSetting};
+use tower_lsp::{
+    lsp_types::{DidOpenTextDocumentParams, TextDocumentItem, Url},
+    LspService,
+};
+
+pub mod code_actions;
+pub mod compiler;
+pub mod completions;
+pub mod lifecycle;
+pub mod selection_ranges;
+pub mod settings;
+pub mod stdlib;
+pub mod version_detection;
+pub mod watched_files;
+pub mod workspace_folders;
+
+/// Language server working with an empty in-memory file system.
+pub fn backend_service(amber_version: impl Into<VersionSetting>) -> LspService<Backend> {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(client, amber_version, Some(Arc::new(MemoryFS::new())))
+    });
+
+    service
+}
+
+/// Paths of a file imported by the main file, and of the main file.
+pub fn paths() -> (&'static Path, &'static Path) {
+    #[cfg(windows)]
+    {
+        (Path::new("C:\\src.ab"), Path::new("C:\\main.ab"))
+    }
+    #[cfg(unix)]
+    {
+        (Path::new("/src.ab"), Path::new("/main.ab"))
+    }
+}
+
+pub fn main_file() -> &'static Path {
+    paths().1
+}
+
+pub fn open_params(uri: &Url, text: &str) -> DidOpenTextDocumentParams {
+    DidOpenTextDocumentParams {
+        text_document: TextDocumentItem {
+            uri: uri.clone(),
+            language_id: "amber".to_string(),
+            version: 1,
+            text: text.to_string(),
+        },
+    }
+}
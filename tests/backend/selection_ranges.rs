@@ -0,0 +1,96 @@
+use amber_lsp::backend::AmberVersion;
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        Position, Range, SelectionRange, SelectionRangeParams, TextDocumentIdentifier, Url,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, main_file, open_params};
+
+/// Texts of the selection range and its parents, from the innermost to the outermost one.
+fn range_texts(text: &str, selection_range: &SelectionRange) -> Vec<String> {
+    let offset = |position: Position| {
+        text.split_inclusive('\n')
+            .take(position.line as usize)
+            .map(str::len)
+            .sum::<usize>()
+            + position.character as usize
+    };
+    let range_text = |range: Range| text[offset(range.start)..offset(range.end)].to_string();
+
+    let mut texts = vec![range_text(selection_range.range)];
+    let mut parent = &selection_range.parent;
+
+    while let Some(selection_range) = parent {
+        texts.push(range_text(selection_range.range));
+        parent = &selection_range.parent;
+    }
+
+    texts
+}
+
+#[test]
+async fn test_command_selection_ranges() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let uri = Url::from_file_path(main_file()).unwrap();
+    let text = r#"let dir = "build"
+trust $cd {dir} && ls -la | sort$
+let files = $ls {$echo "src"$}$ failed {
+    echo "Failed"
+}
+"#;
+
+    backend.did_open(open_params(&uri, text)).await;
+
+    let selection_ranges = backend
+        .selection_range(SelectionRangeParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            positions: vec![
+                Position::new(1, 22),
+                Position::new(2, 26),
+                Position::new(0, 5),
+            ],
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        range_texts(text, &selection_ranges[0]),
+        vec![
+            "-la",
+            "ls -la",
+            "ls -la | sort",
+            "cd {dir} && ls -la | sort",
+            "$cd {dir} && ls -la | sort$",
+            "trust $cd {dir} && ls -la | sort$",
+        ]
+    );
+
+    assert_eq!(
+        range_texts(text, &selection_ranges[1]),
+        vec![
+            "src",
+            "\"src\"",
+            "echo \"src\"",
+            "$echo \"src\"$",
+            "{$echo \"src\"$}",
+            "ls {$echo \"src\"$}",
+            "$ls {$echo \"src\"$}$",
+            "$ls {$echo \"src\"$}$ failed {\n    echo \"Failed\"\n}",
+        ]
+    );
+
+    assert_eq!(
+        selection_ranges[2].range,
+        Range::new(Position::new(0, 5), Position::new(0, 5))
+    );
+    assert_eq!(selection_ranges[2].parent, None);
+}
@@ -0,0 +1,492 @@
+use std::path::Path;
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    diagnostics::DiagnosticKind,
+    grammar::Grammar,
+    settings::{Settings, VersionSetting, WorkspaceSettings, SETTINGS_SECTION},
+};
+use serde_json::json;
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        DiagnosticSeverity, DidChangeConfigurationParams, InitializeParams, InlayHintLabel,
+        InlayHintParams, Position, Range, TextDocumentIdentifier, Url, WorkspaceFolder,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, main_file, open_params};
+
+#[test]
+async fn test_settings_from_value() {
+    let settings = Settings::from_value(json!({
+        "version": "0.4.0-alpha",
+        "maxNumberOfProblems": 10,
+        "diagnostics": {
+            "analysis": "warning",
+            "syntax": "off"
+        },
+        "inlayHints": {
+            "variableTypes": false
+        },
+        "importPaths": ["/lib"],
+        "trace": {
+            "server": "off"
+        }
+    }));
+
+    assert_eq!(settings.version, VersionSetting::Alpha040);
+    assert_eq!(settings.max_number_of_problems, 10);
+    assert_eq!(
+        settings.severity(DiagnosticKind::Analysis),
+        Some(DiagnosticSeverity::WARNING)
+    );
+    assert_eq!(settings.severity(DiagnosticKind::Syntax), None);
+    assert!(settings.inlay_hints.enabled);
+    assert!(!settings.inlay_hints.variable_types);
+    assert_eq!(settings.import_paths, vec![Path::new("/lib").to_path_buf()]);
+    assert_eq!(settings.stdlib_path, None);
+
+    assert_eq!(Settings::from_value(json!(null)), Settings::default());
+    assert_eq!(
+        Settings::from_value(json!({ "maxNumberOfProblems": "many" })),
+        Settings::default()
+    );
+}
+
+#[test]
+async fn test_malformed_settings() {
+    let (settings, problems) = Settings::parse(json!({
+        "maxNumberOfProblems": "many",
+        "diagnostics": {
+            "analysis": "warning",
+            "unknownKind": "off",
+            "syntax": "loud"
+        },
+        "knownCommands": ["mytool"]
+    }));
+
+    assert_eq!(settings.max_number_of_problems, 100);
+    assert_eq!(
+        settings.severity(DiagnosticKind::Analysis),
+        Some(DiagnosticSeverity::WARNING)
+    );
+    assert_eq!(
+        settings.severity(DiagnosticKind::Syntax),
+        Some(DiagnosticSeverity::ERROR)
+    );
+    assert_eq!(settings.known_commands, vec!["mytool".to_string()]);
+
+    let mut problems = problems
+        .iter()
+        .map(|problem| problem.split(':').next().unwrap())
+        .collect::<Vec<_>>();
+    problems.sort();
+
+    assert_eq!(
+        problems,
+        vec![
+            "Invalid setting `diagnostics.syntax`",
+            "Invalid setting `diagnostics.unknownKind`",
+            "Invalid setting `maxNumberOfProblems`",
+        ]
+    );
+}
+
+#[test]
+async fn test_scope_settings_cache() {
+    let folder = Url::parse("file:///project").unwrap();
+    let document = Url::parse("file:///project/main.ab").unwrap();
+    let other_document = Url::parse("file:///other/main.ab").unwrap();
+
+    let mut workspace_settings = WorkspaceSettings::default();
+    workspace_settings.set_scope(folder.clone(), None);
+
+    assert!(!workspace_settings.has_scope_settings(&document));
+
+    workspace_settings.set_scope(folder, Some(Settings::default()));
+
+    assert!(workspace_settings.has_scope_settings(&document));
+    assert!(!workspace_settings.has_scope_settings(&other_document));
+}
+
+#[test]
+async fn test_version_change_reanalyzes_documents() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let file = main_file();
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend.did_open(open_params(&uri, "let x = 1")).await;
+
+    let file_id = backend.files.get(&uri).unwrap();
+    let version = backend.files.get_latest_version(file_id);
+
+    assert!(matches!(
+        *backend.files.ast_map.get(&(file_id, version)).unwrap(),
+        Grammar::Alpha034(_)
+    ));
+
+    backend
+        .update_settings(Settings {
+            version: VersionSetting::Alpha040,
+            ..Settings::default()
+        })
+        .await;
+
+    assert_eq!(backend.amber_version_for(&uri), AmberVersion::Alpha040);
+    assert!(matches!(
+        *backend.files.ast_map.get(&(file_id, version)).unwrap(),
+        Grammar::Alpha040(_)
+    ));
+
+    backend.update_settings(Settings::default()).await;
+
+    assert_eq!(backend.amber_version_for(&uri), AmberVersion::Alpha034);
+}
+
+#[test]
+async fn test_import_paths() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (lib_dir, lib_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\lib"),
+                Path::new("C:\\lib\\src.ab"),
+                Path::new("C:\\project\\main.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/lib"),
+                Path::new("/lib/src.ab"),
+                Path::new("/project/main.ab"),
+            )
+        }
+    };
+    let main_text = r#"
+    import { foo } from "src.ab"
+
+    foo()
+    "#;
+
+    vfs.write(lib_file, r#"pub fun foo() {}"#).await.unwrap();
+    vfs.write(main_file, main_text).await.unwrap();
+
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, main_text)).await;
+
+    let main_file_id = backend.files.get(&main_uri).unwrap();
+    let main_version = backend.files.get_latest_version(main_file_id);
+
+    assert!(!backend
+        .files
+        .diagnostics
+        .get(&(main_file_id, main_version))
+        .unwrap()
+        .is_empty());
+
+    backend
+        .update_settings(Settings {
+            import_paths: vec![lib_dir.to_path_buf()],
+            ..Settings::default()
+        })
+        .await;
+
+    assert!(backend
+        .files
+        .diagnostics
+        .get(&(main_file_id, main_version))
+        .unwrap()
+        .is_empty());
+    assert!(backend
+        .files
+        .get(&Url::from_file_path(lib_file).unwrap())
+        .is_some());
+}
+
+#[test]
+async fn test_relative_import_paths() {
+    let (project_dir, lib_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\project"),
+                Path::new("C:\\project\\lib\\src.ab"),
+                Path::new("C:\\project\\main.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/project"),
+                Path::new("/project/lib/src.ab"),
+                Path::new("/project/main.ab"),
+            )
+        }
+    };
+    let main_text = "import { foo } from \"src.ab\"\n\nfoo()\n";
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    let has_errors = |backend: &Backend| {
+        let file_id = backend.files.get(&main_uri).unwrap();
+        let version = backend.files.get_latest_version(file_id);
+
+        !backend
+            .files
+            .diagnostics
+            .get(&(file_id, version))
+            .unwrap()
+            .is_empty()
+    };
+
+    for workspace_folders in [vec![], vec![project_dir]] {
+        let service = backend_service(AmberVersion::Alpha034);
+
+        let backend = service.inner();
+
+        backend
+            .initialize(InitializeParams {
+                workspace_folders: Some(
+                    workspace_folders
+                        .iter()
+                        .map(|folder| WorkspaceFolder {
+                            uri: Url::from_file_path(folder).unwrap(),
+                            name: "project".to_string(),
+                        })
+                        .collect(),
+                ),
+                ..InitializeParams::default()
+            })
+            .await
+            .unwrap();
+
+        let vfs = &backend.files.fs;
+        vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+        vfs.write(main_file, main_text).await.unwrap();
+
+        backend.did_open(open_params(&main_uri, main_text)).await;
+
+        backend
+            .did_change_configuration(DidChangeConfigurationParams {
+                settings: json!({ SETTINGS_SECTION: { "importPaths": ["lib"] } }),
+            })
+            .await;
+
+        if workspace_folders.is_empty() {
+            // Nothing to resolve the path against, it's skipped
+            assert!(has_errors(backend));
+        } else {
+            assert_eq!(
+                backend.settings_for(&main_uri).import_paths,
+                vec![project_dir.join("lib")]
+            );
+            assert!(!has_errors(backend));
+        }
+    }
+}
+
+#[test]
+async fn test_variable_type_inlay_hints() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let file = main_file();
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(&uri, "let x = 1\nlet y = \"text\""))
+        .await;
+
+    let params = InlayHintParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        range: Range::new(Position::new(0, 0), Position::new(2, 0)),
+        work_done_progress_params: Default::default(),
+    };
+
+    let hints = backend.inlay_hint(params.clone()).await.unwrap().unwrap();
+    let labels = hints
+        .iter()
+        .map(|hint| match &hint.label {
+            InlayHintLabel::String(label) => (hint.position, label.clone()),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        labels,
+        vec![
+            (Position::new(0, 5), ": Num".to_string()),
+            (Position::new(1, 5), ": Text".to_string()),
+        ]
+    );
+
+    backend
+        .update_settings(Settings::from_value(serde_json::json!({
+            "inlayHints": { "enabled": false }
+        })))
+        .await;
+
+    assert!(backend.inlay_hint(params).await.unwrap().is_none());
+}
+
+#[test]
+async fn test_narrowed_type_inlay_hints() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let file = main_file();
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(
+            &uri,
+            "let value = true then 1 else \"one\"\nif value is Text {\n    echo value\n}\necho value",
+        ))
+        .await;
+
+    let params = InlayHintParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        range: Range::new(Position::new(0, 0), Position::new(5, 0)),
+        work_done_progress_params: Default::default(),
+    };
+
+    let hints = backend.inlay_hint(params).await.unwrap().unwrap();
+    let labels = hints
+        .iter()
+        .map(|hint| match &hint.label {
+            InlayHintLabel::String(label) => (hint.position, label.clone()),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        labels,
+        vec![
+            (Position::new(0, 9), ": Num | Text".to_string()),
+            (Position::new(2, 14), ": Text".to_string()),
+        ]
+    );
+}
+
+#[test]
+async fn test_known_commands() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let file = main_file();
+
+    let bin = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\bin")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/bin")
+        }
+    };
+    let binary = |name: &str| bin.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+
+    backend.set_search_path(Some(bin.into()));
+    backend
+        .files
+        .fs
+        .write(&binary("frobnicate"), "")
+        .await
+        .unwrap();
+
+    let text = r#"
+main {
+    let cmd = "ls"
+
+    trust $frobnicate --all$
+    trust $gerp foo$
+    trust $echo "bar"$
+    trust $sudo LANG=C mytool$
+    trust ${cmd} -l$
+    trust $./build.sh$
+}
+"#;
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend.did_open(open_params(&uri, text)).await;
+
+    let missing_commands = || {
+        let file_id = backend.files.get(&uri).unwrap();
+        let version = backend.files.get_latest_version(file_id);
+        let chars = text.chars().collect::<Vec<_>>();
+
+        backend
+            .files
+            .diagnostics
+            .get(&(file_id, version))
+            .unwrap()
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::MissingCommand)
+            .map(|diagnostic| {
+                (
+                    diagnostic.message.clone(),
+                    chars[diagnostic.span.start..diagnostic.span.end]
+                        .iter()
+                        .collect::<String>(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        missing_commands(),
+        vec![
+            (
+                "Command `gerp` is not found on `PATH`".to_string(),
+                "gerp".to_string()
+            ),
+            (
+                "Command `mytool` is not found on `PATH`".to_string(),
+                "mytool".to_string()
+            ),
+        ]
+    );
+
+    backend
+        .update_settings(Settings::from_value(json!({
+            "knownCommands": ["mytool"]
+        })))
+        .await;
+
+    assert_eq!(
+        missing_commands(),
+        vec![(
+            "Command `gerp` is not found on `PATH`".to_string(),
+            "gerp".to_string()
+        )]
+    );
+    // Lookups are done again once the settings change
+    backend.files.fs.write(&binary("gerp"), "").await.unwrap();
+    backend
+        .update_settings(Settings::from_value(json!({
+            "knownCommands": ["mytool", "mytool2"]
+        })))
+        .await;
+
+    assert_eq!(missing_commands(), vec![]);
+}
@@ -0,0 +1,361 @@
+use std::{env::temp_dir, path::Path};
+
+use amber_lsp::{
+    analysis::get_symbol_definition_info,
+    backend::{AmberVersion, Backend},
+    settings::Settings,
+    stdlib::{find_in_stdlib, stdlib_version, STDLIB_SCHEME},
+    stdlib_index::{self, STDLIB_INDEX_PATH},
+};
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        DidOpenTextDocumentParams, Hover, HoverContents, HoverParams, InitializeParams, Position,
+        SemanticTokensServerCapabilities, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams, Url,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, open_params};
+
+fn hover_params(uri: &Url, line: u32, character: u32) -> HoverParams {
+    HoverParams {
+        text_document_position_params: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position { line, character },
+        },
+        work_done_progress_params: Default::default(),
+    }
+}
+
+fn hover_text(hover: Option<Hover>) -> String {
+    match hover {
+        Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) => markup.value,
+        _ => panic!("Expected a markdown hover"),
+    }
+}
+
+/// File the symbol got imported from.
+fn definition_file(backend: &Backend, uri: &Url, symbol: &str) -> Option<Url> {
+    let file_id = backend.files.get(uri).unwrap();
+    let version = backend.files.get_latest_version(file_id);
+    let symbol_table = backend.files.symbol_table.get(&(file_id, version)).unwrap();
+
+    let definition = symbol_table
+        .definitions
+        .get(symbol)?
+        .get(&usize::MAX)?
+        .clone();
+
+    Some(backend.files.lookup(&definition.file.0))
+}
+
+#[test]
+async fn test_installed_stdlib() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let (compiler, installed_stdlib, file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\amber\\bin\\amber.exe"),
+                Path::new("C:\\amber\\lib\\amber"),
+                Path::new("C:\\project\\main.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/opt/amber/bin/amber"),
+                Path::new("/opt/amber/lib/amber"),
+                Path::new("/project/main.ab"),
+            )
+        }
+    };
+
+    backend
+        .files
+        .fs
+        .write(
+            &installed_stdlib.join("std").join("text.ab"),
+            "pub fun shout(text: Text): Text {\n    return text\n}\n",
+        )
+        .await
+        .unwrap();
+
+    backend
+        .update_settings(Settings {
+            amber_path: Some(compiler.to_path_buf()),
+            use_installed_stdlib: true,
+            ..Settings::default()
+        })
+        .await;
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(
+            &uri,
+            "import { shout } from \"std/text\"\nimport { math_sum } from \"std/math\"\n\nshout(\"hi\")\nmath_sum([1, 2])\n",
+        ))
+        .await;
+
+    let shout_file = definition_file(backend, &uri, "shout").unwrap();
+    assert_eq!(
+        shout_file.to_file_path().unwrap(),
+        installed_stdlib.join("std").join("text.ab")
+    );
+
+    // Files missing in the installed stdlib come from the embedded one
+    let math_sum_file = definition_file(backend, &uri, "math_sum").unwrap();
+    assert_eq!(stdlib_version(&math_sum_file), Some(AmberVersion::Alpha040));
+
+    let shout_hover = hover_text(backend.hover(hover_params(&uri, 3, 1)).await.unwrap());
+    assert!(shout_hover.contains(&format!(
+        "From the external stdlib at `{}`",
+        installed_stdlib.display()
+    )));
+
+    let import_hover = hover_text(backend.hover(hover_params(&uri, 0, 25)).await.unwrap());
+    assert!(import_hover.contains("From the external stdlib"));
+
+    let math_sum_hover = hover_text(backend.hover(hover_params(&uri, 4, 1)).await.unwrap());
+    assert!(!math_sum_hover.contains("From the external stdlib"));
+}
+
+#[test]
+async fn test_configured_stdlib_takes_precedence() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let (compiler, installed_stdlib, configured_stdlib, file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\amber\\bin\\amber.exe"),
+                Path::new("C:\\amber\\bin"),
+                Path::new("C:\\stdlib"),
+                Path::new("C:\\project\\main.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/opt/amber/bin/amber"),
+                Path::new("/opt/amber/bin"),
+                Path::new("/stdlib"),
+                Path::new("/project/main.ab"),
+            )
+        }
+    };
+
+    let vfs = &backend.files.fs;
+
+    for dir in [installed_stdlib, configured_stdlib] {
+        vfs.write(
+            &dir.join("std").join("text.ab"),
+            "pub fun shout(text: Text): Text {\n    return text\n}\n",
+        )
+        .await
+        .unwrap();
+    }
+
+    backend
+        .update_settings(Settings {
+            amber_path: Some(compiler.to_path_buf()),
+            stdlib_path: Some(configured_stdlib.to_path_buf()),
+            use_installed_stdlib: true,
+            ..Settings::default()
+        })
+        .await;
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(
+            &uri,
+            "import { shout } from \"std/text\"\n\nshout(\"hi\")\n",
+        ))
+        .await;
+
+    let shout_file = definition_file(backend, &uri, "shout").unwrap();
+    assert_eq!(
+        shout_file.to_file_path().unwrap(),
+        configured_stdlib.join("std").join("text.ab")
+    );
+}
+
+#[test]
+async fn test_embedded_stdlib_documents() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\project\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/project/main.ab")
+        }
+    };
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(
+            &uri,
+            "import { math_sum } from \"std/math\"\n\nmath_sum([1, 2])\n",
+        ))
+        .await;
+
+    let math_sum_file = definition_file(backend, &uri, "math_sum").unwrap();
+    assert_eq!(math_sum_file.scheme(), STDLIB_SCHEME);
+    assert_eq!(math_sum_file.path(), "/alpha040/std/math.ab");
+
+    // Stdlib modules are loaded from the index, without parsing
+    let math_file_id = backend.files.get(&math_sum_file).unwrap();
+    assert!(!backend
+        .files
+        .ast_map
+        .contains_key(&(math_file_id, backend.files.get_latest_version(math_file_id))));
+
+    let contents = backend
+        .stdlib_contents(TextDocumentIdentifier {
+            uri: math_sum_file.clone(),
+        })
+        .await
+        .unwrap();
+    assert!(contents.contains("pub fun math_sum("));
+
+    assert!(backend
+        .stdlib_contents(TextDocumentIdentifier { uri: uri.clone() })
+        .await
+        .is_err());
+
+    // Nothing gets extracted to the disk
+    assert!(!backend.files.fs.exists(&temp_dir().join("amber-lsp")).await);
+
+    let modules = find_in_stdlib(backend, &uri, "std/").await;
+    assert!(modules.contains(&"math".to_string()));
+    assert!(modules.contains(&"text".to_string()));
+}
+
+#[test]
+async fn test_stdlib_index_matches_analysis() {
+    let service = backend_service(AmberVersion::Alpha040);
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\project\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/project/main.ab")
+        }
+    };
+
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend
+        .did_open(open_params(
+            &uri,
+            "import { replace, split } from \"std/text\"\n",
+        ))
+        .await;
+
+    let text_uri = definition_file(backend, &uri, "replace").unwrap();
+    let text_file_id = backend.files.get(&text_uri).unwrap();
+
+    let signatures = |version| {
+        ["replace", "split"]
+            .iter()
+            .map(|name| {
+                get_symbol_definition_info(
+                    &backend.files,
+                    name,
+                    &(text_file_id, version),
+                    usize::MAX,
+                )
+                .unwrap()
+                .to_string(&backend.files.generic_types)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let indexed = signatures(backend.files.get_latest_version(text_file_id));
+
+    // Opening the module in the editor analyzes it in full
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                version: 2,
+                ..open_params(
+                    &text_uri,
+                    &backend
+                        .stdlib_contents(TextDocumentIdentifier {
+                            uri: text_uri.clone(),
+                        })
+                        .await
+                        .unwrap(),
+                )
+                .text_document
+            },
+        })
+        .await;
+
+    let analyzed = signatures(backend.files.get_latest_version(text_file_id));
+
+    assert_eq!(indexed, analyzed);
+}
+
+#[test]
+async fn test_stdlib_index_is_up_to_date() {
+    let index = stdlib_index::build().await;
+    let checked_in =
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(STDLIB_INDEX_PATH))
+            .unwrap();
+
+    // Regenerate the index with `cargo xtask stdlib-index`
+    assert_eq!(
+        serde_json::to_value(&index).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&checked_in).unwrap()
+    );
+}
+
+#[test]
+async fn test_semantic_tokens_in_stdlib_documents() {
+    let service = backend_service(AmberVersion::Alpha040);
+    let backend = service.inner();
+
+    let result = backend
+        .initialize(InitializeParams::default())
+        .await
+        .unwrap();
+
+    let document_selector = match result.capabilities.semantic_tokens_provider {
+        Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options)) => {
+            options
+                .text_document_registration_options
+                .document_selector
+                .unwrap()
+        }
+        capability => panic!("Unexpected semantic tokens capability {:?}", capability),
+    };
+
+    assert!(document_selector
+        .iter()
+        .any(|filter| filter.scheme.as_deref() == Some(STDLIB_SCHEME)));
+}
@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    detection::parse_version,
+    settings::{Settings, VersionSetting},
+};
+use tokio::test;
+use tower_lsp::{lsp_types::Url, LanguageServer};
+
+use super::{backend_service, main_file, open_params};
+
+/// Settings that keep the locally installed compiler out of the detection.
+fn settings_without_compiler() -> Settings {
+    Settings {
+        amber_path: Some(Path::new("/nonexistent/amber").to_path_buf()),
+        ..Settings::default()
+    }
+}
+
+async fn detect(backend: &Backend, file: &Path, text: &str) -> AmberVersion {
+    let uri = Url::from_file_path(file).unwrap();
+
+    backend.did_open(open_params(&uri, text)).await;
+
+    backend.amber_version_for(&uri)
+}
+
+#[test]
+async fn test_parse_version() {
+    assert_eq!(parse_version("0.3.4-alpha"), Some(AmberVersion::Alpha034));
+    assert_eq!(
+        parse_version("amber-0.3.5-alpha"),
+        Some(AmberVersion::Alpha035)
+    );
+    assert_eq!(parse_version("0.4.0-alpha"), Some(AmberVersion::Alpha040));
+    assert_eq!(parse_version("amber"), None);
+}
+
+#[test]
+async fn test_detect_from_syntax() {
+    let service = backend_service(VersionSetting::Auto);
+
+    let backend = service.inner();
+
+    backend.update_settings(settings_without_compiler()).await;
+
+    let dir = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/")
+        }
+    };
+
+    assert_eq!(
+        detect(backend, &dir.join("std.ab"), r#"import * from "std""#).await,
+        AmberVersion::Alpha034
+    );
+    assert_eq!(
+        detect(
+            backend,
+            &dir.join("std_text.ab"),
+            r#"import { split } from "std/text""#
+        )
+        .await,
+        AmberVersion::Alpha040
+    );
+    assert_eq!(
+        detect(backend, &dir.join("exit.ab"), "exit 1").await,
+        AmberVersion::Alpha040
+    );
+    assert_eq!(
+        detect(
+            backend,
+            &dir.join("command.ab"),
+            "import * from \"std\"\necho \"exit\"\n$exit 1$"
+        )
+        .await,
+        AmberVersion::Alpha034
+    );
+    assert_eq!(
+        detect(backend, &dir.join("plain.ab"), "let x = 1").await,
+        AmberVersion::Alpha040
+    );
+}
+
+#[test]
+async fn test_detect_from_shebang() {
+    let service = backend_service(VersionSetting::Auto);
+
+    let backend = service.inner();
+
+    backend.update_settings(settings_without_compiler()).await;
+
+    let file = main_file();
+
+    assert_eq!(
+        detect(backend, file, "#!/usr/bin/env amber-0.3.5-alpha\nexit 1\n").await,
+        AmberVersion::Alpha035
+    );
+}
+
+#[test]
+async fn test_detect_from_config_file() {
+    let service = backend_service(VersionSetting::Auto);
+
+    let backend = service.inner();
+
+    backend.update_settings(settings_without_compiler()).await;
+
+    let vfs = &backend.files.fs;
+
+    let (config_file, file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\project\\.amber-version"),
+                Path::new("C:\\project\\src\\main.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/project/.amber-version"),
+                Path::new("/project/src/main.ab"),
+            )
+        }
+    };
+
+    vfs.write(config_file, "0.3.4-alpha\n").await.unwrap();
+
+    assert_eq!(
+        detect(backend, file, "#!/usr/bin/env amber-0.3.5-alpha\nexit 1\n").await,
+        AmberVersion::Alpha034
+    );
+}
+
+#[test]
+async fn test_configured_version_skips_detection() {
+    let service = backend_service(VersionSetting::Auto);
+
+    let backend = service.inner();
+
+    backend
+        .update_settings(Settings {
+            version: VersionSetting::Alpha035,
+            ..settings_without_compiler()
+        })
+        .await;
+
+    let file = main_file();
+
+    assert_eq!(
+        detect(backend, file, "exit 1").await,
+        AmberVersion::Alpha035
+    );
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    files::FileVersion,
+    fs::MemoryFS,
+    paths::FileId,
+};
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{DidChangeWatchedFilesParams, FileChangeType, FileEvent, Url},
+    LanguageServer, LspService,
+};
+
+use super::{backend_service, open_params, paths};
+
+fn watched_params(uri: &Url, typ: FileChangeType) -> DidChangeWatchedFilesParams {
+    DidChangeWatchedFilesParams {
+        changes: vec![FileEvent {
+            uri: uri.clone(),
+            typ,
+        }],
+    }
+}
+
+fn get_messages(backend: &Backend, file: (FileId, FileVersion)) -> Vec<String> {
+    backend
+        .files
+        .diagnostics
+        .get(&file)
+        .map(|diagnostics| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+const MAIN_TEXT: &str = r#"
+    import { foo } from "src.ab"
+
+    foo()
+    "#;
+
+#[test]
+async fn test_created_file_resolves_import() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (src_file, main_file) = paths();
+    vfs.write(main_file, MAIN_TEXT).await.unwrap();
+
+    let src_uri = Url::from_file_path(src_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, MAIN_TEXT)).await;
+
+    let main_file_id = backend.files.get(&main_uri).unwrap();
+    let main_version = backend.files.get_latest_version(main_file_id);
+
+    assert!(get_messages(backend, (main_file_id, main_version))
+        .contains(&"File doesn't exist".to_string()));
+
+    vfs.write(src_file, r#"pub fun foo() {}"#).await.unwrap();
+    backend
+        .did_change_watched_files(watched_params(&src_uri, FileChangeType::CREATED))
+        .await;
+
+    assert!(get_messages(backend, (main_file_id, main_version)).is_empty());
+    assert!(backend.files.get(&src_uri).is_some());
+}
+
+#[test]
+async fn test_deleted_file_breaks_import() {
+    let fs = Arc::new(MemoryFS::new());
+    let (service, _) =
+        LspService::new(|client| Backend::new(client, AmberVersion::Alpha034, Some(fs.clone())));
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (src_file, main_file) = paths();
+    vfs.write(src_file, r#"pub fun foo() {}"#).await.unwrap();
+    vfs.write(main_file, MAIN_TEXT).await.unwrap();
+
+    let src_uri = Url::from_file_path(src_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, MAIN_TEXT)).await;
+
+    let main_file_id = backend.files.get(&main_uri).unwrap();
+    let main_version = backend.files.get_latest_version(main_file_id);
+
+    assert!(get_messages(backend, (main_file_id, main_version)).is_empty());
+
+    fs.remove(src_file).unwrap();
+    backend
+        .did_change_watched_files(watched_params(&src_uri, FileChangeType::DELETED))
+        .await;
+
+    assert!(get_messages(backend, (main_file_id, main_version))
+        .contains(&"File doesn't exist".to_string()));
+    assert!(backend.files.get(&src_uri).is_none());
+}
+
+#[test]
+async fn test_changed_file_is_reloaded() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let vfs = &backend.files.fs;
+
+    let (src_file, main_file) = paths();
+    vfs.write(src_file, r#"pub fun foo() {}"#).await.unwrap();
+    vfs.write(main_file, MAIN_TEXT).await.unwrap();
+
+    let src_uri = Url::from_file_path(src_file).unwrap();
+    let main_uri = Url::from_file_path(main_file).unwrap();
+
+    backend.did_open(open_params(&main_uri, MAIN_TEXT)).await;
+
+    vfs.write(src_file, r#"pub fun bar() {}"#).await.unwrap();
+    backend
+        .did_change_watched_files(watched_params(&src_uri, FileChangeType::CHANGED))
+        .await;
+
+    let src_file_id = backend.files.get(&src_uri).unwrap();
+    let src_version = backend.files.get_latest_version(src_file_id);
+    let src_symbol_table = backend
+        .files
+        .symbol_table
+        .get(&(src_file_id, src_version))
+        .unwrap();
+
+    assert!(!src_symbol_table.definitions.contains_key("foo"));
+    assert!(src_symbol_table.definitions.contains_key("bar"));
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    grammar::Grammar,
+    settings::{Settings, VersionSetting},
+    stdlib::stdlib_version,
+};
+use tokio::test;
+use tower_lsp::{
+    lsp_types::{
+        DidChangeWorkspaceFoldersParams, InitializeParams, Url, WorkspaceFolder,
+        WorkspaceFoldersChangeEvent,
+    },
+    LanguageServer,
+};
+
+use super::{backend_service, open_params};
+
+fn folder(uri: &Url, name: &str) -> WorkspaceFolder {
+    WorkspaceFolder {
+        uri: uri.clone(),
+        name: name.to_string(),
+    }
+}
+
+/// Amber version of the stdlib file `replace` got imported from.
+fn imported_stdlib_version(backend: &Backend, uri: &Url) -> Option<AmberVersion> {
+    let file_id = backend.files.get(uri).unwrap();
+    let version = backend.files.get_latest_version(file_id);
+    let symbol_table = backend.files.symbol_table.get(&(file_id, version)).unwrap();
+
+    let definition = symbol_table
+        .definitions
+        .get("replace")?
+        .get(&usize::MAX)?
+        .clone();
+
+    stdlib_version(&backend.files.lookup(&definition.file.0))
+}
+
+fn grammar_version(backend: &Backend, uri: &Url) -> AmberVersion {
+    let file_id = backend.files.get(uri).unwrap();
+    let version = backend.files.get_latest_version(file_id);
+
+    match *backend.files.ast_map.get(&(file_id, version)).unwrap() {
+        Grammar::Alpha034(_) => AmberVersion::Alpha034,
+        Grammar::Alpha035(_) => AmberVersion::Alpha035,
+        Grammar::Alpha040(_) => AmberVersion::Alpha040,
+    }
+}
+
+#[test]
+async fn test_version_per_workspace_folder() {
+    let service = backend_service(AmberVersion::Alpha034);
+
+    let backend = service.inner();
+
+    let (old_dir, new_dir) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\old"), Path::new("C:\\new"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/old"), Path::new("/new"))
+        }
+    };
+
+    let old_folder = Url::from_file_path(old_dir).unwrap();
+    let new_folder = Url::from_file_path(new_dir).unwrap();
+    let old_uri = Url::from_file_path(old_dir.join("main.ab")).unwrap();
+    let new_uri = Url::from_file_path(new_dir.join("main.ab")).unwrap();
+
+    backend
+        .initialize(InitializeParams {
+            workspace_folders: Some(vec![folder(&old_folder, "old"), folder(&new_folder, "new")]),
+            ..InitializeParams::default()
+        })
+        .await
+        .unwrap();
+
+    backend
+        .update_scope_settings(
+            old_folder.clone(),
+            Settings {
+                version: VersionSetting::Alpha035,
+                ..Settings::default()
+            },
+        )
+        .await;
+    backend
+        .update_scope_settings(
+            new_folder.clone(),
+            Settings {
+                version: VersionSetting::Alpha040,
+                ..Settings::default()
+            },
+        )
+        .await;
+
+    let text = r#"
+    import { replace } from "std/text"
+
+    replace("a", "b", "c")
+    "#;
+
+    backend.did_open(open_params(&old_uri, text)).await;
+    backend.did_open(open_params(&new_uri, text)).await;
+
+    assert_eq!(grammar_version(backend, &old_uri), AmberVersion::Alpha035);
+    assert_eq!(grammar_version(backend, &new_uri), AmberVersion::Alpha040);
+    assert_eq!(
+        imported_stdlib_version(backend, &old_uri),
+        Some(AmberVersion::Alpha035)
+    );
+    assert_eq!(
+        imported_stdlib_version(backend, &new_uri),
+        Some(AmberVersion::Alpha040)
+    );
+
+    backend
+        .did_change_workspace_folders(DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: vec![],
+                removed: vec![folder(&old_folder, "old")],
+            },
+        })
+        .await;
+
+    assert_eq!(backend.amber_version_for(&old_uri), AmberVersion::Alpha034);
+    assert_eq!(grammar_version(backend, &old_uri), AmberVersion::Alpha034);
+    assert_eq!(grammar_version(backend, &new_uri), AmberVersion::Alpha040);
+}
@@ -0,0 +1,275 @@
+use amber_lsp::grammar::{
+    alpha040::{shell_script, AmberCompiler, Expression, GlobalStatement, Statement},
+    shell::{ListOperator, ShellCommand, ShellCompletion, ShellScript, WordPart},
+    LSPAnalysis, ParserResponse, Span,
+};
+use tower_lsp::lsp_types::SemanticTokenType;
+
+/// Parse the shell script of a command literal given as the only statement of the code.
+fn parse_script(code: &str) -> ShellScript {
+    let tokens = AmberCompiler::new().tokenize(code);
+    let ParserResponse { ast, errors, .. } = AmberCompiler::new().parse(&tokens);
+
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let ast = match ast {
+        amber_lsp::grammar::Grammar::Alpha040(Some(ast)) => ast,
+        _ => panic!("Unexpected AST"),
+    };
+
+    match &ast[0].0 {
+        GlobalStatement::Statement(statement) => match &statement.0 {
+            Statement::Expression(expression) => match &expression.0 {
+                Expression::Command(_, cmd, _) => shell_script(cmd),
+                expression => panic!("Expected a command, got {:?}", expression),
+            },
+            statement => panic!("Expected an expression, got {:?}", statement),
+        },
+        statement => panic!("Expected a statement, got {:?}", statement),
+    }
+}
+
+fn text(code: &str, span: Span) -> String {
+    code.chars()
+        .skip(span.start)
+        .take(span.end - span.start)
+        .collect()
+}
+
+/// Names of the programs run by the script, [`None`] for the interpolated ones.
+fn executables(code: &str) -> Vec<Option<String>> {
+    parse_script(code)
+        .simple_commands()
+        .into_iter()
+        .map(|(command, _)| command.executable().and_then(|word| word.literal()))
+        .collect()
+}
+
+#[test]
+fn test_executables() {
+    assert_eq!(executables("$ls -la$"), vec![Some("ls".to_string())]);
+    assert_eq!(
+        executables("$sudo -E LANG=C rm -rf {dir}$"),
+        vec![Some("rm".to_string())]
+    );
+    assert_eq!(
+        executables("$LANG=C nohup \"my tool\" --verbose$"),
+        vec![Some("my tool".to_string())]
+    );
+    assert_eq!(executables("${cmd} --help$"), vec![None]);
+    assert_eq!(
+        executables("$cd {dir} && make | tee build.log || echo failed$"),
+        vec![
+            Some("cd".to_string()),
+            Some("make".to_string()),
+            Some("tee".to_string()),
+            Some("echo".to_string()),
+        ]
+    );
+    assert_eq!(
+        executables("$echo \"Today is \\$(date +%A)\"$"),
+        vec![Some("echo".to_string()), Some("date".to_string())]
+    );
+    assert_eq!(
+        executables("$(cd build; ls) 2>&1 > files.txt$"),
+        vec![Some("cd".to_string()), Some("ls".to_string())]
+    );
+    assert_eq!(executables("$$"), vec![]);
+}
+
+#[test]
+fn test_script_structure() {
+    let code = "$(cd build; ls -1) 2>/dev/null | grep -v '.o' && echo \\$HOME$";
+    let script = parse_script(code);
+
+    let (list, list_span) = &script.list;
+    assert_eq!(text(code, *list_span), &code[1..code.len() - 1]);
+    assert_eq!(
+        list.operators
+            .iter()
+            .map(|(operator, _)| *operator)
+            .collect::<Vec<_>>(),
+        vec![ListOperator::And]
+    );
+    assert_eq!(list.pipelines.len(), 2);
+
+    let (pipeline, _) = &list.pipelines[0];
+    assert_eq!(pipeline.commands.len(), 2);
+    assert_eq!(pipeline.pipes.len(), 1);
+
+    let (subshell, redirections) = match &pipeline.commands[0].0 {
+        ShellCommand::Subshell(_, list, Some(_), redirections) => (list, redirections),
+        command => panic!("Expected a subshell, got {:?}", command),
+    };
+    assert_eq!(text(code, subshell.1), "cd build; ls -1");
+    assert_eq!(
+        subshell.0.operators,
+        vec![(ListOperator::Sequence, Span::new(10, 11))]
+    );
+    assert_eq!(
+        redirections
+            .iter()
+            .map(|redirection| text(code, redirection.operator.1))
+            .collect::<Vec<_>>(),
+        vec!["2>"]
+    );
+
+    let grep = match &pipeline.commands[1].0 {
+        ShellCommand::Simple(command) => command,
+        command => panic!("Expected a simple command, got {:?}", command),
+    };
+    assert_eq!(
+        grep.words
+            .iter()
+            .map(|word| word.literal().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["grep", "-v", ".o"]
+    );
+
+    let echo = match &list.pipelines[1].0.commands[0].0 {
+        ShellCommand::Simple(command) => command,
+        command => panic!("Expected a simple command, got {:?}", command),
+    };
+    let (part, span) = &echo.words[1].parts[0];
+    assert_eq!(part, &WordPart::Variable("HOME".to_string()));
+    assert_eq!(text(code, *span), "\\$HOME");
+
+    let script = parse_script("$ls 2>/dev/null$");
+    let redirection = match &script.list.0.pipelines[0].0.commands[0].0 {
+        ShellCommand::Simple(command) => &command.redirections[0],
+        command => panic!("Expected a simple command, got {:?}", command),
+    };
+    assert_eq!(redirection.operator, ("2>".to_string(), Span::new(4, 6)));
+    assert_eq!(
+        redirection
+            .target
+            .as_ref()
+            .and_then(|target| target.literal()),
+        Some("/dev/null".to_string())
+    );
+}
+
+#[test]
+fn test_semantic_tokens() {
+    let code = "$LANG=C grep -r \"{pattern}\" . | wc -l > count.txt$";
+    let mut tokens = parse_script(code).semantic_tokens();
+    tokens.sort_by_key(|(_, span)| span.start);
+
+    assert_eq!(
+        tokens
+            .into_iter()
+            .map(|(token_type, span)| (token_type.as_str().to_string(), text(code, span)))
+            .collect::<Vec<_>>(),
+        [
+            (SemanticTokenType::VARIABLE, "LANG"),
+            (SemanticTokenType::OPERATOR, "="),
+            (SemanticTokenType::STRING, "C"),
+            (SemanticTokenType::FUNCTION, "grep"),
+            (SemanticTokenType::new("constant"), "-r"),
+            (SemanticTokenType::STRING, "\""),
+            (SemanticTokenType::STRING, "\""),
+            (SemanticTokenType::STRING, "."),
+            (SemanticTokenType::OPERATOR, "|"),
+            (SemanticTokenType::FUNCTION, "wc"),
+            (SemanticTokenType::new("constant"), "-l"),
+            (SemanticTokenType::OPERATOR, ">"),
+            (SemanticTokenType::STRING, "count.txt"),
+        ]
+        .map(|(token_type, text)| (token_type.as_str().to_string(), text.to_string()))
+    );
+}
+
+#[test]
+fn test_selection_spans() {
+    let code = "$cd {dir} && grep -v \"foo bar\" file.txt$";
+    let script = parse_script(code);
+
+    let position = code.find("bar").unwrap();
+
+    assert_eq!(
+        script
+            .selection_spans(position)
+            .into_iter()
+            .map(|span| text(code, span))
+            .collect::<Vec<_>>(),
+        vec![
+            "bar",
+            "foo bar",
+            "\"foo bar\"",
+            "grep -v \"foo bar\" file.txt",
+            "cd {dir} && grep -v \"foo bar\" file.txt",
+            code,
+        ]
+    );
+
+    assert_eq!(script.selection_spans(code.len() + 1), Vec::<Span>::new());
+}
+
+#[test]
+fn test_completion_at() {
+    let code = "$sudo rm -r \"my fi\" | gr && echo \\$HO {name}$";
+    let script = parse_script(code);
+
+    let completion = |typed: &str| {
+        let position = code.find(typed).unwrap() + typed.len();
+
+        script
+            .completion_at(position)
+            .map(|(completion, (prefix, span))| (completion, prefix, text(code, span)))
+    };
+
+    assert_eq!(
+        completion("$su"),
+        Some((
+            ShellCompletion::Executable,
+            "su".to_string(),
+            "su".to_string()
+        ))
+    );
+    assert_eq!(
+        completion("sudo r"),
+        Some((
+            ShellCompletion::Executable,
+            "r".to_string(),
+            "r".to_string()
+        ))
+    );
+    assert_eq!(
+        completion("rm -"),
+        Some((
+            ShellCompletion::Argument(Some("rm".to_string())),
+            "-".to_string(),
+            "-".to_string()
+        ))
+    );
+    assert_eq!(
+        completion("\"my f"),
+        Some((
+            ShellCompletion::Argument(Some("rm".to_string())),
+            "my f".to_string(),
+            "my f".to_string()
+        ))
+    );
+    assert_eq!(
+        completion("| "),
+        Some((ShellCompletion::Executable, String::new(), String::new()))
+    );
+    assert_eq!(
+        completion("gr"),
+        Some((
+            ShellCompletion::Executable,
+            "gr".to_string(),
+            "gr".to_string()
+        ))
+    );
+    assert_eq!(
+        completion("\\$HO"),
+        Some((
+            ShellCompletion::Variable,
+            "HO".to_string(),
+            "HO".to_string()
+        ))
+    );
+    assert_eq!(completion("{na"), None);
+    assert_eq!(script.completion_at(0), None);
+}
@@ -0,0 +1,35 @@
+//! Development tasks of the language server, run with `cargo xtask <task>`.
+
+use std::{env, path::Path, process::ExitCode};
+
+use amber_lsp::stdlib_index::{self, STDLIB_INDEX_PATH};
+
+const USAGE: &str = "Usage: cargo xtask <task>
+
+Tasks:
+  stdlib-index  Analyze the embedded stdlib and write its index";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match env::args().nth(1).as_deref() {
+        Some("stdlib-index") => {
+            let index = stdlib_index::build().await;
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .parent()
+                .unwrap()
+                .join(STDLIB_INDEX_PATH);
+
+            let json = serde_json::to_string_pretty(&index).unwrap() + "\n";
+            std::fs::write(&path, json).unwrap();
+
+            println!("Wrote {}", path.display());
+
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+
+            ExitCode::FAILURE
+        }
+    }
+}